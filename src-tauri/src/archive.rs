@@ -0,0 +1,625 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::crypto::{self, Key, KEY_LEN};
+use crate::media::{MediaFile, MediaKind};
+use crate::models::EmotionalRecord;
+use crate::schema::{self, SCHEMA_VERSION};
+use crate::settings::Settings;
+use crate::vault::{self, KdfParams};
+
+pub const ARCHIVE_FORMAT: &str = "pick-up-memories-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+// 推送给前端的进度事件名
+pub const PROGRESS_EVENT: &str = "archive-progress";
+
+// 归档中的固定文件，媒体文件保存为 media/<媒体引用>
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const RECORDS_FILE: &str = "records.json";
+pub const SETTINGS_FILE: &str = "settings.json";
+// 归档中尘封载荷使用的密钥，只在包含尘封记录时写入
+pub const SEAL_KEY_FILE: &str = "seal.key";
+pub const MEDIA_PREFIX: &str = "media/";
+// 尘封记录引用的媒体另以尘封密钥加密，保存为 sealed-media/<媒体引用>
+pub const SEALED_MEDIA_PREFIX: &str = "sealed-media/";
+// 清单的大小上限
+const MAX_MANIFEST_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    // 尘封记录在归档中保持尘封，内容仍须等到解封时间才能查看；须同时设置口令
+    pub include_sealed: bool,
+    // 设置后除 manifest.json 外的每个文件都以口令派生的密钥加密
+    pub passphrase: Option<String>,
+}
+
+// 归档清单，始终为明文，导入时据此校验各文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub format: String,
+    pub format_version: u32,
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub encryption: Option<KdfParams>,
+    pub files: Vec<ManifestFile>,
+}
+
+// size 与 sha256 针对归档中实际保存的字节（加密时为密文）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveOperation {
+    Export,
+    Import,
+    // 从其他日记应用的导出中导入
+    ImportJournal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveProgress {
+    pub operation: ArchiveOperation,
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub path: String,
+    pub records: usize,
+    pub sealed: usize,
+    pub media: usize,
+    // 记录引用、但媒体库中已不存在的文件
+    pub missing_media: Vec<String>,
+    pub bytes: u64,
+}
+
+// 导入时 id 相同的记录的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStrategy {
+    // 只导入新记录以及归档中更新过的记录，本地更新过的记录保持不变
+    #[default]
+    Merge,
+    // 以归档为准：导入全部记录，本地有而归档中没有的记录移入回收站
+    Replace,
+    // 内容不同的记录两份都保留，归档中的一份以新 id 导入
+    KeepBoth,
+}
+
+// 导入前的对比结果。changed 为归档中的版本更新，conflicting 为本地的版本更新或无法判断
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub exported_at: String,
+    pub schema_version: u32,
+    pub encrypted: bool,
+    pub records: usize,
+    pub media: usize,
+    pub new_ids: Vec<String>,
+    pub changed_ids: Vec<String>,
+    pub conflicting_ids: Vec<String>,
+    pub unchanged_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    #[serde(flatten)]
+    pub preview: ImportPreview,
+    pub strategy: ImportStrategy,
+    // 新增或覆盖的记录数
+    pub imported: usize,
+    // 因 replace 移入回收站的本地记录数
+    pub trashed: usize,
+    // 因 merge 保留本地版本而未导入的记录数
+    pub skipped: usize,
+}
+
+// 在仓库锁内收集好的导出内容，读取媒体与写入归档在释放锁之后进行
+pub struct ArchiveExport {
+    pub records: Vec<EmotionalRecord>,
+    pub settings: Settings,
+    // 归档专用的尘封密钥，尘封载荷与尘封媒体都以它加密
+    pub seal_key: Zeroizing<Key>,
+    pub media: Vec<ExportMedia>,
+    pub missing_media: Vec<String>,
+    pub exported_at: String,
+    pub passphrase: Option<String>,
+}
+
+pub struct ExportMedia {
+    pub media_ref: String,
+    pub file: MediaFile,
+    // 被尘封记录引用，不能以明文写入归档
+    pub sealed: bool,
+}
+
+impl ArchiveExport {
+    pub fn write(
+        self,
+        path: &Path,
+        progress: &mut dyn FnMut(ArchiveProgress),
+    ) -> Result<ExportSummary, String> {
+        let sealed = self.records.iter().filter(|r| r.is_sealed).count();
+        let media = self.media.len();
+        let total = media + 2 + usize::from(sealed > 0);
+        let mut done = 0;
+        let mut step = || {
+            done += 1;
+            progress(ArchiveProgress {
+                operation: ArchiveOperation::Export,
+                done,
+                total,
+            });
+        };
+
+        let mut writer = ArchiveWriter::create(path, self.passphrase.as_deref(), self.exported_at)?;
+        let content = serde_json::to_vec_pretty(&schema::Envelope::new(&self.records))
+            .map_err(|e| e.to_string())?;
+        writer.add(RECORDS_FILE, &content, true)?;
+        step();
+        let content = serde_json::to_vec_pretty(&self.settings).map_err(|e| e.to_string())?;
+        writer.add(SETTINGS_FILE, &content, true)?;
+        step();
+        if sealed > 0 {
+            writer.add(SEAL_KEY_FILE, self.seal_key.as_ref(), false)?;
+            step();
+        }
+        for item in self.media {
+            let data = Zeroizing::new(item.file.open()?.read_all()?);
+            if item.sealed {
+                let data = crypto::encrypt(&self.seal_key, &data)?;
+                writer.add(&sealed_media_entry(&item.media_ref), &data, false)?;
+            } else {
+                writer.add(&media_entry(&item.media_ref), &data, false)?;
+            }
+            step();
+        }
+        let bytes = writer.finish()?;
+
+        Ok(ExportSummary {
+            path: path.to_string_lossy().into_owned(),
+            records: self.records.len(),
+            sealed,
+            media,
+            missing_media: self.missing_media,
+            bytes,
+        })
+    }
+}
+
+// 逐个写入归档文件。先写同目录下的临时文件，finish 时补上清单并改名，
+// 中途失败或放弃时删除临时文件，不会留下不完整的归档
+pub struct ArchiveWriter {
+    zip: Option<ZipWriter<File>>,
+    path: PathBuf,
+    tmp: PathBuf,
+    key: Option<Zeroizing<Key>>,
+    manifest: Manifest,
+}
+
+impl ArchiveWriter {
+    pub fn create(
+        path: &Path,
+        passphrase: Option<&str>,
+        exported_at: String,
+    ) -> Result<Self, String> {
+        let (encryption, key) = match passphrase {
+            Some(passphrase) => {
+                vault::validate_passphrase(passphrase)?;
+                let kdf = KdfParams::generate();
+                let key = kdf.derive(passphrase)?;
+                (Some(kdf), Some(key))
+            }
+            None => (None, None),
+        };
+
+        let tmp = temp_path(path);
+        let file = File::create(&tmp).map_err(|e| format!("创建归档文件失败: {}", e))?;
+        Ok(Self {
+            zip: Some(ZipWriter::new(file)),
+            path: path.to_path_buf(),
+            tmp,
+            key,
+            manifest: Manifest {
+                format: ARCHIVE_FORMAT.to_string(),
+                format_version: ARCHIVE_VERSION,
+                schema_version: SCHEMA_VERSION,
+                exported_at,
+                encryption,
+                files: Vec::new(),
+            },
+        })
+    }
+
+    // 文本文件压缩保存；图片与音乐本身已压缩，直接存储
+    pub fn add(&mut self, name: &str, data: &[u8], compress: bool) -> Result<(), String> {
+        let encrypted;
+        let data = match &self.key {
+            Some(key) => {
+                encrypted = crypto::encrypt(key, data)?;
+                &encrypted
+            }
+            None => data,
+        };
+        let method = if compress {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = SimpleFileOptions::default()
+            .compression_method(method)
+            .large_file(data.len() as u64 >= u32::MAX as u64);
+
+        let zip = self
+            .zip
+            .as_mut()
+            .ok_or_else(|| "归档已写入完成".to_string())?;
+        zip.start_file(name, options).map_err(write_error)?;
+        zip.write_all(data)
+            .map_err(|e| format!("写入归档失败: {}", e))?;
+        self.manifest.files.push(ManifestFile {
+            path: name.to_string(),
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+        });
+        Ok(())
+    }
+
+    // 写入清单、落盘并改名为目标文件，返回归档大小
+    pub fn finish(mut self) -> Result<u64, String> {
+        let manifest = serde_json::to_vec_pretty(&self.manifest).map_err(|e| e.to_string())?;
+        let mut zip = self
+            .zip
+            .take()
+            .ok_or_else(|| "归档已写入完成".to_string())?;
+        zip.start_file(
+            MANIFEST_FILE,
+            SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+        )
+        .map_err(write_error)?;
+        zip.write_all(&manifest)
+            .map_err(|e| format!("写入归档失败: {}", e))?;
+
+        let file = zip.finish().map_err(write_error)?;
+        file.sync_all()
+            .map_err(|e| format!("写入归档失败: {}", e))?;
+        let bytes = file
+            .metadata()
+            .map(|m| m.len())
+            .map_err(|e| format!("写入归档失败: {}", e))?;
+        drop(file);
+        fs::rename(&self.tmp, &self.path).map_err(|e| format!("保存归档失败: {}", e))?;
+        Ok(bytes)
+    }
+}
+
+impl Drop for ArchiveWriter {
+    fn drop(&mut self) {
+        if self.tmp.exists() {
+            self.zip = None;
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+// 读取并校验归档：每个文件都须列在清单中且大小与哈希一致，加密的归档须提供口令
+pub struct ArchiveReader {
+    zip: ZipArchive<File>,
+    manifest: Manifest,
+    key: Option<Zeroizing<Key>>,
+    // 读取尘封媒体时才从归档中取出
+    seal_key: Option<Zeroizing<Key>>,
+}
+
+impl ArchiveReader {
+    pub fn open(path: &Path, passphrase: Option<&str>) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("打开归档失败: {}", e))?;
+        let mut zip = ZipArchive::new(file).map_err(|e| format!("不是有效的归档文件: {}", e))?;
+        let manifest: Manifest = {
+            let mut entry = zip
+                .by_name(MANIFEST_FILE)
+                .map_err(|_| "归档缺少清单文件".to_string())?;
+            let mut content = Vec::new();
+            entry
+                .by_ref()
+                .take(MAX_MANIFEST_BYTES)
+                .read_to_end(&mut content)
+                .map_err(read_error)?;
+            serde_json::from_slice(&content).map_err(|e| format!("归档清单已损坏: {}", e))?
+        };
+        if manifest.format != ARCHIVE_FORMAT {
+            return Err("不是本应用导出的归档".to_string());
+        }
+        if DateTime::parse_from_rfc3339(&manifest.exported_at).is_err() {
+            return Err("归档清单中的导出时间无效".to_string());
+        }
+        if manifest.format_version > ARCHIVE_VERSION {
+            return Err(format!(
+                "归档格式版本 {} 高于当前支持的版本 {}，请升级应用后再导入",
+                manifest.format_version, ARCHIVE_VERSION
+            ));
+        }
+
+        let key = match (&manifest.encryption, passphrase) {
+            (Some(kdf), Some(passphrase)) => Some(kdf.derive(passphrase)?),
+            (Some(_), None) => return Err("归档已加密，请输入口令".to_string()),
+            (None, _) => None,
+        };
+        Ok(Self {
+            zip,
+            manifest,
+            key,
+            seal_key: None,
+        })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.manifest.files.iter().any(|f| f.path == name)
+    }
+
+    // 清单中列出的媒体引用，包括尘封媒体
+    pub fn media_refs(&self) -> Vec<String> {
+        self.manifest
+            .files
+            .iter()
+            .filter_map(|f| {
+                f.path
+                    .strip_prefix(MEDIA_PREFIX)
+                    .or_else(|| f.path.strip_prefix(SEALED_MEDIA_PREFIX))
+            })
+            .map(str::to_string)
+            .collect()
+    }
+
+    // 读出媒体文件，尘封媒体再以归档中的尘封密钥解密
+    pub fn read_media(&mut self, media_ref: &str) -> Result<Vec<u8>, String> {
+        let entry = sealed_media_entry(media_ref);
+        if !self.contains(&entry) {
+            return self.read(&media_entry(media_ref));
+        }
+        let key = match self.seal_key.take() {
+            Some(key) => key,
+            None if self.contains(SEAL_KEY_FILE) => self.read_seal_key()?,
+            None => return Err("归档缺少尘封密钥，无法导入尘封媒体".to_string()),
+        };
+        let data = self.read(&entry);
+        let data = data.and_then(|data| {
+            crypto::decrypt(&key, &data).map_err(|_| format!("无法解密 {}：归档已损坏", entry))
+        });
+        self.seal_key = Some(key);
+        data
+    }
+
+    // 读出文件，校验大小与哈希后解密
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let listed = self
+            .manifest
+            .files
+            .iter()
+            .find(|f| f.path == name)
+            .ok_or_else(|| format!("归档缺少文件: {}", name))?;
+        let mut entry = self
+            .zip
+            .by_name(name)
+            .map_err(|_| format!("归档缺少文件: {}", name))?;
+        let mut data = Vec::new();
+        // 按清单中的大小读取，防止解压出超出预期的数据
+        entry
+            .by_ref()
+            .take(listed.size + 1)
+            .read_to_end(&mut data)
+            .map_err(read_error)?;
+        if data.len() as u64 != listed.size || sha256_hex(&data) != listed.sha256 {
+            return Err(format!("归档文件已损坏: {}", name));
+        }
+        match &self.key {
+            Some(key) => crypto::decrypt(key, &data)
+                .map_err(|_| format!("无法解密 {}：口令错误或归档已损坏", name)),
+            None => Ok(data),
+        }
+    }
+
+    pub fn read_seal_key(&mut self) -> Result<Zeroizing<Key>, String> {
+        let data = Zeroizing::new(self.read(SEAL_KEY_FILE)?);
+        if data.len() != KEY_LEN {
+            return Err("归档中的尘封密钥已损坏".to_string());
+        }
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&data);
+        Ok(key)
+    }
+}
+
+// 逐条检查归档中的记录，任何一条不合格都拒绝整个归档。心情按本机的色板与强度级数检查
+pub fn validate_records(
+    records: &[EmotionalRecord],
+    media: &HashSet<String>,
+    has_seal_key: bool,
+    settings: &Settings,
+) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for record in records {
+        let id = &record.id;
+        if id.trim().is_empty() {
+            return Err("归档中有记录缺少 id".to_string());
+        }
+        if !is_record_id(id) {
+            return Err(format!("归档中的记录 id 无效: {}", id));
+        }
+        if !ids.insert(id) {
+            return Err(format!("归档中的记录 id 重复: {}", id));
+        }
+
+        let times = [
+            ("createdAt", Some(&record.created_at)),
+            ("updatedAt", Some(&record.updated_at)),
+            ("sealUntil", record.seal_until.as_ref()),
+            ("autoDestroyAt", record.auto_destroy_at.as_ref()),
+            ("capturedAt", record.captured_at.as_ref()),
+        ];
+        for (field, value) in times {
+            if let Some(value) = value.filter(|v| DateTime::parse_from_rfc3339(v).is_err()) {
+                return Err(format!(
+                    "记录 {} 的 {} 不是有效的时间: {}",
+                    id, field, value
+                ));
+            }
+        }
+
+        if record.is_sealed != record.sealed_payload.is_some() {
+            return Err(format!("记录 {} 的尘封状态与内容不一致", id));
+        }
+        if record.is_sealed && !has_seal_key {
+            return Err("归档缺少尘封密钥，无法导入尘封记录".to_string());
+        }
+        if let Some(mood) = &record.mood {
+            settings
+                .check_mood(mood)
+                .map_err(|e| format!("记录 {} 的心情无效: {}", id, e))?;
+        }
+        let missing = record
+            .images
+            .iter()
+            .chain(&record.music_url)
+            .find(|r| MediaKind::of_ref(r).is_some() && !media.contains(*r));
+        if let Some(missing) = missing {
+            return Err(format!("记录 {} 引用的媒体不在归档中: {}", id, missing));
+        }
+    }
+    Ok(())
+}
+
+// 与前端生成的 id 一致：record_<毫秒时间戳>_<至多 9 位小写字母或数字>
+fn is_record_id(id: &str) -> bool {
+    let Some((millis, suffix)) = id
+        .strip_prefix("record_")
+        .and_then(|rest| rest.split_once('_'))
+    else {
+        return false;
+    };
+    millis.bytes().all(|b| b.is_ascii_digit())
+        && millis.parse::<i64>().is_ok()
+        && (1..=9).contains(&suffix.len())
+        && suffix
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b.is_ascii_lowercase())
+}
+
+pub fn media_entry(media_ref: &str) -> String {
+    format!("{}{}", MEDIA_PREFIX, media_ref)
+}
+
+pub fn sealed_media_entry(media_ref: &str) -> String {
+    format!("{}{}", SEALED_MEDIA_PREFIX, media_ref)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// memories.zip -> memories.zip.tmp
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn read_error(e: std::io::Error) -> String {
+    format!("读取归档失败: {}", e)
+}
+
+fn write_error(e: zip::result::ZipError) -> String {
+    format!("写入归档失败: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Mood;
+
+    fn record(id: &str, mood: Option<(&str, u8)>) -> EmotionalRecord {
+        EmotionalRecord {
+            id: id.to_string(),
+            created_at: "2024-05-01T12:00:00Z".to_string(),
+            updated_at: "2024-05-01T12:00:00Z".to_string(),
+            mood: mood.map(|(id, intensity)| Mood {
+                id: id.to_string(),
+                intensity,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn validate(records: &[EmotionalRecord]) -> Result<(), String> {
+        validate_records(records, &HashSet::new(), false, &Settings::default())
+    }
+
+    #[test]
+    fn accepts_ids_generated_by_the_frontend() {
+        assert!(validate(&[
+            record("record_1714564800000_k3j9x2a1b", Some(("happy", 5))),
+            record("record_1714564800001_z7", None),
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_ids() {
+        for id in [
+            "1714564800000",
+            "record__abc",
+            "record_+1714564800000_abc",
+            "record_1714564800000_",
+            "record_1714564800000_ABC",
+            "record_1714564800000_abcdefghij",
+            "record_1714564800000_../x",
+        ] {
+            assert!(validate(&[record(id, None)]).is_err(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn checks_moods_against_the_palette_and_levels() {
+        let id = "record_1714564800000_abc";
+        assert!(validate(&[record(id, Some(("unknown", 1)))]).is_err());
+        assert!(validate(&[record(id, Some(("happy", 0)))]).is_err());
+        assert!(validate(&[record(id, Some(("happy", 6)))]).is_err());
+        assert!(validate(&[record(id, Some(("happy", 1)))]).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        let mut bad = record("record_1714564800000_abc", None);
+        bad.updated_at = "yesterday".to_string();
+        assert!(validate(&[bad]).is_err());
+    }
+}
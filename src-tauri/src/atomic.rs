@@ -0,0 +1,49 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// 原子写入：先写同目录下的临时文件并落盘，再改名覆盖目标文件。
+// 任何时刻崩溃，目标文件要么是完整的旧内容，要么是完整的新内容
+pub fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = temp_path(path);
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_dir(path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// 改名替换目标文件并落盘目录项，from 与 to 须在同一文件系统
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)?;
+    sync_dir(to)
+}
+
+// records.vault -> records.vault.tmp，保留原扩展名以免不同文件的临时文件互相覆盖
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+// 改名本身也要落盘，否则断电后目录项可能仍指向旧文件
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+// Windows 无法打开目录句柄落盘，MoveFileEx 的替换已由文件系统日志保证
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::backup::{self, BACKUP_EVENT};
+use crate::models::{EventKind, MemoryEvent};
+use crate::paths::AppPaths;
+use crate::resurface;
+use crate::scheduler::{is_transition, Scheduler};
+use crate::session::Session;
+use crate::settings::SettingsStore;
+
+// 推送给前端的事件名
+pub const MEMORY_EVENT: &str = "memory-event";
+// 最长空闲等待：系统睡眠或时钟调整后最迟在这段时间内补发错过的事件
+const MAX_IDLE: Duration = Duration::from_secs(60);
+
+// 唤醒调度线程的句柄，在记录变化或解锁后调用以便立即重新规划
+pub struct SchedulerWaker(Mutex<Sender<()>>);
+
+impl SchedulerWaker {
+    pub fn wake(&self) {
+        if let Ok(tx) = self.0.lock() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+pub fn start_scheduler(app: &AppHandle, paths: &AppPaths) -> SchedulerWaker {
+    let (tx, rx) = mpsc::channel();
+    let scheduler = Scheduler::open(paths.scheduler_file());
+    let handle = app.clone();
+    let paths = paths.clone();
+    thread::spawn(move || run(handle, scheduler, paths, rx));
+    SchedulerWaker(Mutex::new(tx))
+}
+
+fn run(app: AppHandle, mut scheduler: Scheduler, paths: AppPaths, rx: Receiver<()>) {
+    loop {
+        let wait = tick(&app, &mut scheduler, &paths).unwrap_or(MAX_IDLE);
+        if let Err(RecvTimeoutError::Disconnected) = rx.recv_timeout(wait) {
+            break;
+        }
+    }
+}
+
+// 处理所有到期事件并投递，返回距下一个事件的等待时间；锁定期间返回 None
+fn tick(app: &AppHandle, scheduler: &mut Scheduler, paths: &AppPaths) -> Option<Duration> {
+    let settings = app.state::<SettingsStore>().get().unwrap_or_default();
+    let session = app.state::<Session>();
+    let result = session.with_store(|store| {
+        scheduler.rebuild(store.records())?;
+        let now = store.now();
+        let due = scheduler.take_due(now)?;
+        if due.iter().any(|e| is_transition(e.kind)) {
+            store.apply_due()?;
+        }
+
+        // 解封与销毁事件来自仓库，包括浏览记录时顺带完成的状态变化
+        let mut events = store.take_events();
+        for event in due.into_iter().filter(|e| !is_transition(e.kind)) {
+            scheduler.mark_delivered(&event)?;
+            let title = store
+                .records()
+                .iter()
+                .find(|r| r.id == event.record_id && !r.is_sealed)
+                .map(|r| r.title.clone());
+            events.push(MemoryEvent {
+                kind: event.kind,
+                record_id: event.record_id,
+                title,
+            });
+        }
+
+        // 每天一次的“那年今日”，关闭提醒后不再推送
+        let local_now = now.with_timezone(&Local);
+        let hour = u32::from(settings.on_this_day_hour);
+        let mut next_daily = None;
+        if settings.on_this_day_reminder {
+            if let Some(today) = scheduler.daily_due(local_now, hour) {
+                if let Some(found) = resurface::on_this_day(store.records(), today).first() {
+                    events.push(MemoryEvent {
+                        kind: EventKind::OnThisDay,
+                        record_id: found.id.clone(),
+                        title: Some(found.title.clone()),
+                    });
+                }
+                scheduler.mark_daily(today)?;
+            }
+            next_daily = scheduler.next_daily(local_now, hour);
+        }
+
+        // 超过保留期限的历史版本与回收站记录随时间清理
+        store.prune_revisions(&settings)?;
+        store.purge_trash(&settings)?;
+
+        // 按间隔自动备份：持锁期间只取快照，打包与校验在释放锁之后进行
+        let dir = backup::directory(&settings, paths);
+        let shredded = store.shredded_keys();
+        let mut snapshot = None;
+        if settings.auto_backup && scheduler.backup_due(now, settings.backup_interval) {
+            snapshot = Some(backup::Snapshot::take(&dir, paths, now, &|path| {
+                store.snapshot_database(path)
+            }));
+        }
+
+        scheduler.rebuild(store.records())?;
+        let next_due = scheduler.next_due().into_iter().chain(next_daily).min();
+        Ok((events, snapshot, shredded, dir, next_due, now))
+    });
+
+    let (events, snapshot, shredded, dir, next_due, now) = result.ok()?;
+    for event in &events {
+        deliver(app, event);
+    }
+    // 只记录成功的备份并轮换旧备份；失败时隔一小段时间重试，连续失败只通知一次
+    let failure = match snapshot.map(|snapshot| snapshot.and_then(backup::Snapshot::write)) {
+        Some(Ok(info)) => {
+            let _ = scheduler.mark_backup(now);
+            let _ = app.emit(BACKUP_EVENT, &info);
+            backup::rotate(&dir, &settings)
+                .err()
+                .map(|e| ("💾 清理旧备份失败", e))
+        }
+        Some(Err(e)) => scheduler
+            .defer_backup(now)
+            .then_some(("💾 自动备份失败", e)),
+        None => None,
+    };
+    if let Some((title, body)) = failure {
+        let _ = app.notification().builder().title(title).body(body).show();
+    }
+    // 已销毁的密钥从各份备份中删除后不再记录，失败时下次再试
+    if let Some((shredded, master_key)) = shredded {
+        if backup::shred(&dir, &master_key, &shredded).is_ok() {
+            let _ = session.with_store(|store| store.forget_shredded(&shredded));
+        }
+    }
+    let next_backup = settings
+        .auto_backup
+        .then(|| scheduler.next_backup(settings.backup_interval))
+        .flatten();
+    let next_due = next_due.into_iter().chain(next_backup).min();
+    let wait = next_due
+        .map(|due| (due - now).to_std().unwrap_or(Duration::ZERO))
+        .unwrap_or(MAX_IDLE);
+    Some(wait.min(MAX_IDLE))
+}
+
+fn deliver(app: &AppHandle, event: &MemoryEvent) {
+    let _ = app.emit(MEMORY_EVENT, event);
+
+    let name = event
+        .title
+        .as_deref()
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("\"{}\"", t))
+        .unwrap_or_else(|| "一段记忆".to_string());
+    let (title, body) = match event.kind {
+        EventKind::UnsealReminder => ("⏰ 即将解封", format!("{} 将在1小时后解封", name)),
+        EventKind::Unseal => ("🔓 记忆解封", format!("{} 已经解封，可以重新查看了", name)),
+        EventKind::DestroyWarning => ("⚠️ 销毁警告", format!("{} 将在24小时后被永久销毁", name)),
+        EventKind::DestroyFinalWarning => {
+            ("🚨 最后警告", format!("{} 将在1小时后被永久销毁！", name))
+        }
+        EventKind::Destroy => ("🔥 记忆已销毁", format!("{} 已按计划永久销毁", name)),
+        EventKind::OnThisDay => ("📅 那年今日", format!("往年的今天，你写下了 {}", name)),
+    };
+    let _ = app.notification().builder().title(title).body(body).show();
+}
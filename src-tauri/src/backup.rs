@@ -0,0 +1,1057 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::archive::{self, ManifestFile};
+use crate::atomic;
+use crate::crypto::{Key, KEY_LEN};
+use crate::database::Database;
+use crate::keyring::{self, Shredded};
+use crate::media::mime_for_extension;
+use crate::paths::AppPaths;
+use crate::schema::SCHEMA_VERSION;
+use crate::settings::Settings;
+
+pub const BACKUP_FORMAT: &str = "pick-up-memories-backup";
+// 第 2 版起共享媒体库中的文件按密文哈希命名
+pub const BACKUP_VERSION: u32 = 2;
+// 自动备份完成后推送给前端的事件名
+pub const BACKUP_EVENT: &str = "backup-created";
+const MANIFEST_FILE: &str = "manifest.json";
+const MAX_MANIFEST_BYTES: u64 = 64 * 1024 * 1024;
+const DATABASE_FILE: &str = "records.db";
+const KEYRING_FILE: &str = "keyring.bin";
+const VAULT_FILE: &str = "records.vault";
+const SEAL_KEY_FILE: &str = "seal.key";
+// 恢复时在数据目录中暂存文件的目录，写入完成后才有标记文件
+const STAGING_DIR: &str = "restore.tmp";
+const STAGING_MARKER: &str = "staged.json";
+// 备份目录中各份备份共享的媒体库，同一文件只保存一份
+const MEDIA_DIR: &str = "media";
+const NAME_PREFIX: &str = "backup-";
+const NAME_SUFFIX: &str = ".zip";
+const STALE_TMP: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+// 备份清单。备份中的文件都是数据目录中已加密的原样文件，恢复时须使用备份时的口令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupManifest {
+    format: String,
+    format_version: u32,
+    schema_version: u32,
+    created_at: String,
+    records: usize,
+    files: Vec<ManifestFile>,
+    media: Vec<BackupMedia>,
+}
+
+// 共享媒体库中的文件。path 为媒体引用，sha256 为密文的哈希：同一媒体改用新密钥加密后
+// 两个版本分别保存，各自对应备份中的密钥环。第 1 版备份没有哈希，只核对大小
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupMedia {
+    path: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl BackupMedia {
+    // 在共享媒体库中的位置：images/<密文哈希>.jpg，第 1 版备份为媒体引用本身
+    fn pool_path(&self) -> Result<String, String> {
+        let invalid = || format!("备份中有无效的媒体引用: {}", self.path);
+        let (kind, name) = self.path.split_once('/').ok_or_else(invalid)?;
+        if !matches!(kind, "images" | "music") || !is_media_file(name) {
+            return Err(invalid());
+        }
+        match &self.sha256 {
+            Some(sha256) => {
+                let (_, extension) = name.split_once('.').ok_or_else(invalid)?;
+                let pooled = format!("{}.{}", sha256, extension);
+                if !is_media_file(&pooled) {
+                    return Err(invalid());
+                }
+                Ok(format!("{}/{}", kind, pooled))
+            }
+            None => Ok(self.path.clone()),
+        }
+    }
+}
+
+// 恢复标记：暂存目录中须替换的文件，以及须替换的媒体引用
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Staged {
+    files: Vec<String>,
+    media: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub name: String,
+    pub path: String,
+    pub created_at: String,
+    pub records: usize,
+    pub media: usize,
+    // zip 文件大小，不含共享媒体库
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub restored: BackupInfo,
+    // 恢复前为当前数据另做的备份
+    pub safety_backup: Option<BackupInfo>,
+}
+
+// 设置中的备份目录，未设置时为数据目录下的 backups
+pub fn directory(settings: &Settings, paths: &AppPaths) -> PathBuf {
+    settings
+        .backup_dir
+        .as_deref()
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| paths.backups_dir())
+}
+
+// 在仓库锁内取得的快照：一致的数据库副本、保险库与密钥文件的内容以及媒体文件列表。
+// 打包、复制媒体与校验由 write 在释放锁之后完成，放弃时删除临时文件
+pub struct Snapshot {
+    dir: PathBuf,
+    name: String,
+    created_at: DateTime<Utc>,
+    database: PathBuf,
+    files: Vec<(String, Vec<u8>)>,
+    media: Vec<(String, PathBuf)>,
+}
+
+impl Snapshot {
+    // database 在给定路径写入一致的数据库副本
+    pub fn take(
+        dir: &Path,
+        paths: &AppPaths,
+        now: DateTime<Utc>,
+        database: &dyn Fn(&Path) -> Result<(), String>,
+    ) -> Result<Self, String> {
+        fs::create_dir_all(dir.join(MEDIA_DIR)).map_err(|e| format!("创建备份目录失败: {}", e))?;
+        let name = reserve_name(dir, now)?;
+        let mut snapshot = Self {
+            dir: dir.to_path_buf(),
+            database: dir.join(format!("{}.db.tmp", name)),
+            name,
+            created_at: now,
+            files: Vec::new(),
+            media: Vec::new(),
+        };
+        database(&snapshot.database)?;
+        for (name, source) in data_files(paths) {
+            match fs::read(&source) {
+                // 旧版本以明文保存的尘封密钥须在解锁时改为加密保存后才能备份
+                Ok(data) if name == SEAL_KEY_FILE && data.len() == KEY_LEN => {
+                    return Err("尘封密钥尚未加密保存，请解锁后再备份".to_string())
+                }
+                Ok(data) => snapshot.files.push((name, data)),
+                // 从未尘封过记录时可能还没有尘封密钥
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && name != VAULT_FILE => {}
+                Err(e) => return Err(read_error(e)),
+            }
+        }
+        snapshot.media = list_media(paths)?;
+        Ok(snapshot)
+    }
+
+    // 检查数据库副本，复制媒体并打包，再完整校验一遍后改名为正式的备份
+    pub fn write(mut self) -> Result<BackupInfo, String> {
+        let records = Database::verify_file(&self.database)?;
+        let mut files = vec![(
+            DATABASE_FILE.to_string(),
+            fs::read(&self.database).map_err(read_error)?,
+        )];
+        files.append(&mut self.files);
+        let media = copy_media(&self.dir, &self.media)?;
+
+        let manifest = BackupManifest {
+            format: BACKUP_FORMAT.to_string(),
+            format_version: BACKUP_VERSION,
+            schema_version: SCHEMA_VERSION,
+            created_at: self.created_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            records,
+            files: Vec::new(),
+            media,
+        };
+        let tmp = self.tmp();
+        write_zip(&tmp, manifest, &files)?;
+        verify_file(&tmp, &self.dir)?;
+        fs::rename(&tmp, self.dir.join(&self.name)).map_err(|e| format!("保存备份失败: {}", e))?;
+        info(&self.dir, &self.name)
+    }
+
+    fn tmp(&self) -> PathBuf {
+        self.dir.join(format!("{}.tmp", self.name))
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.database);
+        let _ = fs::remove_file(self.tmp());
+    }
+}
+
+// 备份目录中可以读取清单的备份，从新到旧
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    Ok(scan(dir)?.0.into_iter().map(|(info, _)| info).collect())
+}
+
+// 完整校验一份备份：zip 中每个文件的哈希以及共享媒体库中的文件
+pub fn verify(dir: &Path, name: &str) -> Result<BackupInfo, String> {
+    verify_file(&backup_path(dir, name)?, dir)?;
+    info(dir, name)
+}
+
+// 用备份替换数据目录中的加密文件并补齐媒体。调用前须关闭数据库；
+// 设置、可信时钟与调度状态不随备份恢复。
+// 先把备份中的文件与内容不同的媒体写入暂存目录并校验，写入完成标记后再逐个改名替换，
+// 替换中途退出时由 resume_restore 按标记继续完成
+pub fn restore(dir: &Path, name: &str, paths: &AppPaths) -> Result<(), String> {
+    let path = backup_path(dir, name)?;
+    let manifest = verify_file(&path, dir)?;
+    let (mut zip, _) = open(&path)?;
+    let staging = paths.data_dir.join(STAGING_DIR);
+    resume_restore(paths)?;
+    fs::create_dir_all(staging.join(MEDIA_DIR)).map_err(|e| format!("创建暂存目录失败: {}", e))?;
+
+    let result = (|| {
+        let targets = restore_targets(paths);
+        let mut staged = Staged::default();
+        for file in &manifest.files {
+            if !targets.iter().any(|(name, _)| *name == file.path) {
+                return Err(format!("备份中有无法识别的文件: {}", file.path));
+            }
+            let data = read_entry(&mut zip, file)?;
+            atomic::write_file(&staging.join(&file.path), &data)
+                .map_err(|e| format!("恢复 {} 失败: {}", file.path, e))?;
+            staged.files.push(file.path.clone());
+        }
+        Database::verify_file(&staging.join(DATABASE_FILE))?;
+
+        // 缺少的媒体直接补齐；同名但内容不同（如改用新密钥加密过）的媒体随其余文件一起替换
+        for media in &manifest.media {
+            let target = media_target(paths, &media.path)?;
+            let current = match fs::read(&target) {
+                Ok(data) => Some(data),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(read_error(e)),
+            };
+            let same = match (&current, &media.sha256) {
+                (None, _) => false,
+                (Some(data), Some(sha256)) => archive::sha256_hex(data) == *sha256,
+                // 第 1 版备份没有哈希，同名文件视为相同
+                (Some(_), None) => true,
+            };
+            if same {
+                continue;
+            }
+            let data = read_pool(dir, media)?;
+            let destination = match current {
+                None => target,
+                Some(_) => {
+                    staged.media.push(media.path.clone());
+                    staging.join(MEDIA_DIR).join(&media.path)
+                }
+            };
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("恢复媒体失败: {}", e))?;
+            }
+            atomic::write_file(&destination, &data).map_err(|e| format!("恢复媒体失败: {}", e))?;
+        }
+
+        let marker = serde_json::to_vec(&staged).map_err(|e| e.to_string())?;
+        atomic::write_file(&staging.join(STAGING_MARKER), &marker)
+            .map_err(|e| format!("写入恢复标记失败: {}", e))
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    resume_restore(paths)
+}
+
+// 完成已暂存的恢复：删除旧的数据库日志与快照，逐个改名替换，并删除备份中没有的密钥环与尘封密钥。
+// 每一步都可以重复执行；没有完成标记的暂存目录是写入中途失败留下的，直接删除。
+// 须在打开数据库之前调用
+pub fn resume_restore(paths: &AppPaths) -> Result<(), String> {
+    let staging = paths.data_dir.join(STAGING_DIR);
+    let marker = match fs::read(staging.join(STAGING_MARKER)) {
+        Ok(marker) => marker,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return match fs::remove_dir_all(&staging) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("删除暂存目录失败: {}", e))
+                }
+                _ => Ok(()),
+            };
+        }
+        Err(e) => return Err(read_error(e)),
+    };
+    let staged: Staged =
+        serde_json::from_slice(&marker).map_err(|e| format!("恢复标记已损坏: {}", e))?;
+
+    // 旧的数据库日志与快照不能与恢复的文件混用
+    let database = paths.database_file();
+    let vault = paths.vault_file();
+    for stale in [
+        with_suffix(&database, "-wal"),
+        with_suffix(&database, "-shm"),
+        with_suffix(&database, ".bak"),
+        with_suffix(&vault, ".bak"),
+    ] {
+        remove_if_exists(&stale)?;
+    }
+    for (name, target) in restore_targets(paths) {
+        let source = staging.join(&name);
+        if !staged.files.contains(&name) {
+            remove_if_exists(&target)?;
+        } else if source.exists() {
+            atomic::rename(&source, &target).map_err(|e| format!("恢复 {} 失败: {}", name, e))?;
+        }
+    }
+    for media_ref in &staged.media {
+        let source = staging.join(MEDIA_DIR).join(media_ref);
+        if source.exists() {
+            atomic::rename(&source, &media_target(paths, media_ref)?)
+                .map_err(|e| format!("恢复媒体失败: {}", e))?;
+        }
+    }
+    fs::remove_dir_all(&staging).map_err(|e| format!("删除暂存目录失败: {}", e))
+}
+
+// 从每份备份的密钥环中删除已销毁的密钥，使销毁的记录、媒体与标签在备份中同样无法解开。
+// 改写后的备份先完整校验再替换原文件；无法读取或来自另一个保险库的备份保持不变
+pub fn shred(dir: &Path, master_key: &Key, shredded: &Shredded) -> Result<(), String> {
+    for (info, manifest) in scan(dir)?.0 {
+        let path = PathBuf::from(&info.path);
+        let Some(entry) = manifest.files.iter().find(|f| f.path == KEYRING_FILE) else {
+            continue;
+        };
+        let (mut zip, _) = open(&path)?;
+        let Some(keyring) =
+            keyring::drop_keys(&read_entry(&mut zip, entry)?, master_key, shredded)?
+        else {
+            continue;
+        };
+        let mut files = Vec::with_capacity(manifest.files.len());
+        for file in &manifest.files {
+            let data = if file.path == KEYRING_FILE {
+                keyring.clone()
+            } else {
+                read_entry(&mut zip, file)?
+            };
+            files.push((file.path.clone(), data));
+        }
+        drop(zip);
+
+        let tmp = dir.join(format!("{}.shred.tmp", info.name));
+        let result = write_zip(
+            &tmp,
+            BackupManifest {
+                files: Vec::new(),
+                ..manifest
+            },
+            &files,
+        )
+        .and_then(|_| verify_file(&tmp, dir))
+        .and_then(|_| atomic::rename(&tmp, &path).map_err(|e| format!("保存备份失败: {}", e)));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result?;
+    }
+    Ok(())
+}
+
+// 按天、周、月各保留最近若干个时段中最新的一份备份，最新的一份始终保留；
+// 随后删除共享媒体库中不再被任何备份引用的文件
+pub fn rotate(dir: &Path, settings: &Settings) -> Result<(), String> {
+    let (backups, unreadable) = scan(dir)?;
+    let dated: Vec<(String, DateTime<Local>)> = backups
+        .iter()
+        .filter_map(|(info, _)| {
+            let time = DateTime::parse_from_rfc3339(&info.created_at).ok()?;
+            Some((info.name.clone(), time.with_timezone(&Local)))
+        })
+        .collect();
+    let kept = keep(&dated, settings);
+
+    let mut referenced = HashSet::new();
+    for (info, manifest) in backups {
+        if kept.contains(&info.name) {
+            referenced.extend(manifest.media.iter().filter_map(|m| m.pool_path().ok()));
+        } else {
+            fs::remove_file(&info.path).map_err(|e| format!("删除旧备份失败: {}", e))?;
+        }
+    }
+    // 无法读取的备份可能来自更新版本的应用；正在写入的备份已复制的媒体尚未写入任何清单。
+    // 这两种情况下都不清理媒体
+    if unreadable > 0 || writing(dir) {
+        return Ok(());
+    }
+    for kind in ["images", "music"] {
+        let pool = dir.join(MEDIA_DIR).join(kind);
+        let Ok(entries) = fs::read_dir(&pool) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let media_ref = format!("{}/{}", kind, entry.file_name().to_string_lossy());
+            if !referenced.contains(&media_ref) {
+                fs::remove_file(entry.path()).map_err(|e| format!("清理备份媒体失败: {}", e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 备份所在的本地时段：年内的日、ISO 周或月
+type PeriodKey = fn(&DateTime<Local>) -> (i32, u32);
+
+// 每类时段从新到旧数过去，每个时段保留最新的一份，直到达到保留数
+fn keep(backups: &[(String, DateTime<Local>)], settings: &Settings) -> HashSet<String> {
+    let periods: [(u32, PeriodKey); 3] = [
+        (settings.backup_keep_daily, |t| (t.year(), t.ordinal())),
+        (settings.backup_keep_weekly, |t| {
+            (t.iso_week().year(), t.iso_week().week())
+        }),
+        (settings.backup_keep_monthly, |t| (t.year(), t.month())),
+    ];
+    let mut newest_first: Vec<&(String, DateTime<Local>)> = backups.iter().collect();
+    newest_first.sort_by_key(|(_, time)| Reverse(*time));
+
+    let mut kept: HashSet<String> = newest_first
+        .first()
+        .map(|(n, _)| n.clone())
+        .into_iter()
+        .collect();
+    for (limit, period) in periods {
+        let mut last = None;
+        let mut count = 0;
+        for (name, time) in &newest_first {
+            if count >= limit {
+                break;
+            }
+            let key = period(time);
+            if last != Some(key) {
+                kept.insert(name.clone());
+                last = Some(key);
+                count += 1;
+            }
+        }
+    }
+    kept
+}
+
+// 读取备份目录中全部备份的清单，并返回无法读取的备份数
+fn scan(dir: &Path) -> Result<(Vec<(BackupInfo, BackupManifest)>, usize), String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(format!("读取备份目录失败: {}", e)),
+    };
+    let mut backups = Vec::new();
+    let mut unreadable = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_backup_name(&name) {
+            continue;
+        }
+        match open(&entry.path()) {
+            Ok((_, manifest)) => {
+                let bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                backups.push((describe(dir, &name, &manifest, bytes), manifest));
+            }
+            Err(_) => unreadable += 1,
+        }
+    }
+    backups.sort_by(|a, b| b.0.created_at.cmp(&a.0.created_at));
+    Ok((backups, unreadable))
+}
+
+fn info(dir: &Path, name: &str) -> Result<BackupInfo, String> {
+    let path = backup_path(dir, name)?;
+    let (_, manifest) = open(&path)?;
+    let bytes = fs::metadata(&path).map(|m| m.len()).map_err(read_error)?;
+    Ok(describe(dir, name, &manifest, bytes))
+}
+
+fn describe(dir: &Path, name: &str, manifest: &BackupManifest, bytes: u64) -> BackupInfo {
+    BackupInfo {
+        name: name.to_string(),
+        path: dir.join(name).to_string_lossy().into_owned(),
+        created_at: manifest.created_at.clone(),
+        records: manifest.records,
+        media: manifest.media.len(),
+        bytes,
+    }
+}
+
+fn write_zip(
+    path: &Path,
+    mut manifest: BackupManifest,
+    files: &[(String, Vec<u8>)],
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("创建备份文件失败: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        zip.start_file(
+            name.as_str(),
+            options.large_file(data.len() as u64 >= u32::MAX as u64),
+        )
+        .map_err(write_error)?;
+        zip.write_all(data)
+            .map_err(|e| format!("写入备份失败: {}", e))?;
+        manifest.files.push(ManifestFile {
+            path: name.clone(),
+            size: data.len() as u64,
+            sha256: archive::sha256_hex(data),
+        });
+    }
+    let content = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    zip.start_file(MANIFEST_FILE, options)
+        .map_err(write_error)?;
+    zip.write_all(&content)
+        .map_err(|e| format!("写入备份失败: {}", e))?;
+    let file = zip.finish().map_err(write_error)?;
+    file.sync_all().map_err(|e| format!("写入备份失败: {}", e))
+}
+
+fn open(path: &Path) -> Result<(ZipArchive<File>, BackupManifest), String> {
+    let file = File::open(path).map_err(|e| format!("无法打开备份: {}", e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("备份已损坏: {}", e))?;
+    let mut content = Vec::new();
+    zip.by_name(MANIFEST_FILE)
+        .map_err(|_| "不是有效的备份文件".to_string())?
+        .take(MAX_MANIFEST_BYTES)
+        .read_to_end(&mut content)
+        .map_err(read_error)?;
+    let manifest: BackupManifest =
+        serde_json::from_slice(&content).map_err(|_| "不是有效的备份文件".to_string())?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err("不是有效的备份文件".to_string());
+    }
+    if manifest.format_version > BACKUP_VERSION || manifest.schema_version > SCHEMA_VERSION {
+        return Err("备份来自更新版本的应用，请先升级".to_string());
+    }
+    Ok((zip, manifest))
+}
+
+fn verify_file(path: &Path, dir: &Path) -> Result<BackupManifest, String> {
+    let (mut zip, manifest) = open(path)?;
+    if !manifest.files.iter().any(|f| f.path == DATABASE_FILE) {
+        return Err("备份已损坏: 缺少数据库".to_string());
+    }
+    for file in &manifest.files {
+        read_entry(&mut zip, file)?;
+    }
+    for media in &manifest.media {
+        match media.sha256 {
+            Some(_) => {
+                read_pool(dir, media)?;
+            }
+            None => {
+                let size =
+                    fs::metadata(dir.join(MEDIA_DIR).join(media.pool_path()?)).map(|m| m.len());
+                if size.ok() != Some(media.size) {
+                    return Err(format!("备份媒体缺失或已损坏: {}", media.path));
+                }
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+// 读出共享媒体库中的文件并核对大小与哈希
+fn read_pool(dir: &Path, media: &BackupMedia) -> Result<Vec<u8>, String> {
+    let corrupt = || format!("备份媒体缺失或已损坏: {}", media.path);
+    let data = fs::read(dir.join(MEDIA_DIR).join(media.pool_path()?)).map_err(|_| corrupt())?;
+    let matches = data.len() as u64 == media.size
+        && media
+            .sha256
+            .as_ref()
+            .is_none_or(|sha256| archive::sha256_hex(&data) == *sha256);
+    if !matches {
+        return Err(corrupt());
+    }
+    Ok(data)
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, file: &ManifestFile) -> Result<Vec<u8>, String> {
+    let corrupt = || format!("备份已损坏: {}", file.path);
+    let mut data = Vec::new();
+    zip.by_name(&file.path)
+        .map_err(|_| corrupt())?
+        .take(file.size + 1)
+        .read_to_end(&mut data)
+        .map_err(|_| corrupt())?;
+    if data.len() as u64 != file.size || archive::sha256_hex(&data) != file.sha256 {
+        return Err(corrupt());
+    }
+    Ok(data)
+}
+
+// 数据目录中的媒体文件：媒体引用与文件路径。只列出文件，复制在释放仓库锁之后进行
+fn list_media(paths: &AppPaths) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut media = Vec::new();
+    for (kind, source_dir) in [("images", &paths.images_dir), ("music", &paths.music_dir)] {
+        let entries = match fs::read_dir(source_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("读取媒体目录失败: {}", e)),
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_media_file(&name) {
+                media.push((format!("{}/{}", kind, name), entry.path()));
+            }
+        }
+    }
+    Ok(media)
+}
+
+// 把媒体复制到共享媒体库。库中按密文哈希命名，已有且哈希相符的文件不再复制，
+// 新复制的文件读回核对
+fn copy_media(dir: &Path, media: &[(String, PathBuf)]) -> Result<Vec<BackupMedia>, String> {
+    let mut copied = Vec::with_capacity(media.len());
+    for (media_ref, source) in media {
+        // 快照之后被删除的媒体仍被快照中的记录引用，备份不完整，等下次重试
+        let data = fs::read(source).map_err(|e| format!("读取媒体 {} 失败: {}", media_ref, e))?;
+        let sha256 = archive::sha256_hex(&data);
+        let item = BackupMedia {
+            path: media_ref.clone(),
+            size: data.len() as u64,
+            sha256: Some(sha256.clone()),
+        };
+        let target = dir.join(MEDIA_DIR).join(item.pool_path()?);
+        let pooled = fs::read(&target).ok();
+        if pooled.is_none_or(|pooled| archive::sha256_hex(&pooled) != sha256) {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("创建备份目录失败: {}", e))?;
+            }
+            atomic::write_file(&target, &data).map_err(|e| format!("备份媒体失败: {}", e))?;
+            if fs::read(&target).ok().as_deref() != Some(data.as_slice()) {
+                return Err(format!("备份校验失败: {}", media_ref));
+            }
+        }
+        copied.push(item);
+    }
+    Ok(copied)
+}
+
+// 与媒体库引用的格式一致：64 位十六进制的内容哈希加扩展名，不含临时文件
+fn is_media_file(name: &str) -> bool {
+    name.split_once('.').is_some_and(|(id, extension)| {
+        id.len() == 64
+            && id.bytes().all(|b| b.is_ascii_hexdigit())
+            && mime_for_extension(extension).is_some()
+    })
+}
+
+fn media_target(paths: &AppPaths, media_ref: &str) -> Result<PathBuf, String> {
+    let invalid = || format!("备份中有无效的媒体引用: {}", media_ref);
+    let (kind, name) = media_ref.split_once('/').ok_or_else(invalid)?;
+    if !is_media_file(name) {
+        return Err(invalid());
+    }
+    match kind {
+        "images" => Ok(paths.images_dir.join(name)),
+        "music" => Ok(paths.music_dir.join(name)),
+        _ => Err(invalid()),
+    }
+}
+
+// 恢复时替换的全部文件
+fn restore_targets(paths: &AppPaths) -> Vec<(String, PathBuf)> {
+    let mut targets = data_files(paths);
+    targets.push((DATABASE_FILE.to_string(), paths.database_file()));
+    targets
+}
+
+// 除数据库外随备份保存的加密文件
+fn data_files(paths: &AppPaths) -> Vec<(String, PathBuf)> {
+    [
+        paths.vault_file(),
+        paths.keyring_file(),
+        paths.seal_key_file(),
+    ]
+    .into_iter()
+    .filter_map(|path| {
+        let name = path.file_name()?.to_str()?.to_string();
+        Some((name, path))
+    })
+    .collect()
+}
+
+// backup-20241018-093000.zip，同一秒内的多份备份加序号。
+// 先创建对应的临时文件占用名称，同时进行的两份备份不会用同一个名称
+fn reserve_name(dir: &Path, now: DateTime<Utc>) -> Result<String, String> {
+    let stem = format!("{}{}", NAME_PREFIX, now.format("%Y%m%d-%H%M%S"));
+    let mut name = format!("{}{}", stem, NAME_SUFFIX);
+    let mut counter = 2;
+    loop {
+        if !dir.join(&name).exists() {
+            let reserved = File::options()
+                .write(true)
+                .create_new(true)
+                .open(dir.join(format!("{}.tmp", name)));
+            match reserved {
+                Ok(_) => return Ok(name),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(format!("创建备份文件失败: {}", e)),
+            }
+        }
+        name = format!("{}-{}{}", stem, counter, NAME_SUFFIX);
+        counter += 1;
+    }
+}
+
+// 备份目录中是否有正在写入的备份。超过一天的临时文件是中途退出留下的，直接删除
+fn writing(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return true;
+    };
+    let mut writing = false;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let stem = [".db.tmp", ".shred.tmp", ".tmp"]
+            .into_iter()
+            .find_map(|suffix| name.strip_suffix(suffix));
+        if !stem.is_some_and(is_backup_name) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        if age.is_some_and(|age| age > STALE_TMP) {
+            let _ = fs::remove_file(entry.path());
+        } else {
+            writing = true;
+        }
+    }
+    writing
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(NAME_PREFIX)
+        && name.ends_with(NAME_SUFFIX)
+        && !name.contains(['/', '\\'])
+        && name != ".."
+}
+
+// 只接受备份目录中的文件名，防止越出备份目录
+fn backup_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if !is_backup_name(name) {
+        return Err(format!("无效的备份名称: {}", name));
+    }
+    Ok(dir.join(name))
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("删除 {} 失败: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn read_error(e: std::io::Error) -> String {
+    format!("读取文件失败: {}", e)
+}
+
+fn write_error(e: zip::result::ZipError) -> String {
+    format!("写入备份失败: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    // 本地时间正午，避开夏令时切换
+    fn at(year: i32, month: u32, day: u32) -> (String, DateTime<Local>) {
+        let time = Local
+            .with_ymd_and_hms(year, month, day, 12, 0, 0)
+            .single()
+            .unwrap();
+        (format!("{:04}-{:02}-{:02}", year, month, day), time)
+    }
+
+    fn limits(daily: u32, weekly: u32, monthly: u32) -> Settings {
+        Settings {
+            backup_keep_daily: daily,
+            backup_keep_weekly: weekly,
+            backup_keep_monthly: monthly,
+            ..Settings::default()
+        }
+    }
+
+    fn kept(backups: &[(String, DateTime<Local>)], settings: &Settings) -> Vec<String> {
+        let mut kept: Vec<String> = keep(backups, settings).into_iter().collect();
+        kept.sort();
+        kept
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_recent_day() {
+        let mut backups: Vec<_> = (1..=5).map(|day| at(2024, 3, day)).collect();
+        // 同一天较早的一份不占用保留数
+        let mut earlier = at(2024, 3, 5);
+        earlier.0 = "2024-03-05-early".to_string();
+        earlier.1 -= chrono::Duration::hours(2);
+        backups.push(earlier);
+        assert_eq!(
+            kept(&backups, &limits(3, 0, 0)),
+            ["2024-03-03", "2024-03-04", "2024-03-05"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_recent_week_and_month() {
+        // 2024-03-04 与 2024-03-10 同在第 10 周，2024-03-11 在第 11 周
+        let backups = [
+            at(2024, 1, 20),
+            at(2024, 2, 10),
+            at(2024, 2, 25),
+            at(2024, 3, 4),
+            at(2024, 3, 10),
+            at(2024, 3, 11),
+        ];
+        assert_eq!(
+            kept(&backups, &limits(0, 2, 0)),
+            ["2024-03-10", "2024-03-11"]
+        );
+        assert_eq!(
+            kept(&backups, &limits(0, 0, 3)),
+            ["2024-01-20", "2024-02-25", "2024-03-11"]
+        );
+    }
+
+    #[test]
+    fn weeks_follow_iso_years_across_new_year() {
+        // 2020-12-31 与 2021-01-01 同属 2020 年第 53 周，2021-01-04 是 2021 年第 1 周
+        let backups = [
+            at(2020, 12, 28),
+            at(2020, 12, 31),
+            at(2021, 1, 1),
+            at(2021, 1, 4),
+        ];
+        assert_eq!(
+            kept(&backups, &limits(0, 2, 0)),
+            ["2021-01-01", "2021-01-04"]
+        );
+        // 按月则跨年的两天分属不同月份
+        assert_eq!(
+            kept(&backups, &limits(0, 0, 2)),
+            ["2020-12-31", "2021-01-04"]
+        );
+    }
+
+    #[test]
+    fn always_keeps_the_newest_backup() {
+        let backups = [at(2024, 3, 1), at(2024, 3, 2)];
+        assert_eq!(kept(&backups, &limits(0, 0, 0)), ["2024-03-02"]);
+        assert!(keep(&[], &limits(0, 0, 0)).is_empty());
+    }
+
+    const PHOTO: &str =
+        "images/0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef.jpg";
+
+    // 数据目录中放入数据库、保险库、尘封密钥与一张照片，返回数据目录与备份目录
+    fn data_dir() -> (AppPaths, PathBuf) {
+        let root = std::env::temp_dir().join(format!("backup-{:016x}", rand::random::<u64>()));
+        let paths = AppPaths::from_data_dir(root.join("data"));
+        fs::create_dir_all(&paths.images_dir).unwrap();
+        Database::open(&paths.database_file()).unwrap();
+        fs::write(paths.vault_file(), b"vault").unwrap();
+        fs::write(paths.seal_key_file(), [7u8; 60]).unwrap();
+        fs::write(photo(&paths), b"photo").unwrap();
+        (paths, root.join("backups"))
+    }
+
+    fn photo(paths: &AppPaths) -> PathBuf {
+        media_target(paths, PHOTO).unwrap()
+    }
+
+    fn back_up(dir: &Path, paths: &AppPaths) -> BackupInfo {
+        let database = Database::open(&paths.database_file()).unwrap();
+        Snapshot::take(dir, paths, Utc::now(), &|path| database.snapshot_to(path))
+            .unwrap()
+            .write()
+            .unwrap()
+    }
+
+    fn cleanup(paths: &AppPaths) {
+        fs::remove_dir_all(paths.data_dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restore_replaces_the_whole_set_of_files() {
+        let (paths, dir) = data_dir();
+        let info = back_up(&dir, &paths);
+        assert_eq!(verify(&dir, &info.name).unwrap().media, 1);
+        assert!(!writing(&dir));
+
+        // 备份之后的改动：保险库与照片内容变化、新增密钥环、留下旧的预写日志
+        fs::write(paths.vault_file(), b"changed").unwrap();
+        fs::write(photo(&paths), b"rekeyed").unwrap();
+        fs::write(paths.keyring_file(), b"keyring").unwrap();
+        let wal = with_suffix(&paths.database_file(), "-wal");
+        fs::write(&wal, b"stale").unwrap();
+
+        restore(&dir, &info.name, &paths).unwrap();
+        assert_eq!(fs::read(paths.vault_file()).unwrap(), b"vault");
+        assert_eq!(fs::read(photo(&paths)).unwrap(), b"photo");
+        assert_eq!(fs::read(paths.seal_key_file()).unwrap(), [7u8; 60]);
+        assert!(!paths.keyring_file().exists());
+        assert!(!wal.exists());
+        assert!(!paths.data_dir.join(STAGING_DIR).exists());
+        Database::verify_file(&paths.database_file()).unwrap();
+        cleanup(&paths);
+    }
+
+    #[test]
+    fn plaintext_seal_key_is_not_backed_up() {
+        let (paths, dir) = data_dir();
+        fs::write(paths.seal_key_file(), [7u8; KEY_LEN]).unwrap();
+        let database = Database::open(&paths.database_file()).unwrap();
+        let snapshot = Snapshot::take(&dir, &paths, Utc::now(), &|path| database.snapshot_to(path));
+        assert!(snapshot.is_err());
+        assert!(list(&dir).unwrap().is_empty());
+        assert!(!writing(&dir));
+        cleanup(&paths);
+    }
+
+    #[test]
+    fn corrupt_pool_media_fails_verification() {
+        let (paths, dir) = data_dir();
+        let info = back_up(&dir, &paths);
+        let pooled = dir
+            .join(MEDIA_DIR)
+            .join("images")
+            .join(format!("{}.jpg", archive::sha256_hex(b"photo")));
+        fs::write(&pooled, b"PHOTO").unwrap();
+        assert!(verify(&dir, &info.name).is_err());
+        assert!(restore(&dir, &info.name, &paths).is_err());
+        // 下一份备份发现内容不符，重新复制
+        let next = back_up(&dir, &paths);
+        assert_eq!(fs::read(&pooled).unwrap(), b"photo");
+        verify(&dir, &next.name).unwrap();
+        cleanup(&paths);
+    }
+
+    #[test]
+    fn resume_restore_finishes_a_partly_renamed_restore() {
+        let (paths, _) = data_dir();
+        fs::write(paths.keyring_file(), b"keyring").unwrap();
+        let staging = paths.data_dir.join(STAGING_DIR);
+        fs::create_dir_all(staging.join(MEDIA_DIR).join("images")).unwrap();
+        // 数据库已改名替换，保险库、尘封密钥与照片仍在暂存目录中
+        fs::write(staging.join(VAULT_FILE), b"restored vault").unwrap();
+        fs::write(staging.join(SEAL_KEY_FILE), b"restored seal").unwrap();
+        fs::write(staging.join(MEDIA_DIR).join(PHOTO), b"restored photo").unwrap();
+        let staged = Staged {
+            files: vec![
+                DATABASE_FILE.to_string(),
+                VAULT_FILE.to_string(),
+                SEAL_KEY_FILE.to_string(),
+            ],
+            media: vec![PHOTO.to_string()],
+        };
+        fs::write(
+            staging.join(STAGING_MARKER),
+            serde_json::to_vec(&staged).unwrap(),
+        )
+        .unwrap();
+
+        resume_restore(&paths).unwrap();
+        assert_eq!(fs::read(paths.vault_file()).unwrap(), b"restored vault");
+        assert_eq!(fs::read(paths.seal_key_file()).unwrap(), b"restored seal");
+        assert_eq!(fs::read(photo(&paths)).unwrap(), b"restored photo");
+        assert!(paths.database_file().exists());
+        // 备份中没有的密钥环被删除
+        assert!(!paths.keyring_file().exists());
+        assert!(!staging.exists());
+        // 再次调用没有任何作用
+        resume_restore(&paths).unwrap();
+        cleanup(&paths);
+    }
+
+    #[test]
+    fn unfinished_staging_is_discarded() {
+        let (paths, _) = data_dir();
+        let staging = paths.data_dir.join(STAGING_DIR);
+        fs::create_dir_all(&staging).unwrap();
+        fs::write(staging.join(VAULT_FILE), b"partial").unwrap();
+
+        resume_restore(&paths).unwrap();
+        assert!(!staging.exists());
+        assert_eq!(fs::read(paths.vault_file()).unwrap(), b"vault");
+        cleanup(&paths);
+    }
+
+    #[test]
+    fn shred_drops_destroyed_keys_from_stored_keyrings() {
+        let (paths, dir) = data_dir();
+        let master_key = crate::crypto::generate_key();
+        let mut keyring = keyring::Keyring::load(paths.keyring_file(), &master_key).unwrap();
+        keyring.encrypt_for("record_1_a", b"a").unwrap();
+        let kept = keyring.encrypt_for("record_2_b", b"b").unwrap();
+        keyring.save(&master_key).unwrap();
+        let info = back_up(&dir, &paths);
+
+        let shredded = Shredded {
+            records: ["record_1_a".to_string()].into(),
+            ..Shredded::default()
+        };
+        shred(&dir, &master_key, &shredded).unwrap();
+        verify(&dir, &info.name).unwrap();
+
+        let (mut zip, manifest) = open(&dir.join(&info.name)).unwrap();
+        let entry = manifest
+            .files
+            .iter()
+            .find(|f| f.path == KEYRING_FILE)
+            .unwrap();
+        let extracted = dir.join("keyring.bin");
+        fs::write(&extracted, read_entry(&mut zip, entry).unwrap()).unwrap();
+        let stored = keyring::Keyring::load(extracted, &master_key).unwrap();
+        assert!(stored.decrypt_for("record_1_a", "").unwrap().is_none());
+        assert_eq!(
+            stored.decrypt_for("record_2_b", &kept).unwrap().unwrap(),
+            b"b"
+        );
+        // 已经删除过的密钥不再改写备份
+        let before = fs::read(dir.join(&info.name)).unwrap();
+        shred(&dir, &master_key, &shredded).unwrap();
+        assert_eq!(fs::read(dir.join(&info.name)).unwrap(), before);
+        cleanup(&paths);
+    }
+}
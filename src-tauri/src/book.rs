@@ -0,0 +1,960 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::Datelike;
+use fontdb::{Family, Query, Style, Weight};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::types::{CidFontType, FontFlags, SystemInfo, UnicodeCmap};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use serde::{Deserialize, Serialize};
+use ttf_parser::{name_id, Face, GlyphId, RawFace, Tag};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::document;
+use crate::media::MediaFile;
+use crate::models::EmotionalRecord;
+use crate::resurface;
+use crate::settings::Settings;
+use crate::thumbnail;
+
+// A5 纸张，单位为 pt
+const PAGE_WIDTH: f32 = 419.53;
+const PAGE_HEIGHT: f32 = 595.28;
+const MARGIN: f32 = 56.0;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+// 图片缩小到该边长后以 JPEG 嵌入，按 150 dpi 排版，最高占版心的七成
+const IMAGE_SIZE: u32 = 1600;
+const IMAGE_DPI: f32 = 150.0;
+const IMAGE_MAX_HEIGHT: f32 = (PAGE_HEIGHT - 2.0 * MARGIN) * 0.7;
+// 记录标题与日期至少要和正文第一行排在同一页
+const KEEP_WITH_NEXT: f32 = 72.0;
+const DEFAULT_TITLE: &str = "Pick Up Memories";
+const FONT_NAME: Name = Name(b"F1");
+const CFF: Tag = Tag::from_bytes(b"CFF ");
+const CFF2: Tag = Tag::from_bytes(b"CFF2");
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+// 依次尝试的字体，印刷优先宋体；都不可用时在全部系统字体中挑选覆盖最多字符的一个
+const PREFERRED_FONTS: &[&str] = &[
+    "Noto Serif CJK SC",
+    "Source Han Serif SC",
+    "Songti SC",
+    "STSong",
+    "SimSun",
+    "Noto Sans CJK SC",
+    "Source Han Sans SC",
+    "PingFang SC",
+    "Hiragino Sans GB",
+    "Microsoft YaHei",
+    "WenQuanYi Micro Hei",
+    "Noto Serif",
+    "DejaVu Serif",
+    "Liberation Serif",
+    "Times New Roman",
+    "Noto Sans",
+    "DejaVu Sans",
+    "Arial",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BookOptions {
+    // 要收录的记录，未指定时为全部（不含回收站）
+    pub ids: Option<Vec<String>>,
+    // 只收录该年（按本地时区）创建的记录
+    pub year: Option<i32>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookSummary {
+    pub path: String,
+    pub records: usize,
+    // 尘封中的记录只印出日期与尘封信息
+    pub sealed: usize,
+    pub pages: usize,
+    pub images: usize,
+    // 缺失或无法解码的图片
+    pub missing_media: Vec<String>,
+    pub font: String,
+    // 所用字体中没有、因而略去的字符（如表情符号）
+    pub missing_characters: String,
+}
+
+// 缩小并转为 JPEG 的图片，尺寸为像素
+pub struct BookImage {
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+// 在仓库锁内收集好的纪念册内容，解码图片、排版与写入在释放锁之后进行
+pub struct BookExport {
+    pub title: Option<String>,
+    // 已按创建时间排序
+    pub records: Vec<EmotionalRecord>,
+    pub media: Vec<(String, MediaFile)>,
+    pub missing_media: Vec<String>,
+}
+
+impl BookExport {
+    pub fn write(self, path: &Path, settings: &Settings) -> Result<BookSummary, String> {
+        let mut images = HashMap::new();
+        let mut missing_media = self.missing_media;
+        for (media_ref, file) in self.media {
+            let image = file
+                .open()
+                .and_then(|mut reader| reader.read_all())
+                .and_then(|data| prepare_image(&Zeroizing::new(data)));
+            match image {
+                Ok(image) => {
+                    images.insert(media_ref, image);
+                }
+                Err(_) => missing_media.push(media_ref),
+            }
+        }
+
+        let rendered = render(self.title.as_deref(), &self.records, settings, &images)?;
+        atomic::write_file(path, &rendered.pdf).map_err(|e| format!("写入文件失败: {}", e))?;
+
+        Ok(BookSummary {
+            path: path.to_string_lossy().into_owned(),
+            records: self.records.len(),
+            sealed: self.records.iter().filter(|r| r.is_sealed).count(),
+            pages: rendered.pages,
+            images: images.len(),
+            missing_media,
+            font: rendered.font,
+            missing_characters: rendered.missing_characters,
+        })
+    }
+}
+
+pub struct RenderedBook {
+    pub pdf: Vec<u8>,
+    pub pages: usize,
+    pub font: String,
+    pub missing_characters: String,
+}
+
+pub fn prepare_image(data: &[u8]) -> Result<BookImage, String> {
+    let image = thumbnail::decode(data)?;
+    let (width, height) = thumbnail::fit(image.width(), image.height(), IMAGE_SIZE);
+    Ok(BookImage {
+        jpeg: thumbnail::render(&image, IMAGE_SIZE)?,
+        width,
+        height,
+    })
+}
+
+// 排版为带封面、按月分章、有页码的 PDF。records 须已按创建时间排序，
+// images 为未尘封记录中可用的图片。字体取自系统字体并只嵌入用到的字形
+pub fn render(
+    title: Option<&str>,
+    records: &[EmotionalRecord],
+    settings: &Settings,
+    images: &HashMap<String, BookImage>,
+) -> Result<RenderedBook, String> {
+    let title = title
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(DEFAULT_TITLE);
+    let chapters = chapters(records);
+    let mut text: String = [title, "共篇记忆年月日0123456789—·/♪#"].concat();
+    for record in records {
+        text.push_str(document::display_title(record));
+        text.push_str(&record.content);
+        text.push_str(&document::sealed_notice(record));
+        text.extend(record.tags.iter().map(String::as_str));
+        text.extend(record.music_title.as_deref());
+        if let Some(mood) = &record.mood {
+            text.push_str(&document::mood_label(settings, &mood.id));
+        }
+    }
+    let font = find_font(&text)?;
+    let face = Face::parse(&font.data, font.index).map_err(|e| format!("无法读取字体: {}", e))?;
+    let mut layout = Layout::new(&face);
+
+    // 封面
+    layout.new_page();
+    layout.cursor = PAGE_HEIGHT * 0.3;
+    layout.text(title, &COVER_TITLE);
+    layout.space(18.0);
+    if let (Some(first), Some(last)) = (records.first(), records.last()) {
+        let date = |r: &EmotionalRecord| document::local_time(&r.created_at, "%Y年%-m月%-d日");
+        let range = match (date(first), date(last)) {
+            (first, last) if first == last => first,
+            (first, last) => format!("{} — {}", first, last),
+        };
+        layout.text(&range, &COVER_LINE);
+    }
+    layout.text(&format!("共 {} 篇记忆", records.len()), &COVER_LINE);
+
+    let mut image_order: Vec<&str> = Vec::new();
+    let mut outline = Vec::new();
+    for (heading, chapter) in chapters {
+        layout.new_page();
+        layout.space(36.0);
+        outline.push((heading.clone(), layout.pages.len() - 1));
+        layout.text(&heading, &CHAPTER);
+        layout.space(24.0);
+
+        for record in chapter {
+            if !layout.fits(KEEP_WITH_NEXT) {
+                layout.new_page();
+            }
+            layout.text(document::display_title(record), &HEADING);
+            let mut meta = document::local_time(&record.created_at, "%Y-%m-%d %H:%M");
+            if let Some(mood) = &record.mood {
+                meta.push_str(&format!(
+                    " · {} {}/{}",
+                    document::mood_label(settings, &mood.id),
+                    mood.intensity,
+                    settings.mood_intensity_levels
+                ));
+            }
+            for tag in &record.tags {
+                meta.push_str(&format!(" · #{}", tag));
+            }
+            layout.text(&meta, &META);
+            layout.space(6.0);
+
+            if record.is_sealed {
+                layout.text(&document::sealed_notice(record), &SEALED);
+            } else {
+                for line in record.content.trim_end().lines() {
+                    match line.trim() {
+                        "" => layout.space(BODY.size * 0.6),
+                        _ => layout.text(line, &BODY),
+                    }
+                }
+                for media_ref in &record.images {
+                    let Some(image) = images.get(media_ref) else {
+                        continue;
+                    };
+                    let index = match image_order.iter().position(|r| r == media_ref) {
+                        Some(index) => index,
+                        None => {
+                            image_order.push(media_ref);
+                            image_order.len() - 1
+                        }
+                    };
+                    layout.image(index, image);
+                }
+            }
+            if let Some(music_title) = &record.music_title {
+                layout.text(&format!("♪ {}", music_title), &META);
+            }
+            layout.space(28.0);
+        }
+    }
+    layout.number_pages();
+
+    let images: Vec<&BookImage> = image_order.iter().map(|r| &images[*r]).collect();
+    let pages = layout.pages.len();
+    let missing_characters = layout.missing.iter().collect();
+    let pdf = write_pdf(title, &font, &face, &layout, &images, &outline)?;
+    Ok(RenderedBook {
+        pdf,
+        pages,
+        font: font.name,
+        missing_characters,
+    })
+}
+
+// 按创建时间所在的本地月份分章，时间无法解析的记录归入最后一章
+fn chapters(records: &[EmotionalRecord]) -> Vec<(String, Vec<&EmotionalRecord>)> {
+    let mut chapters: Vec<(String, Vec<&EmotionalRecord>)> = Vec::new();
+    let mut undated = Vec::new();
+    for record in records {
+        let Some(date) = resurface::local_date(&record.created_at) else {
+            undated.push(record);
+            continue;
+        };
+        let heading = format!("{}年{}月", date.year(), date.month());
+        match chapters.last_mut() {
+            Some((last, chapter)) if *last == heading => chapter.push(record),
+            _ => chapters.push((heading, vec![record])),
+        }
+    }
+    if !undated.is_empty() {
+        chapters.push(("日期不详".to_string(), undated));
+    }
+    chapters
+}
+
+struct BookFont {
+    name: String,
+    data: Vec<u8>,
+    index: u32,
+}
+
+// 扫描系统字体较慢，只在首次导出时进行
+fn system_fonts() -> &'static fontdb::Database {
+    static FONTS: OnceLock<fontdb::Database> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        db
+    })
+}
+
+// 在系统字体中挑选覆盖 text 中字符最多的字体，全部覆盖时立即选用
+fn find_font(text: &str) -> Result<BookFont, String> {
+    let chars: BTreeSet<char> = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let db = system_fonts();
+
+    let preferred = PREFERRED_FONTS.iter().filter_map(|family| {
+        db.query(&Query {
+            families: &[Family::Name(family)],
+            weight: Weight::NORMAL,
+            ..Query::default()
+        })
+    });
+    let others = db
+        .faces()
+        .filter(|f| f.style == Style::Normal && f.weight == Weight::NORMAL)
+        .map(|f| f.id);
+    let mut best = None;
+    let mut best_covered = 0;
+    for id in preferred.chain(others) {
+        let covered = db
+            .with_face_data(id, |data, index| {
+                let face = Face::parse(data, index).ok()?;
+                // CFF2 字形须转换后才能嵌入 PDF 1.7，不予采用
+                if face.raw_face().table(CFF2).is_some() {
+                    return None;
+                }
+                Some(
+                    chars
+                        .iter()
+                        .filter(|c| face.glyph_index(**c).is_some())
+                        .count(),
+                )
+            })
+            .flatten()
+            .unwrap_or(0);
+        if covered > best_covered {
+            best_covered = covered;
+            best = Some(id);
+            if covered == chars.len() {
+                break;
+            }
+        }
+    }
+
+    let id = best.ok_or_else(|| "系统中没有可用于导出 PDF 的字体".to_string())?;
+    let name = db
+        .face(id)
+        .map(|f| match f.families.first() {
+            Some((family, _)) => family.clone(),
+            None => f.post_script_name.clone(),
+        })
+        .unwrap_or_default();
+    let (data, index) = db
+        .with_face_data(id, |data, index| (data.to_vec(), index))
+        .ok_or_else(|| "无法读取字体".to_string())?;
+    Ok(BookFont { name, data, index })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+}
+
+struct TextStyle {
+    size: f32,
+    leading: f32,
+    gray: f32,
+    align: Align,
+}
+
+const COVER_TITLE: TextStyle = TextStyle {
+    size: 26.0,
+    leading: 1.4,
+    gray: 0.1,
+    align: Align::Center,
+};
+const COVER_LINE: TextStyle = TextStyle {
+    size: 11.0,
+    leading: 1.8,
+    gray: 0.4,
+    align: Align::Center,
+};
+const CHAPTER: TextStyle = TextStyle {
+    size: 20.0,
+    leading: 1.4,
+    gray: 0.1,
+    align: Align::Center,
+};
+const HEADING: TextStyle = TextStyle {
+    size: 14.0,
+    leading: 1.5,
+    gray: 0.1,
+    align: Align::Left,
+};
+const META: TextStyle = TextStyle {
+    size: 8.5,
+    leading: 1.6,
+    gray: 0.45,
+    align: Align::Left,
+};
+const BODY: TextStyle = TextStyle {
+    size: 10.5,
+    leading: 1.75,
+    gray: 0.15,
+    align: Align::Left,
+};
+const SEALED: TextStyle = TextStyle {
+    size: 10.5,
+    leading: 1.75,
+    gray: 0.45,
+    align: Align::Left,
+};
+const PAGE_NUMBER: TextStyle = TextStyle {
+    size: 8.5,
+    leading: 1.0,
+    gray: 0.45,
+    align: Align::Center,
+};
+
+// 排好的字形：advance 以 em 为单位
+struct Glyph {
+    cid: u16,
+    advance: f32,
+    ch: char,
+}
+
+// 页面上的内容，坐标以页面左下角为原点
+enum Item {
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        gray: f32,
+        cids: Vec<u16>,
+    },
+    Image {
+        index: usize,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+}
+
+// 逐行排版：按字宽折行，放不下时另起一页
+struct Layout<'a> {
+    face: &'a Face<'a>,
+    is_cff: bool,
+    pages: Vec<Vec<Item>>,
+    // 当前页已排到的位置，自页面顶端向下
+    cursor: f32,
+    // 用到的字形 -> (CID, 字符)，用于字体子集、字宽表与 ToUnicode 表
+    used: BTreeMap<u16, (u16, char)>,
+    missing: BTreeSet<char>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(face: &'a Face<'a>) -> Self {
+        Self {
+            face,
+            is_cff: face.raw_face().table(CFF).is_some(),
+            pages: Vec::new(),
+            cursor: MARGIN,
+            used: BTreeMap::new(),
+            missing: BTreeSet::new(),
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.cursor = MARGIN;
+    }
+
+    fn fits(&self, height: f32) -> bool {
+        self.cursor + height <= PAGE_HEIGHT - MARGIN
+    }
+
+    fn space(&mut self, height: f32) {
+        self.cursor += height;
+    }
+
+    fn text(&mut self, text: &str, style: &TextStyle) {
+        let glyphs = self.shape(text);
+        let line_height = style.size * style.leading;
+        for range in wrap(&glyphs, CONTENT_WIDTH / style.size) {
+            let line = &glyphs[range];
+            if !self.fits(line_height) {
+                self.new_page();
+            }
+            let width: f32 = line.iter().map(|g| g.advance * style.size).sum();
+            let x = match style.align {
+                Align::Left => MARGIN,
+                Align::Center => MARGIN + (CONTENT_WIDTH - width) / 2.0,
+            };
+            // 基线位于行高的中部偏下，使字身在行内居中
+            let baseline = self.cursor + (line_height + style.size * 0.7) / 2.0;
+            self.place(Item::Text {
+                x,
+                y: PAGE_HEIGHT - baseline,
+                size: style.size,
+                gray: style.gray,
+                cids: line.iter().map(|g| g.cid).collect(),
+            });
+            self.cursor += line_height;
+        }
+    }
+
+    fn image(&mut self, index: usize, image: &BookImage) {
+        let natural = image.width as f32 * 72.0 / IMAGE_DPI;
+        let mut width = natural.min(CONTENT_WIDTH);
+        let mut height = width * image.height as f32 / image.width.max(1) as f32;
+        if height > IMAGE_MAX_HEIGHT {
+            width *= IMAGE_MAX_HEIGHT / height;
+            height = IMAGE_MAX_HEIGHT;
+        }
+        self.space(6.0);
+        if !self.fits(height) {
+            self.new_page();
+        }
+        self.place(Item::Image {
+            index,
+            x: MARGIN + (CONTENT_WIDTH - width) / 2.0,
+            y: PAGE_HEIGHT - self.cursor - height,
+            width,
+            height,
+        });
+        self.cursor += height + 6.0;
+    }
+
+    // 封面之后的每一页在页脚居中印上页码
+    fn number_pages(&mut self) {
+        for index in 1..self.pages.len() {
+            let glyphs = self.shape(&index.to_string());
+            let width: f32 = glyphs.iter().map(|g| g.advance * PAGE_NUMBER.size).sum();
+            self.pages[index].push(Item::Text {
+                x: (PAGE_WIDTH - width) / 2.0,
+                y: MARGIN / 2.0,
+                size: PAGE_NUMBER.size,
+                gray: PAGE_NUMBER.gray,
+                cids: glyphs.iter().map(|g| g.cid).collect(),
+            });
+        }
+    }
+
+    fn place(&mut self, item: Item) {
+        if self.pages.is_empty() {
+            self.new_page();
+        }
+        if let Some(page) = self.pages.last_mut() {
+            page.push(item);
+        }
+    }
+
+    // 逐字取字形，字体中没有的字符略去并记下
+    fn shape(&mut self, text: &str) -> Vec<Glyph> {
+        let units = f32::from(self.face.units_per_em());
+        let mut glyphs = Vec::new();
+        for ch in text.chars().map(|c| if c == '\t' { ' ' } else { c }) {
+            if ch.is_control() {
+                continue;
+            }
+            let Some(id) = self.face.glyph_index(ch) else {
+                if !ch.is_whitespace() {
+                    self.missing.insert(ch);
+                }
+                continue;
+            };
+            let cid = match self.is_cff {
+                true => self
+                    .face
+                    .tables()
+                    .cff
+                    .and_then(|cff| cff.glyph_cid(id))
+                    .unwrap_or(id.0),
+                false => id.0,
+            };
+            self.used.entry(id.0).or_insert((cid, ch));
+            glyphs.push(Glyph {
+                cid,
+                advance: f32::from(self.face.glyph_hor_advance(id).unwrap_or(0)) / units,
+                ch,
+            });
+        }
+        glyphs
+    }
+}
+
+// 贪心折行，width 以 em 为单位。中日文字符之间可随处断行，西文在空白处断行，
+// 过长的单词强行截断；行首行尾的空白略去
+fn wrap(glyphs: &[Glyph], width: f32) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    loop {
+        while start < glyphs.len() && glyphs[start].ch.is_whitespace() {
+            start += 1;
+        }
+        if start == glyphs.len() {
+            return lines;
+        }
+        let mut end = start;
+        let mut used = 0.0;
+        let mut last_break = None;
+        while end < glyphs.len() {
+            if end > start && can_break(glyphs[end - 1].ch, glyphs[end].ch) {
+                last_break = Some(end);
+            }
+            used += glyphs[end].advance;
+            if used > width && end > start {
+                end = last_break.unwrap_or(end);
+                break;
+            }
+            end += 1;
+        }
+        let mut trimmed = end;
+        while trimmed > start && glyphs[trimmed - 1].ch.is_whitespace() {
+            trimmed -= 1;
+        }
+        lines.push(start..trimmed);
+        start = end;
+    }
+}
+
+// 避头尾：闭合标点不出现在行首，开启标点不出现在行尾
+fn can_break(previous: char, next: char) -> bool {
+    if "，。、；：！？）」』》〉】〕”’…,.;:!?)]}".contains(next)
+        || "（「『《〈【〔“‘([{".contains(previous)
+    {
+        return false;
+    }
+    previous.is_whitespace() || is_wide(previous) || is_wide(next)
+}
+
+// 中日韩文字与全角符号
+fn is_wide(c: char) -> bool {
+    c >= '\u{2E80}'
+}
+
+fn write_pdf(
+    title: &str,
+    font: &BookFont,
+    face: &Face,
+    layout: &Layout,
+    images: &[&BookImage],
+    outline: &[(String, usize)],
+) -> Result<Vec<u8>, String> {
+    let mut next = Ref::new(1);
+    let mut alloc = || next.bump();
+    let catalog_id = alloc();
+    let tree_id = alloc();
+    let info_id = alloc();
+    let outline_id = alloc();
+    let font_id = alloc();
+    let cid_font_id = alloc();
+    let descriptor_id = alloc();
+    let cmap_id = alloc();
+    let program_id = alloc();
+    let image_ids: Vec<Ref> = images.iter().map(|_| alloc()).collect();
+    let page_ids: Vec<(Ref, Ref)> = layout.pages.iter().map(|_| (alloc(), alloc())).collect();
+    let outline_ids: Vec<Ref> = outline.iter().map(|_| alloc()).collect();
+
+    let mut pdf = Pdf::new();
+    let mut catalog = pdf.catalog(catalog_id);
+    catalog.pages(tree_id);
+    if !outline.is_empty() {
+        catalog.outlines(outline_id);
+    }
+    catalog.finish();
+    pdf.document_info(info_id)
+        .title(TextStr(title))
+        .producer(TextStr(DEFAULT_TITLE));
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().map(|(page, _)| *page))
+        .count(page_ids.len() as i32);
+
+    for ((page_id, content_id), items) in page_ids.iter().zip(&layout.pages) {
+        let mut content = Content::new();
+        let mut used_images = BTreeSet::new();
+        for item in items {
+            match item {
+                Item::Text {
+                    x,
+                    y,
+                    size,
+                    gray,
+                    cids,
+                } => {
+                    let bytes: Vec<u8> = cids.iter().flat_map(|cid| cid.to_be_bytes()).collect();
+                    content
+                        .begin_text()
+                        .set_font(FONT_NAME, *size)
+                        .set_fill_gray(*gray)
+                        .next_line(*x, *y)
+                        .show(Str(&bytes))
+                        .end_text();
+                }
+                Item::Image {
+                    index,
+                    x,
+                    y,
+                    width,
+                    height,
+                } => {
+                    used_images.insert(*index);
+                    content
+                        .save_state()
+                        .transform([*width, 0.0, 0.0, *height, *x, *y])
+                        .x_object(Name(image_name(*index).as_bytes()))
+                        .restore_state();
+                }
+            }
+        }
+
+        let mut page = pdf.page(*page_id);
+        page.parent(tree_id)
+            .media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .contents(*content_id);
+        let mut resources = page.resources();
+        resources.fonts().pair(FONT_NAME, font_id);
+        let names: Vec<String> = used_images.iter().map(|i| image_name(*i)).collect();
+        let mut x_objects = resources.x_objects();
+        for (index, name) in used_images.iter().zip(&names) {
+            x_objects.pair(Name(name.as_bytes()), image_ids[*index]);
+        }
+        x_objects.finish();
+        resources.finish();
+        page.finish();
+        pdf.stream(*content_id, &compress_to_vec_zlib(&content.finish(), 6))
+            .filter(Filter::FlateDecode);
+    }
+
+    for (image, id) in images.iter().zip(&image_ids) {
+        let mut xobject = pdf.image_xobject(*id, &image.jpeg);
+        xobject.filter(Filter::DctDecode);
+        xobject
+            .width(image.width as i32)
+            .height(image.height as i32)
+            .bits_per_component(8);
+        xobject.color_space().device_rgb();
+        xobject.finish();
+    }
+
+    write_font(
+        &mut pdf,
+        [font_id, cid_font_id, descriptor_id, cmap_id, program_id],
+        font,
+        face,
+        layout,
+    )?;
+
+    if let (Some(first), Some(last)) = (outline_ids.first(), outline_ids.last()) {
+        pdf.outline(outline_id)
+            .first(*first)
+            .last(*last)
+            .count(outline_ids.len() as i32);
+        for (index, (heading, page)) in outline.iter().enumerate() {
+            let mut item = pdf.outline_item(outline_ids[index]);
+            item.title(TextStr(heading)).parent(outline_id);
+            if index > 0 {
+                item.prev(outline_ids[index - 1]);
+            }
+            if let Some(next) = outline_ids.get(index + 1) {
+                item.next(*next);
+            }
+            item.dest()
+                .page(page_ids[*page].0)
+                .xyz(0.0, PAGE_HEIGHT, None);
+        }
+    }
+    Ok(pdf.finish())
+}
+
+// 以 Type0 / CID 字体嵌入只含用到字形的字体子集，ToUnicode 表使文字可以复制与检索
+fn write_font(
+    pdf: &mut Pdf,
+    [font_id, cid_font_id, descriptor_id, cmap_id, program_id]: [Ref; 5],
+    font: &BookFont,
+    face: &Face,
+    layout: &Layout,
+) -> Result<(), String> {
+    let glyphs: Vec<u16> = layout.used.keys().copied().collect();
+    let subset = subsetter::subset(&font.data, font.index, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| format!("无法嵌入字体 {}: {}", font.name, e))?;
+    // CFF 字形只嵌入 CFF 表本身
+    let program = match layout.is_cff {
+        true => RawFace::parse(&subset, 0)
+            .ok()
+            .and_then(|raw| raw.table(CFF))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| format!("无法嵌入字体 {}", font.name))?,
+        false => subset,
+    };
+
+    let postscript_name = face
+        .names()
+        .into_iter()
+        .find(|n| n.name_id == name_id::POST_SCRIPT_NAME)
+        .and_then(|n| n.to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "Font".to_string());
+    // 子集字体名以 6 个大写字母为前缀，由所含字形决定
+    let tag: String = {
+        let mut hash = glyphs.iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, g| {
+            (h ^ u64::from(*g)).wrapping_mul(0x100_0000_01b3)
+        });
+        (0..6)
+            .map(|_| {
+                let letter = (b'A' + (hash % 26) as u8) as char;
+                hash /= 26;
+                letter
+            })
+            .collect()
+    };
+    let base_font = format!("{}+{}", tag, postscript_name);
+    let type0_name = match layout.is_cff {
+        true => format!("{}-Identity-H", base_font),
+        false => base_font.clone(),
+    };
+
+    pdf.type0_font(font_id)
+        .base_font(Name(type0_name.as_bytes()))
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_font_id)
+        .to_unicode(cmap_id);
+
+    let units = f32::from(face.units_per_em());
+    let to_font_units = |value: f32| value * 1000.0 / units;
+    let mut cid = pdf.cid_font(cid_font_id);
+    cid.subtype(match layout.is_cff {
+        true => CidFontType::Type0,
+        false => CidFontType::Type2,
+    })
+    .base_font(Name(base_font.as_bytes()))
+    .system_info(SYSTEM_INFO)
+    .font_descriptor(descriptor_id)
+    .default_width(0.0);
+    if !layout.is_cff {
+        cid.cid_to_gid_map_predefined(Name(b"Identity"));
+    }
+    let mut widths = cid.widths();
+    for (glyph, (cid, _)) in &layout.used {
+        let advance = face.glyph_hor_advance(GlyphId(*glyph)).unwrap_or(0);
+        widths.consecutive(*cid, [to_font_units(f32::from(advance))]);
+    }
+    widths.finish();
+    cid.finish();
+
+    let bbox = face.global_bounding_box();
+    let mut flags = FontFlags::SYMBOLIC;
+    flags.set(FontFlags::SERIF, postscript_name.contains("Serif"));
+    pdf.font_descriptor(descriptor_id)
+        .name(Name(base_font.as_bytes()))
+        .flags(flags)
+        .bbox(Rect::new(
+            to_font_units(f32::from(bbox.x_min)),
+            to_font_units(f32::from(bbox.y_min)),
+            to_font_units(f32::from(bbox.x_max)),
+            to_font_units(f32::from(bbox.y_max)),
+        ))
+        .italic_angle(face.italic_angle())
+        .ascent(to_font_units(f32::from(face.ascender())))
+        .descent(to_font_units(f32::from(face.descender())))
+        .cap_height(to_font_units(f32::from(
+            face.capital_height().unwrap_or(face.ascender()),
+        )))
+        .stem_v(80.0)
+        .pair(
+            Name(match layout.is_cff {
+                true => b"FontFile3",
+                false => b"FontFile2",
+            }),
+            program_id,
+        );
+
+    let mut cmap = UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+    for (cid, ch) in layout.used.values() {
+        cmap.pair(*cid, *ch);
+    }
+    pdf.cmap(cmap_id, &compress_to_vec_zlib(&cmap.finish(), 6))
+        .filter(Filter::FlateDecode);
+
+    let program = compress_to_vec_zlib(&program, 6);
+    let mut stream = pdf.stream(program_id, &program);
+    stream.filter(Filter::FlateDecode);
+    if layout.is_cff {
+        stream.pair(Name(b"Subtype"), Name(b"CIDFontType0C"));
+    }
+    stream.finish();
+    Ok(())
+}
+
+fn image_name(index: usize) -> String {
+    format!("Im{}", index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 中日文字符宽 1 em，其余 0.5 em
+    fn glyphs(text: &str) -> Vec<Glyph> {
+        text.chars()
+            .map(|ch| Glyph {
+                cid: 0,
+                advance: if is_wide(ch) { 1.0 } else { 0.5 },
+                ch,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn breaks_between_cjk_characters() {
+        assert_eq!(wrap(&glyphs("一二三四五六"), 4.0), vec![0..4, 4..6]);
+    }
+
+    #[test]
+    fn keeps_closing_punctuation_off_the_line_start() {
+        assert_eq!(wrap(&glyphs("一二三，四"), 3.0), vec![0..2, 2..5]);
+        assert_eq!(wrap(&glyphs("一二（三"), 3.0), vec![0..2, 2..4]);
+    }
+
+    #[test]
+    fn breaks_western_text_at_spaces() {
+        assert_eq!(wrap(&glyphs("hello world"), 4.0), vec![0..5, 6..11]);
+    }
+
+    #[test]
+    fn cuts_words_longer_than_a_line() {
+        assert_eq!(wrap(&glyphs("abcdefghij"), 2.0), vec![0..4, 4..8, 8..10]);
+    }
+
+    #[test]
+    fn empty_and_blank_paragraphs_produce_no_lines() {
+        assert!(wrap(&glyphs(""), 4.0).is_empty());
+        assert!(wrap(&glyphs("   "), 4.0).is_empty());
+        assert_eq!(wrap(&glyphs("  一  "), 4.0), vec![2..3]);
+    }
+
+    #[test]
+    fn break_opportunities() {
+        assert!(can_break('中', '文'));
+        assert!(can_break('a', '中'));
+        assert!(can_break(' ', 'a'));
+        assert!(!can_break('a', 'b'));
+        assert!(!can_break('中', '。'));
+        assert!(!can_break('“', '中'));
+        assert!(!can_break('(', 'a'));
+    }
+}
@@ -0,0 +1,508 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use percent_encoding::percent_decode_str;
+use tauri::ipc::{InvokeBody, Request};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::archive::{
+    self, ArchiveReader, ExportOptions, ExportSummary, ImportPreview, ImportReport, ImportStrategy,
+};
+use crate::background::SchedulerWaker;
+use crate::backup::{self, BackupInfo, RestoreReport};
+use crate::book::{BookOptions, BookSummary};
+use crate::document::{DocumentOptions, DocumentSummary};
+use crate::history::{Revision, RevisionDiff, RevisionSummary};
+use crate::importer::{self, Bundle, JournalImportOptions, JournalImportReport, JournalPreview};
+use crate::insights::{InsightRange, Insights};
+use crate::media::MediaKind;
+use crate::models::{
+    CreateRecordInput, EmotionalRecord, RecordFilter, SealConfig, TagSummary, TrashedRecord,
+    UpdateRecordInput,
+};
+use crate::paths::AppPaths;
+use crate::resurface::OnThisDay;
+use crate::schema;
+use crate::search::{self, SearchHit};
+use crate::session::{Session, VaultStatus};
+use crate::settings::{Settings, SettingsStore};
+use crate::thumbnail::{self, ThumbnailInfo};
+
+// import_media 的请求头
+const MEDIA_KIND_HEADER: &str = "x-media-kind";
+const FILE_NAME_HEADER: &str = "x-file-name";
+
+// 记录 CRUD 命令
+#[tauri::command]
+pub fn list_records(
+    session: State<'_, Session>,
+    filter: Option<RecordFilter>,
+) -> Result<Vec<EmotionalRecord>, String> {
+    session.with_store(|store| store.list(&filter.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub fn get_record(
+    session: State<'_, Session>,
+    id: String,
+) -> Result<Option<EmotionalRecord>, String> {
+    session.with_store(|store| store.get(&id))
+}
+
+#[tauri::command]
+pub fn create_record(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    input: CreateRecordInput,
+) -> Result<EmotionalRecord, String> {
+    let settings = settings.get()?;
+    session.with_store(|store| store.create(input, &settings))
+}
+
+#[tauri::command]
+pub fn update_record(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    input: UpdateRecordInput,
+) -> Result<EmotionalRecord, String> {
+    let settings = settings.get()?;
+    session.with_store(|store| store.update(input, &settings))
+}
+
+// 删除的记录先移入回收站，保留期满后由后台彻底删除
+#[tauri::command]
+pub fn delete_record(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    id: String,
+) -> Result<(), String> {
+    let settings = settings.get()?;
+    session.with_store(|store| store.delete(&id, &settings))
+}
+
+// 回收站命令
+#[tauri::command]
+pub fn list_trash(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+) -> Result<Vec<TrashedRecord>, String> {
+    let settings = settings.get()?;
+    session.with_store(|store| store.list_trash(&settings))
+}
+
+// 恢复的记录可能带有解封与销毁时间，须重新规划调度
+#[tauri::command]
+pub fn restore_record(
+    session: State<'_, Session>,
+    waker: State<'_, SchedulerWaker>,
+    id: String,
+) -> Result<EmotionalRecord, String> {
+    let restored = session.with_store(|store| store.restore_record(&id))?;
+    waker.wake();
+    Ok(restored)
+}
+
+// 未指定 ids 时清空整个回收站，返回彻底删除的条数
+#[tauri::command]
+pub fn empty_trash(session: State<'_, Session>, ids: Option<Vec<String>>) -> Result<usize, String> {
+    session.with_store(|store| store.empty_trash(ids.as_deref()))
+}
+
+// 历史版本命令，尘封中的记录不可查看
+#[tauri::command]
+pub fn list_revisions(
+    session: State<'_, Session>,
+    id: String,
+) -> Result<Vec<RevisionSummary>, String> {
+    session.with_store(|store| store.list_revisions(&id))
+}
+
+#[tauri::command]
+pub fn get_revision(
+    session: State<'_, Session>,
+    id: String,
+    number: u32,
+) -> Result<Revision, String> {
+    session.with_store(|store| store.get_revision(&id, number))
+}
+
+// from / to 缺省时为记录的当前内容
+#[tauri::command]
+pub fn diff_revisions(
+    session: State<'_, Session>,
+    id: String,
+    from: Option<u32>,
+    to: Option<u32>,
+) -> Result<RevisionDiff, String> {
+    session.with_store(|store| store.diff_revisions(&id, from, to))
+}
+
+#[tauri::command]
+pub fn restore_revision(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    id: String,
+    number: u32,
+) -> Result<EmotionalRecord, String> {
+    let settings = settings.get()?;
+    session.with_store(|store| store.restore_revision(&id, number, &settings))
+}
+
+// 在未尘封记录的标题与正文中全文检索，limit 缺省为 50
+#[tauri::command]
+pub fn search_records(
+    session: State<'_, Session>,
+    query: String,
+    limit: Option<usize>,
+    filter: Option<RecordFilter>,
+) -> Result<Vec<SearchHit>, String> {
+    let limit = limit.unwrap_or(search::DEFAULT_LIMIT);
+    session.with_store(|store| store.search(&query, limit, &filter.unwrap_or_default()))
+}
+
+// 统计命令，range 缺省时统计全部记录并按天分桶
+#[tauri::command]
+pub fn get_insights(
+    session: State<'_, Session>,
+    range: Option<InsightRange>,
+) -> Result<Insights, String> {
+    session.with_store(|store| store.insights(&range.unwrap_or_default()))
+}
+
+// “那年今日”，date 为本地日期 YYYY-MM-DD，缺省为今天
+#[tauri::command]
+pub fn get_on_this_day(
+    session: State<'_, Session>,
+    date: Option<NaiveDate>,
+) -> Result<Vec<OnThisDay>, String> {
+    session.with_store(|store| store.on_this_day(date))
+}
+
+// 标签命令
+#[tauri::command]
+pub fn list_tags(session: State<'_, Session>) -> Result<Vec<TagSummary>, String> {
+    session.with_store(|store| Ok(store.list_tags()))
+}
+
+#[tauri::command]
+pub fn add_tag(
+    session: State<'_, Session>,
+    ids: Vec<String>,
+    tag: String,
+) -> Result<Vec<EmotionalRecord>, String> {
+    session.with_store(|store| store.add_tag(&ids, &tag))
+}
+
+// 未指定 ids 时从全部记录中移除该标签
+#[tauri::command]
+pub fn remove_tag(
+    session: State<'_, Session>,
+    ids: Option<Vec<String>>,
+    tag: String,
+) -> Result<Vec<EmotionalRecord>, String> {
+    session.with_store(|store| store.remove_tag(ids.as_deref(), &tag))
+}
+
+#[tauri::command]
+pub fn rename_tag(
+    session: State<'_, Session>,
+    from: String,
+    to: String,
+) -> Result<Vec<EmotionalRecord>, String> {
+    session.with_store(|store| store.rename_tag(&from, &to))
+}
+
+// 前端启动时传入旧版 localStorage 中的记录，只在第一次调用时导入，返回导入条数
+#[tauri::command]
+pub fn import_legacy_records(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    records: Vec<serde_json::Value>,
+) -> Result<usize, String> {
+    // localStorage 中是未带版本的旧版前端记录
+    let records = schema::upgrade_records(records, 0)?;
+    let settings = settings.get()?;
+    session.with_store(|store| store.import_local_storage(records, &settings))
+}
+
+// 尘封命令：内容在解封时间到达前由后端加密保管
+#[tauri::command]
+pub fn seal_record(
+    session: State<'_, Session>,
+    waker: State<'_, SchedulerWaker>,
+    id: String,
+    config: SealConfig,
+) -> Result<EmotionalRecord, String> {
+    let sealed = session.with_store(|store| store.seal(&id, config))?;
+    waker.wake();
+    Ok(sealed)
+}
+
+#[tauri::command]
+pub fn unseal_record(session: State<'_, Session>, id: String) -> Result<EmotionalRecord, String> {
+    session.with_store(|store| store.unseal(&id))
+}
+
+// 保险库命令：口令派生密钥在后台线程且不持有仓库锁的情况下计算，避免阻塞主线程与其他命令
+#[tauri::command]
+pub fn get_vault_status(session: State<'_, Session>) -> Result<VaultStatus, String> {
+    session.status()
+}
+
+#[tauri::command]
+pub async fn unlock(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    waker: State<'_, SchedulerWaker>,
+    passphrase: String,
+) -> Result<(), String> {
+    let settings = settings.get()?;
+    session.unlock(&passphrase, &settings)?;
+    waker.wake();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn lock(session: State<'_, Session>) -> Result<(), String> {
+    session.lock()
+}
+
+#[tauri::command]
+pub async fn change_passphrase(
+    session: State<'_, Session>,
+    old: String,
+    new: String,
+) -> Result<(), String> {
+    session.change_passphrase(&old, &new)
+}
+
+// 媒体命令：返回写入记录 images / musicUrl 的媒体引用。
+// 文件内容作为二进制请求体发送，避免序列化为 JSON 数组；类型与文件名放在请求头中，
+// 文件名须经 encodeURIComponent 编码
+#[tauri::command]
+pub async fn import_media(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    request: Request<'_>,
+) -> Result<String, String> {
+    let data = match request.body() {
+        InvokeBody::Raw(data) => data,
+        InvokeBody::Json(_) => return Err("媒体内容须以二进制请求体发送".to_string()),
+    };
+    let kind = match header(&request, MEDIA_KIND_HEADER)?.as_str() {
+        "image" => MediaKind::Image,
+        "music" => MediaKind::Music,
+        other => return Err(format!("不支持的媒体类型: {}", other)),
+    };
+    let file_name = header(&request, FILE_NAME_HEADER)?;
+    let settings = settings.get()?;
+    session.with_store(|store| store.ingest_media(kind, data, &file_name, &settings))
+}
+
+#[tauri::command]
+pub async fn import_media_file(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    kind: MediaKind,
+    path: String,
+) -> Result<String, String> {
+    let settings = settings.get()?;
+    let data = fs::read(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+    session.with_store(|store| store.ingest_media(kind, &data, &path, &settings))
+}
+
+// 返回缩略图的协议路径、尺寸与模糊占位符，缓存缺失时重新生成
+#[tauri::command]
+pub async fn get_thumbnail(
+    session: State<'_, Session>,
+    image_id: String,
+    size: u32,
+) -> Result<ThumbnailInfo, String> {
+    let size = thumbnail::snap_size(size);
+    session
+        .with_store(|store| store.thumbnail_info(&image_id, size))?
+        .ok_or_else(|| "媒体不可访问".to_string())
+}
+
+// 导出完整归档到用户选择的路径，写入过程中推送 archive-progress 事件
+#[tauri::command]
+pub async fn export_archive(
+    app: AppHandle,
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    options: Option<ExportOptions>,
+) -> Result<ExportSummary, String> {
+    let settings = settings.get()?;
+    let options = options.unwrap_or_default();
+    let export = session.with_store(|store| store.prepare_export(&options, &settings))?;
+    export.write(Path::new(&path), &mut |progress| {
+        let _ = app.emit(archive::PROGRESS_EVENT, progress);
+    })
+}
+
+// 导出可直接阅读的 Markdown 文件夹或 HTML 书
+#[tauri::command]
+pub async fn export_document(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    options: Option<DocumentOptions>,
+) -> Result<DocumentSummary, String> {
+    let settings = settings.get()?;
+    let options = options.unwrap_or_default();
+    let export = session.with_store(|store| store.prepare_document(&options))?;
+    export.write(Path::new(&path), &settings)
+}
+
+// 导出按月分章、带页码的 PDF 纪念册
+#[tauri::command]
+pub async fn export_book(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    options: Option<BookOptions>,
+) -> Result<BookSummary, String> {
+    let settings = settings.get()?;
+    let options = options.unwrap_or_default();
+    let book = session.with_store(|store| store.prepare_book(&options))?;
+    book.write(Path::new(&path), &settings)
+}
+
+// 导入前校验归档并列出新增、更新与冲突的记录
+#[tauri::command]
+pub async fn preview_archive(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<ImportPreview, String> {
+    let settings = settings.get()?;
+    // 口令的密钥派生较慢，在仓库锁外完成
+    let mut reader = ArchiveReader::open(Path::new(&path), passphrase.as_deref())?;
+    session.with_store(|store| store.preview_archive(&mut reader, &settings))
+}
+
+#[tauri::command]
+pub async fn import_archive(
+    app: AppHandle,
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    waker: State<'_, SchedulerWaker>,
+    path: String,
+    strategy: Option<ImportStrategy>,
+    passphrase: Option<String>,
+) -> Result<ImportReport, String> {
+    let settings = settings.get()?;
+    let mut reader = ArchiveReader::open(Path::new(&path), passphrase.as_deref())?;
+    let report = session.with_store(|store| {
+        store.import_archive(
+            &mut reader,
+            strategy.unwrap_or_default(),
+            &settings,
+            &mut |progress| {
+                let _ = app.emit(archive::PROGRESS_EVENT, progress);
+            },
+        )
+    })?;
+    // 导入的记录可能带有新的解封与销毁时间
+    waker.wake();
+    Ok(report)
+}
+
+// 解析 Day One、Journey 或 Markdown 文件夹的导出，列出将导入与重复的日记
+#[tauri::command]
+pub async fn preview_journal(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    options: Option<JournalImportOptions>,
+) -> Result<JournalPreview, String> {
+    let settings = settings.get()?;
+    let options = options.unwrap_or_default();
+    // 解析导出不占用仓库锁
+    let mut bundle = Bundle::open(Path::new(&path))?;
+    let parsed = importer::parse(&mut bundle, options.format, &settings)?;
+    session.with_store(|store| store.preview_journal(&parsed))
+}
+
+#[tauri::command]
+pub async fn import_journal(
+    app: AppHandle,
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    path: String,
+    options: Option<JournalImportOptions>,
+) -> Result<JournalImportReport, String> {
+    let settings = settings.get()?;
+    let options = options.unwrap_or_default();
+    let mut emit = |progress| {
+        let _ = app.emit(archive::PROGRESS_EVENT, progress);
+    };
+    // 解析导出、读取照片、去除元数据与生成缩略图都在锁外完成，只在查重与写入时加锁
+    let mut bundle = Bundle::open(Path::new(&path))?;
+    let parsed = importer::parse(&mut bundle, options.format, &settings)?;
+    let preview = session.with_store(|store| store.preview_journal(&parsed))?;
+    let journal = importer::prepare(&mut bundle, parsed, preview, &options, &settings, &mut emit)?;
+    session.with_store(|store| store.import_journal(journal, &mut emit))
+}
+
+// 列出备份目录中的备份，从新到旧
+#[tauri::command]
+pub async fn list_backups(
+    settings: State<'_, SettingsStore>,
+    paths: State<'_, AppPaths>,
+) -> Result<Vec<BackupInfo>, String> {
+    let settings = settings.get()?;
+    backup::list(&backup::directory(&settings, &paths))
+}
+
+// 用备份替换当前数据，恢复后会话处于锁定状态，须以备份时的口令解锁。
+// 为当前数据另做备份失败时中止，force 为 true 时仍继续恢复
+#[tauri::command]
+pub async fn restore_backup(
+    session: State<'_, Session>,
+    settings: State<'_, SettingsStore>,
+    paths: State<'_, AppPaths>,
+    name: String,
+    force: Option<bool>,
+) -> Result<RestoreReport, String> {
+    let settings = settings.get()?;
+    session.restore_backup(
+        &backup::directory(&settings, &paths),
+        &name,
+        force.unwrap_or(false),
+    )
+}
+
+// 设置命令
+#[tauri::command]
+pub fn get_settings(settings: State<'_, SettingsStore>) -> Result<Settings, String> {
+    settings.get()
+}
+
+#[tauri::command]
+pub fn update_settings(
+    store: State<'_, SettingsStore>,
+    waker: State<'_, SchedulerWaker>,
+    settings: Settings,
+) -> Result<Settings, String> {
+    let updated = store.update(settings)?;
+    // 提醒设置可能变化，让调度线程重新规划
+    waker.wake();
+    Ok(updated)
+}
+
+// 读取并解码必需的请求头
+fn header(request: &Request<'_>, name: &str) -> Result<String, String> {
+    let value = request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| format!("缺少请求头 {}", name))?;
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|v| v.into_owned())
+        .map_err(|_| format!("请求头 {} 编码错误", name))
+}
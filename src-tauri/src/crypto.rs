@@ -0,0 +1,111 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 24;
+// Poly1305 认证标签的长度
+pub const TAG_LEN: usize = 16;
+
+pub type Key = [u8; KEY_LEN];
+
+pub fn generate_key() -> Key {
+    let mut key = [0u8; KEY_LEN];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+// 认证加密，输出格式为 nonce || ciphertext
+pub fn encrypt(key: &Key, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| "加密失败".to_string())?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+pub fn decrypt(key: &Key, data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < NONCE_LEN {
+        return Err("密文已损坏".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "解密失败：密钥错误或数据已损坏".to_string())
+}
+
+// 以调用方给定的 nonce 与附加认证数据加密，输出不含 nonce；调用方须保证 nonce 不重复
+pub fn encrypt_with(
+    key: &Key,
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, String> {
+    XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| "加密失败".to_string())
+}
+
+pub fn decrypt_with(
+    key: &Key,
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, String> {
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| "解密失败：密钥错误或数据已损坏".to_string())
+}
+
+// 便于嵌入 JSON 的 base64 形式
+pub fn encrypt_to_string(key: &Key, plaintext: &[u8]) -> Result<String, String> {
+    encrypt(key, plaintext).map(|data| STANDARD.encode(data))
+}
+
+pub fn decrypt_from_string(key: &Key, data: &str) -> Result<Vec<u8>, String> {
+    let bytes = STANDARD
+        .decode(data)
+        .map_err(|e| format!("密文格式错误: {}", e))?;
+    decrypt(key, &bytes)
+}
+
+// HMAC-SHA256，用于由主密钥派生子密钥以及生成不可逆的查找键
+pub fn keyed_hash(key: &Key, data: &[u8]) -> [u8; 32] {
+    const BLOCK_LEN: usize = 64;
+    let mut inner = Sha256::new();
+    let mut outer = Sha256::new();
+    let mut ipad = [0x36u8; BLOCK_LEN];
+    let mut opad = [0x5cu8; BLOCK_LEN];
+    for (i, byte) in key.iter().enumerate() {
+        ipad[i] ^= byte;
+        opad[i] ^= byte;
+    }
+    inner.update(ipad);
+    inner.update(data);
+    outer.update(opad);
+    outer.update(inner.finalize());
+    ipad.zeroize();
+    opad.zeroize();
+    outer.finalize().into()
+}
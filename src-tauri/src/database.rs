@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, OptionalExtension, Transaction};
+
+use crate::history::{Retention, StoredRevision};
+use crate::keyring::StoredRecord;
+use crate::tags::StoredTag;
+
+// 按顺序执行的迁移脚本，下标 + 1 即迁移后的 user_version。已发布的脚本不可修改，只能追加
+const MIGRATIONS: &[&str] = &[
+    // 1：记录、尘封与媒体
+    "
+    CREATE TABLE records (
+        id TEXT PRIMARY KEY,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        auto_destroy_at TEXT,
+        -- 以记录的数据密钥加密的内容
+        body TEXT NOT NULL
+    );
+    CREATE TABLE seals (
+        record_id TEXT PRIMARY KEY REFERENCES records(id) ON DELETE CASCADE,
+        seal_until TEXT
+    );
+    CREATE TABLE media (
+        media_ref TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX idx_records_auto_destroy_at ON records(auto_destroy_at);
+    CREATE INDEX idx_seals_seal_until ON seals(seal_until);
+    ",
+    // 2：自动销毁的时间，只用于统计，不保留任何记录信息
+    "
+    CREATE TABLE destructions (
+        destroyed_at TEXT NOT NULL
+    );
+    ",
+    // 3：标签。标签名以密钥环中该标签的密钥加密，lookup 为带密钥的哈希，用于去重与按标签查询
+    "
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        lookup TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL
+    );
+    CREATE TABLE record_tags (
+        record_id TEXT NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (record_id, tag_id)
+    );
+    ",
+    // 4：历史版本，以记录的数据密钥加密，随记录级联删除
+    "
+    CREATE TABLE revisions (
+        id INTEGER PRIMARY KEY,
+        record_id TEXT NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+        number INTEGER NOT NULL,
+        saved_at TEXT NOT NULL,
+        replaced_at TEXT NOT NULL,
+        body TEXT NOT NULL,
+        UNIQUE (record_id, number)
+    );
+    ",
+    // 5：回收站，记录本身仍在 records 表中，彻底删除前可以恢复
+    "
+    CREATE TABLE trash (
+        record_id TEXT PRIMARY KEY REFERENCES records(id) ON DELETE CASCADE,
+        deleted_at TEXT NOT NULL
+    );
+    ",
+];
+
+// 一次归档导入对数据库的改动。写入的记录同时移出回收站
+pub struct ImportBatch<'a> {
+    pub records: &'a [StoredRecord],
+    pub revisions: &'a [StoredRevision],
+    // 内容被尘封版本替换（或原本尘封）的记录，旧的差异链已无法还原，整段删除
+    pub cleared_history: &'a [String],
+    pub trashed: &'a [String],
+    pub deleted_at: &'a str,
+}
+
+// 内嵌 SQLite 数据库 records.db。以 WAL 模式写入，每次修改都是一个事务，崩溃后由 SQLite 自动恢复；
+// 每次成功打开与关闭时另存一份快照 records.db.bak，数据库文件损坏时据此恢复
+pub struct Database {
+    conn: Connection,
+    backup: PathBuf,
+    // 从快照恢复时，损坏的文件改名保留在此处
+    corrupt_copy: Option<PathBuf>,
+}
+
+// 打开失败的原因，只有文件损坏时才用快照覆盖
+enum OpenError {
+    Corrupt(String),
+    Other(String),
+}
+
+impl OpenError {
+    fn message(self) -> String {
+        match self {
+            Self::Corrupt(message) | Self::Other(message) => message,
+        }
+    }
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let backup = backup_path(path);
+        let (mut conn, corrupt_copy) = match open_checked(path) {
+            Ok(conn) => (conn, None),
+            // 文件被占用、无权限等错误不属于损坏，不能用快照覆盖
+            Err(OpenError::Corrupt(message)) if backup.exists() => {
+                let corrupt_copy = move_aside(path)?;
+                fs::copy(&backup, path).map_err(|e| format!("从备份恢复数据库失败: {}", e))?;
+                let conn = open_checked(path)
+                    .map_err(|e| format!("{}，备份也无法打开: {}", message, e.message()))?;
+                (conn, Some(corrupt_copy))
+            }
+            Err(e) => return Err(e.message()),
+        };
+        // 版本过新同样不属于损坏
+        migrate(&mut conn)?;
+
+        let database = Self {
+            conn,
+            backup,
+            corrupt_copy,
+        };
+        if database.corrupt_copy.is_none() {
+            database.snapshot()?;
+        }
+        Ok(database)
+    }
+
+    // 本次打开时数据库文件已损坏，数据来自快照
+    pub fn recovered(&self) -> bool {
+        self.corrupt_copy.is_some()
+    }
+
+    // 改名保留的损坏文件，供用户自行处理
+    pub fn corrupt_copy(&self) -> Option<&Path> {
+        self.corrupt_copy.as_deref()
+    }
+
+    // 按写入顺序读出全部记录
+    pub fn load_records(&self) -> Result<Vec<StoredRecord>, String> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT r.id, r.created_at, r.updated_at, r.auto_destroy_at, r.body,
+                        s.record_id IS NOT NULL, s.seal_until, t.deleted_at
+                 FROM records r
+                 LEFT JOIN seals s ON s.record_id = r.id
+                 LEFT JOIN trash t ON t.record_id = r.id
+                 ORDER BY r.rowid",
+            )
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(StoredRecord {
+                    id: row.get(0)?,
+                    created_at: row.get(1)?,
+                    updated_at: row.get(2)?,
+                    auto_destroy_at: row.get(3)?,
+                    body: row.get(4)?,
+                    is_sealed: row.get(5)?,
+                    seal_until: row.get(6)?,
+                    tags: Vec::new(),
+                    deleted_at: row.get(7)?,
+                })
+            })
+            .map_err(query_error)?;
+        let mut records = rows.collect::<Result<Vec<_>, _>>().map_err(query_error)?;
+
+        // 按添加顺序附上每条记录的标签
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT rt.record_id, t.lookup, t.name
+                 FROM record_tags rt JOIN tags t ON t.id = rt.tag_id
+                 ORDER BY rt.rowid",
+            )
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    StoredTag {
+                        lookup: row.get(1)?,
+                        name: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(query_error)?;
+        let mut tags: HashMap<String, Vec<StoredTag>> = HashMap::new();
+        for row in rows {
+            let (record_id, tag) = row.map_err(query_error)?;
+            tags.entry(record_id).or_default().push(tag);
+        }
+        for record in &mut records {
+            record.tags = tags.remove(&record.id).unwrap_or_default();
+        }
+        Ok(records)
+    }
+
+    // 在一个事务中写入或更新多条记录
+    pub fn save_records(&mut self, records: &[StoredRecord]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for record in records {
+            upsert(&tx, record).map_err(write_error)?;
+            replace_tags(&tx, record).map_err(write_error)?;
+        }
+        remove_unused_tags(&tx).map_err(write_error)?;
+        tx.commit().map_err(write_error)
+    }
+
+    // 写入修改后的记录及其上一版本，并按保留策略清理历史。
+    // 二者须在同一事务中完成，否则差异链的起点与记录的当前内容不一致
+    pub fn save_revision(
+        &mut self,
+        record: &StoredRecord,
+        revision: &StoredRevision,
+        retention: &Retention,
+    ) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        upsert(&tx, record).map_err(write_error)?;
+        replace_tags(&tx, record).map_err(write_error)?;
+        remove_unused_tags(&tx).map_err(write_error)?;
+        insert_revision(&tx, revision).map_err(write_error)?;
+        prune(&tx, retention).map_err(write_error)?;
+        tx.commit().map_err(write_error)
+    }
+
+    // 导入归档的全部改动在一个事务中完成，任何一步失败都不会留下部分导入的数据
+    pub fn import_records(
+        &mut self,
+        batch: &ImportBatch,
+        retention: &Retention,
+    ) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for id in batch.cleared_history {
+            tx.execute("DELETE FROM revisions WHERE record_id = ?1", [id])
+                .map_err(write_error)?;
+        }
+        for record in batch.records {
+            upsert(&tx, record).map_err(write_error)?;
+            replace_tags(&tx, record).map_err(write_error)?;
+            tx.execute("DELETE FROM trash WHERE record_id = ?1", [&record.id])
+                .map_err(write_error)?;
+        }
+        for revision in batch.revisions {
+            insert_revision(&tx, revision).map_err(write_error)?;
+        }
+        for id in batch.trashed {
+            tx.execute(
+                "INSERT OR REPLACE INTO trash (record_id, deleted_at) VALUES (?1, ?2)",
+                params![id, batch.deleted_at],
+            )
+            .map_err(write_error)?;
+        }
+        remove_unused_tags(&tx).map_err(write_error)?;
+        prune(&tx, retention).map_err(write_error)?;
+        tx.commit().map_err(write_error)
+    }
+
+    // 记录的全部历史版本，新的在前
+    pub fn load_revisions(&self, record_id: &str) -> Result<Vec<StoredRevision>, String> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT number, saved_at, replaced_at, body FROM revisions
+                 WHERE record_id = ?1 ORDER BY number DESC",
+            )
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map([record_id], |row| {
+                Ok(StoredRevision {
+                    record_id: record_id.to_string(),
+                    number: row.get(0)?,
+                    saved_at: row.get(1)?,
+                    replaced_at: row.get(2)?,
+                    body: row.get(3)?,
+                })
+            })
+            .map_err(query_error)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(query_error)
+    }
+
+    pub fn prune_revisions(&mut self, retention: &Retention) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        prune(&tx, retention).map_err(write_error)?;
+        tx.commit().map_err(write_error)
+    }
+
+    // 移入回收站，记录内容保持不变
+    pub fn trash_records(&mut self, ids: &[String], deleted_at: &str) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for id in ids {
+            tx.execute(
+                "INSERT OR REPLACE INTO trash (record_id, deleted_at) VALUES (?1, ?2)",
+                params![id, deleted_at],
+            )
+            .map_err(write_error)?;
+        }
+        tx.commit().map_err(write_error)
+    }
+
+    pub fn restore_records(&mut self, ids: &[String]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for id in ids {
+            tx.execute("DELETE FROM trash WHERE record_id = ?1", [id])
+                .map_err(write_error)?;
+        }
+        tx.commit().map_err(write_error)
+    }
+
+    // 尘封信息、回收站条目、标签关联与历史版本随记录级联删除。
+    // 自动销毁时传入 destroyed_at，销毁日志与删除在同一事务中写入
+    pub fn delete_records(
+        &mut self,
+        ids: &[String],
+        destroyed_at: Option<&str>,
+    ) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for id in ids {
+            tx.execute("DELETE FROM records WHERE id = ?1", [id])
+                .map_err(write_error)?;
+            if let Some(destroyed_at) = destroyed_at {
+                tx.execute(
+                    "INSERT INTO destructions (destroyed_at) VALUES (?1)",
+                    [destroyed_at],
+                )
+                .map_err(write_error)?;
+            }
+        }
+        remove_unused_tags(&tx).map_err(write_error)?;
+        tx.commit().map_err(write_error)
+    }
+
+    pub fn load_destructions(&self) -> Result<Vec<String>, String> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT destroyed_at FROM destructions")
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(query_error)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(query_error)
+    }
+
+    pub fn register_media(
+        &self,
+        media_ref: &str,
+        kind: &str,
+        size: u64,
+        created_at: &str,
+    ) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO media (media_ref, kind, size, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![media_ref, kind, size, created_at],
+            )
+            .map(|_| ())
+            .map_err(write_error)
+    }
+
+    pub fn unregister_media(&mut self, media_refs: &[String]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        for media_ref in media_refs {
+            tx.execute("DELETE FROM media WHERE media_ref = ?1", [media_ref])
+                .map_err(write_error)?;
+        }
+        tx.commit().map_err(write_error)
+    }
+
+    pub fn meta(&self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(query_error)
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map(|_| ())
+            .map_err(write_error)
+    }
+
+    // 用 VACUUM INTO 在 path 写入一致的数据库副本，path 不能已存在
+    pub fn snapshot_to(&self, path: &Path) -> Result<(), String> {
+        self.conn
+            .execute("VACUUM INTO ?1", [path.to_string_lossy()])
+            .map(|_| ())
+            .map_err(|e| format!("备份数据库失败: {}", e))
+    }
+
+    // 锁定状态下只读打开数据库文件写入副本，不做迁移、不写快照，也不改动可能已损坏的文件
+    pub fn snapshot_file(source: &Path, path: &Path) -> Result<(), String> {
+        Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("打开数据库失败: {}", e))?
+            .execute("VACUUM INTO ?1", [path.to_string_lossy()])
+            .map(|_| ())
+            .map_err(|e| format!("备份数据库失败: {}", e))
+    }
+
+    // 只读打开数据库副本做完整性检查，返回其中回收站以外的记录数
+    pub fn verify_file(path: &Path) -> Result<usize, String> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("打开数据库失败: {}", e))?;
+        let check: String = conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(|e| format!("数据库文件已损坏: {}", e))?;
+        if check != "ok" {
+            return Err(format!("数据库文件已损坏: {}", check));
+        }
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(query_error)?;
+        if version > MIGRATIONS.len() {
+            return Err("数据库由更新版本的应用创建，请升级后再打开".to_string());
+        }
+        conn.query_row(
+            "SELECT COUNT(*) FROM records WHERE id NOT IN (SELECT record_id FROM trash)",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(query_error)
+    }
+
+    // 用 VACUUM INTO 生成一致的快照，再原子替换旧快照
+    fn snapshot(&self) -> Result<(), String> {
+        let mut tmp = self.backup.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let _ = fs::remove_file(&tmp);
+        self.snapshot_to(&tmp)?;
+        fs::rename(&tmp, &self.backup).map_err(|e| format!("备份数据库失败: {}", e))
+    }
+}
+
+// 锁定或退出时更新快照
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = self.snapshot();
+    }
+}
+
+// 打开数据库并做完整性检查
+fn open_checked(path: &Path) -> Result<Connection, OpenError> {
+    let conn = Connection::open(path).map_err(open_error)?;
+    // FULL 保证断电后已提交的事务不丢失；secure_delete 让删除的内容不残留在空闲页中
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = FULL;
+         PRAGMA foreign_keys = ON;
+         PRAGMA secure_delete = ON;",
+    )
+    .map_err(open_error)?;
+
+    let check: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(open_error)?;
+    if check != "ok" {
+        return Err(OpenError::Corrupt(format!("数据库文件已损坏: {}", check)));
+    }
+    Ok(conn)
+}
+
+// SQLITE_CORRUPT 与 SQLITE_NOTADB 表示文件已损坏，其余错误原样报告
+fn open_error(e: rusqlite::Error) -> OpenError {
+    match e.sqlite_error_code() {
+        Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => {
+            OpenError::Corrupt(format!("数据库文件已损坏: {}", e))
+        }
+        _ => OpenError::Other(format!("打开数据库失败: {}", e)),
+    }
+}
+
+// 依次执行尚未应用的迁移，每个迁移与版本号更新在同一事务中完成
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(query_error)?;
+    if version > MIGRATIONS.len() {
+        return Err("数据库由更新版本的应用创建，请升级后再打开".to_string());
+    }
+
+    for (index, script) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(write_error)?;
+        tx.execute_batch(script)
+            .and_then(|_| tx.pragma_update(None, "user_version", index + 1))
+            .and_then(|_| tx.commit())
+            .map_err(|e| format!("数据库迁移到第 {} 版失败: {}", index + 1, e))?;
+    }
+    Ok(())
+}
+
+fn upsert(tx: &Transaction, record: &StoredRecord) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO records (id, created_at, updated_at, auto_destroy_at, body)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET
+             created_at = excluded.created_at,
+             updated_at = excluded.updated_at,
+             auto_destroy_at = excluded.auto_destroy_at,
+             body = excluded.body",
+        params![
+            record.id,
+            record.created_at,
+            record.updated_at,
+            record.auto_destroy_at,
+            record.body
+        ],
+    )?;
+    if record.is_sealed {
+        tx.execute(
+            "INSERT INTO seals (record_id, seal_until) VALUES (?1, ?2)
+             ON CONFLICT(record_id) DO UPDATE SET seal_until = excluded.seal_until",
+            params![record.id, record.seal_until],
+        )?;
+    } else {
+        tx.execute("DELETE FROM seals WHERE record_id = ?1", [&record.id])?;
+    }
+    Ok(())
+}
+
+// 以记录当前的标签替换原有关联。同名标签共用一行，显示名称以最后一次写入为准
+fn replace_tags(tx: &Transaction, record: &StoredRecord) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM record_tags WHERE record_id = ?1", [&record.id])?;
+    for tag in &record.tags {
+        let tag_id: i64 = tx.query_row(
+            "INSERT INTO tags (lookup, name) VALUES (?1, ?2)
+             ON CONFLICT(lookup) DO UPDATE SET name = excluded.name
+             RETURNING id",
+            params![tag.lookup, tag.name],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO record_tags (record_id, tag_id) VALUES (?1, ?2)",
+            params![record.id, tag_id],
+        )?;
+    }
+    Ok(())
+}
+
+// 版本号取该记录已有的最大版本号加一
+fn insert_revision(tx: &Transaction, revision: &StoredRevision) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO revisions (record_id, number, saved_at, replaced_at, body)
+         VALUES (?1, (SELECT COALESCE(MAX(number), 0) + 1 FROM revisions WHERE record_id = ?1),
+                 ?2, ?3, ?4)",
+        params![
+            revision.record_id,
+            revision.saved_at,
+            revision.replaced_at,
+            revision.body
+        ],
+    )
+    .map(|_| ())
+}
+
+// 不再被任何记录使用的标签连同加密名称一并删除
+fn remove_unused_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM record_tags)",
+        [],
+    )
+    .map(|_| ())
+}
+
+// 每条记录只删除最旧的一段版本：超出数量上限的，以及最后一个过期版本及其之前的全部版本
+fn prune(tx: &Transaction, retention: &Retention) -> rusqlite::Result<()> {
+    tx.execute(
+        "DELETE FROM revisions WHERE id IN (
+             SELECT r.id FROM revisions r
+             WHERE (SELECT COUNT(*) FROM revisions n
+                    WHERE n.record_id = r.record_id AND n.number > r.number) >= ?1
+                OR r.number <= (SELECT MAX(o.number) FROM revisions o
+                                WHERE o.record_id = r.record_id AND o.replaced_at < ?2)
+         )",
+        params![retention.keep, retention.replaced_before],
+    )
+    .map(|_| ())
+}
+
+// 把损坏的数据库连同预写日志改名为 records.db.corrupt-<时间>，返回新的路径。
+// 预写日志不能留给恢复出的快照，否则会被当作快照的日志重放
+fn move_aside(path: &Path) -> Result<PathBuf, String> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(format!(".corrupt-{}", Utc::now().format("%Y%m%dT%H%M%SZ")));
+    for suffix in ["", "-wal", "-shm"] {
+        let (mut from, mut to) = (path.as_os_str().to_owned(), target.clone());
+        from.push(suffix);
+        to.push(suffix);
+        let result = match suffix {
+            "-shm" => fs::remove_file(&from),
+            _ => fs::rename(&from, &to),
+        };
+        match result {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("移走损坏的数据库失败: {}", e))
+            }
+            _ => {}
+        }
+    }
+    Ok(PathBuf::from(target))
+}
+
+// records.db -> records.db.bak
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn query_error(e: rusqlite::Error) -> String {
+    format!("读取数据库失败: {}", e)
+}
+
+fn write_error(e: rusqlite::Error) -> String {
+    format!("写入数据库失败: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("database-{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn corrupt_file_is_moved_aside_and_restored_from_snapshot() {
+        let dir = temp_dir();
+        let path = dir.join("records.db");
+        let database = Database::open(&path).unwrap();
+        database.set_meta("marker", "kept").unwrap();
+        drop(database);
+
+        fs::write(&path, b"not a database at all, just some garbage bytes").unwrap();
+        let database = Database::open(&path).unwrap();
+        assert!(database.recovered());
+        assert_eq!(database.meta("marker").unwrap().as_deref(), Some("kept"));
+        let corrupt = database.corrupt_copy().unwrap().to_path_buf();
+        assert!(corrupt
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("records.db.corrupt-"));
+        assert_eq!(
+            fs::read(&corrupt).unwrap(),
+            b"not a database at all, just some garbage bytes"
+        );
+        drop(database);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn new_database_runs_every_migration() {
+        let dir = temp_dir();
+        let database = Database::open(&dir.join("records.db")).unwrap();
+        let version: usize = database
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        assert!(!database.recovered());
+        drop(database);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+// 超过该规模（两侧行数之积）时不再求最长公共子序列，直接整段替换
+const MAX_TABLE: usize = 4_000_000;
+
+// 把一段文本变换为另一段文本的编辑步骤，以行为单位（行尾换行符属于该行）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Edit {
+    Keep { lines: usize },
+    Delete { lines: usize },
+    Insert { text: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+// 生成把 old 变为 new 的编辑步骤，相邻的同类步骤合并
+pub fn diff(old: &str, new: &str) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    for (kind, line) in compare_lines(&lines(old), &lines(new)) {
+        match (kind, edits.last_mut()) {
+            (LineKind::Equal, Some(Edit::Keep { lines })) => *lines += 1,
+            (LineKind::Equal, _) => edits.push(Edit::Keep { lines: 1 }),
+            (LineKind::Delete, Some(Edit::Delete { lines })) => *lines += 1,
+            (LineKind::Delete, _) => edits.push(Edit::Delete { lines: 1 }),
+            (LineKind::Insert, Some(Edit::Insert { text })) => text.push_str(line),
+            (LineKind::Insert, _) => edits.push(Edit::Insert {
+                text: line.to_string(),
+            }),
+        }
+    }
+    edits
+}
+
+// 对 base 依次执行编辑步骤；步骤与 base 不符时说明差异链已损坏
+pub fn apply(base: &str, edits: &[Edit]) -> Result<String, String> {
+    let mut remaining = lines(base).into_iter();
+    let mut out = String::with_capacity(base.len());
+    for edit in edits {
+        match edit {
+            Edit::Keep { lines } => {
+                for _ in 0..*lines {
+                    out.push_str(remaining.next().ok_or_else(corrupt)?);
+                }
+            }
+            Edit::Delete { lines } => {
+                for _ in 0..*lines {
+                    remaining.next().ok_or_else(corrupt)?;
+                }
+            }
+            Edit::Insert { text } => out.push_str(text),
+        }
+    }
+    if remaining.next().is_some() {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+// 供界面展示的逐行对比
+pub fn compare(old: &str, new: &str) -> Vec<DiffLine> {
+    compare_lines(&lines(old), &lines(new))
+        .into_iter()
+        .map(|(kind, text)| DiffLine {
+            kind,
+            text: text.to_string(),
+        })
+        .collect()
+}
+
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+// 去掉相同的首尾后，对中间部分求最长公共子序列
+fn compare_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(LineKind, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut result: Vec<(LineKind, &str)> = old[..prefix]
+        .iter()
+        .map(|l| (LineKind::Equal, *l))
+        .collect();
+    if a.len().saturating_mul(b.len()) > MAX_TABLE {
+        result.extend(a.iter().map(|l| (LineKind::Delete, *l)));
+        result.extend(b.iter().map(|l| (LineKind::Insert, *l)));
+    } else {
+        result.extend(lcs(a, b));
+    }
+    result.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|l| (LineKind::Equal, *l)),
+    );
+    result
+}
+
+fn lcs<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(LineKind, &'a str)> {
+    // table[i][j] 为 a[i..] 与 b[j..] 的最长公共子序列长度
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i * width + j] = if a[i] == b[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            result.push((LineKind::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            result.push((LineKind::Delete, a[i]));
+            i += 1;
+        } else {
+            result.push((LineKind::Insert, b[j]));
+            j += 1;
+        }
+    }
+    result.extend(a[i..].iter().map(|l| (LineKind::Delete, *l)));
+    result.extend(b[j..].iter().map(|l| (LineKind::Insert, *l)));
+    result
+}
+
+fn corrupt() -> String {
+    "历史版本的差异数据已损坏".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(old: &str, new: &str) {
+        assert_eq!(apply(old, &diff(old, new)).unwrap(), new);
+    }
+
+    #[test]
+    fn edits_round_trip() {
+        round_trip("", "");
+        round_trip("", "a\nb\n");
+        round_trip("a\nb\n", "");
+        round_trip("a\nb\nc\n", "a\nx\nc\n");
+        round_trip("a\nb\nc", "b\nc\nd");
+        round_trip("same\nlast line without newline", "same\nlast line changed");
+        round_trip("一\n二\n三\n", "零\n一\n三\n四\n");
+    }
+
+    #[test]
+    fn adjacent_steps_are_merged() {
+        let edits = diff("a\nb\nc\nd\n", "a\nx\ny\nd\n");
+        let ops: Vec<String> = edits
+            .iter()
+            .map(|edit| match edit {
+                Edit::Keep { lines } => format!("keep {}", lines),
+                Edit::Delete { lines } => format!("delete {}", lines),
+                Edit::Insert { text } => format!("insert {:?}", text),
+            })
+            .collect();
+        assert_eq!(ops, ["keep 1", "delete 2", "insert \"x\\ny\\n\"", "keep 1"]);
+    }
+
+    #[test]
+    fn mismatched_base_is_reported_as_corrupt() {
+        let edits = diff("a\nb\n", "a\n");
+        assert!(apply("a\n", &edits).is_err());
+        assert!(apply("a\nb\nc\n", &edits).is_err());
+    }
+
+    #[test]
+    fn compare_marks_each_line() {
+        let kinds: Vec<(LineKind, String)> = compare("a\nb\n", "a\nc\n")
+            .into_iter()
+            .map(|line| (line.kind, line.text))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (LineKind::Equal, "a\n".to_string()),
+                (LineKind::Delete, "b\n".to_string()),
+                (LineKind::Insert, "c\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_inputs_fall_back_to_replacing_the_middle() {
+        let old: String = (0..2100).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..2100).map(|i| format!("new {}\n", i)).collect();
+        let old = format!("head\n{}tail\n", old);
+        let new = format!("head\n{}tail\n", new);
+        let edits = diff(&old, &new);
+        assert!(matches!(edits[0], Edit::Keep { lines: 1 }));
+        assert!(matches!(edits[1], Edit::Delete { lines: 2100 }));
+        assert_eq!(apply(&old, &edits).unwrap(), new);
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::media::{mime_for_extension, MediaFile};
+use crate::models::EmotionalRecord;
+use crate::settings::Settings;
+
+// 未指定标题时 HTML 书的标题
+const DEFAULT_TITLE: &str = "Pick Up Memories";
+// 尘封记录的标题在加密载荷中，导出时以此代替
+const SEALED_TITLE: &str = "尘封的记忆";
+// 没有标题的记录导出时的标题，导入时还原为空标题
+pub const UNTITLED: &str = "无标题";
+// 文件名中标题部分的最大字符数
+const MAX_NAME_CHARS: usize = 40;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentFormat {
+    // 一个文件夹，每条记录一个 Markdown 文件，媒体复制到 media/ 下
+    #[default]
+    Markdown,
+    // 单个 HTML 文件，图片以 data URL 内嵌，带目录
+    Html,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DocumentOptions {
+    pub format: DocumentFormat,
+    // 要导出的记录，未指定时为全部（不含回收站）
+    pub ids: Option<Vec<String>>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSummary {
+    pub path: String,
+    pub format: DocumentFormat,
+    pub records: usize,
+    // 尘封中的记录只导出日期与尘封信息
+    pub sealed: usize,
+    pub media: usize,
+    pub missing_media: Vec<String>,
+}
+
+// 在仓库锁内收集好的导出内容，读取媒体与写入文件在释放锁之后进行
+pub struct DocumentExport {
+    pub format: DocumentFormat,
+    pub title: Option<String>,
+    // 已按创建时间排序
+    pub records: Vec<EmotionalRecord>,
+    pub media: Vec<(String, MediaFile)>,
+    pub missing_media: Vec<String>,
+}
+
+impl DocumentExport {
+    // 把记录导出为 Markdown 文件夹或单个 HTML 文件，供人直接阅读
+    pub fn write(self, path: &Path, settings: &Settings) -> Result<DocumentSummary, String> {
+        let media_count = self.media.len();
+        match self.format {
+            DocumentFormat::Markdown => {
+                let is_empty = fs::read_dir(path).map_or(true, |mut d| d.next().is_none());
+                if !is_empty {
+                    return Err("请选择一个空文件夹".to_string());
+                }
+                fs::create_dir_all(path).map_err(|e| format!("创建文件夹失败: {}", e))?;
+                let mut exported = HashSet::new();
+                for (media_ref, file) in self.media {
+                    let target = path.join("media").join(&media_ref);
+                    if let Some(dir) = target.parent() {
+                        fs::create_dir_all(dir).map_err(|e| format!("创建文件夹失败: {}", e))?;
+                    }
+                    let data = Zeroizing::new(file.open()?.read_all()?);
+                    atomic::write_file(&target, &data)
+                        .map_err(|e| format!("写入文件失败: {}", e))?;
+                    exported.insert(media_ref);
+                }
+                let link = |media_ref: &str| {
+                    exported
+                        .contains(media_ref)
+                        .then(|| format!("media/{}", media_ref))
+                };
+                let mut used = HashSet::new();
+                for record in &self.records {
+                    let content = markdown(record, settings, &link);
+                    let target = path.join(file_name(record, &mut used));
+                    atomic::write_file(&target, content.as_bytes())
+                        .map_err(|e| format!("写入文件失败: {}", e))?;
+                }
+            }
+            DocumentFormat::Html => {
+                let mut images = HashMap::new();
+                for (media_ref, file) in self.media {
+                    if !media_ref.starts_with("images/") {
+                        continue;
+                    }
+                    let data = Zeroizing::new(file.open()?.read_all()?);
+                    if let Some(url) = data_url(&media_ref, &data) {
+                        images.insert(media_ref, url);
+                    }
+                }
+                let content = html(
+                    self.title.as_deref(),
+                    &self.records,
+                    settings,
+                    &|media_ref| images.get(media_ref).cloned(),
+                );
+                atomic::write_file(path, content.as_bytes())
+                    .map_err(|e| format!("写入文件失败: {}", e))?;
+            }
+        }
+
+        Ok(DocumentSummary {
+            path: path.to_string_lossy().into_owned(),
+            format: self.format,
+            records: self.records.len(),
+            sealed: self.records.iter().filter(|r| r.is_sealed).count(),
+            media: media_count,
+            missing_media: self.missing_media,
+        })
+    }
+}
+
+// 按创建时间从早到晚排列，无法解析的时间排在最前
+pub fn sort_records(records: &mut [EmotionalRecord]) {
+    records.sort_by_cached_key(|r| {
+        (
+            DateTime::parse_from_rfc3339(&r.created_at).ok(),
+            r.created_at.clone(),
+        )
+    });
+}
+
+// 形如 2024-03-05-标题.md 的文件名，去掉文件系统不允许的字符，重名时加序号
+pub fn file_name(record: &EmotionalRecord, used: &mut HashSet<String>) -> String {
+    // 无法解析的时间原样返回，其中的冒号同样须去掉
+    let date: String = sanitize(&local_time(&record.created_at, "%Y-%m-%d"))
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let title: String = sanitize(display_title(record))
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let stem = match (date.as_str(), title.trim_matches(['-', '.'])) {
+        ("", "") => "record".to_string(),
+        (date, "") => date.to_string(),
+        ("", title) => title.to_string(),
+        (date, title) => format!("{}-{}", date, title),
+    };
+
+    let mut name = format!("{}.md", stem);
+    let mut counter = 2;
+    while !used.insert(name.to_lowercase()) {
+        name = format!("{}-{}.md", stem, counter);
+        counter += 1;
+    }
+    name
+}
+
+// 单条记录的 Markdown：YAML front matter 记录日期、尘封信息与心情，正文原样保留。
+// link 把媒体引用换成导出后的相对路径，返回 None 时省略该媒体
+pub fn markdown(
+    record: &EmotionalRecord,
+    settings: &Settings,
+    link: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    let mut out = String::from("---\n");
+    let mut field = |key: &str, value: String| out.push_str(&format!("{}: {}\n", key, value));
+    field("id", quote(&record.id));
+    field("title", quote(display_title(record)));
+    field("date", quote(&record.created_at));
+    field("updated", quote(&record.updated_at));
+    if let Some(captured_at) = &record.captured_at {
+        field("captured", quote(captured_at));
+    }
+    if !record.tags.is_empty() {
+        let tags: Vec<String> = record.tags.iter().map(|t| quote(t)).collect();
+        field("tags", format!("[{}]", tags.join(", ")));
+    }
+    if let Some(mood) = &record.mood {
+        field("mood", quote(&mood_label(settings, &mood.id)));
+        field("mood_intensity", mood.intensity.to_string());
+    }
+    if record.is_sealed {
+        field("sealed", "true".to_string());
+        if let Some(seal_until) = &record.seal_until {
+            field("seal_until", quote(seal_until));
+        }
+    }
+    if let Some(auto_destroy_at) = &record.auto_destroy_at {
+        field("auto_destroy_at", quote(auto_destroy_at));
+    }
+    if let Some(music_title) = &record.music_title {
+        field("music", quote(music_title));
+    }
+    if let Some(file) = record.music_url.as_deref().and_then(link) {
+        field("music_file", quote(&file));
+    }
+    out.push_str("---\n\n");
+
+    out.push_str(&format!("# {}\n\n", display_title(record)));
+    if record.is_sealed {
+        out.push_str(&format!("> {}\n", sealed_notice(record)));
+        return out;
+    }
+    let content = record.content.trim_end();
+    if !content.is_empty() {
+        out.push_str(content);
+        out.push('\n');
+    }
+    for image in record.images.iter().filter_map(|i| link(i)) {
+        out.push_str(&format!("\n![]({})\n", image.replace(' ', "%20")));
+    }
+    out
+}
+
+// 自包含的 HTML 书：目录在前，每条记录一节。images 返回图片的 data URL，返回 None 时省略
+pub fn html(
+    title: Option<&str>,
+    records: &[EmotionalRecord],
+    settings: &Settings,
+    images: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    let title = escape(
+        title
+            .filter(|t| !t.trim().is_empty())
+            .unwrap_or(DEFAULT_TITLE),
+    );
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, STYLE, title
+    );
+
+    out.push_str("<nav>\n<h2>目录</h2>\n<ol>\n");
+    for (index, record) in records.iter().enumerate() {
+        out.push_str(&format!(
+            "<li><a href=\"#memory-{}\"><time>{}</time> {}</a></li>\n",
+            index + 1,
+            local_time(&record.created_at, "%Y-%m-%d"),
+            escape(display_title(record))
+        ));
+    }
+    out.push_str("</ol>\n</nav>\n");
+
+    for (index, record) in records.iter().enumerate() {
+        out.push_str(&format!(
+            "<article id=\"memory-{}\">\n<h2>{}</h2>\n<p class=\"meta\"><time>{}</time>",
+            index + 1,
+            escape(display_title(record)),
+            local_time(&record.created_at, "%Y-%m-%d %H:%M")
+        ));
+        if let Some(mood) = &record.mood {
+            out.push_str(&format!(
+                " · {} {}/{}",
+                escape(&mood_label(settings, &mood.id)),
+                mood.intensity,
+                settings.mood_intensity_levels
+            ));
+        }
+        for tag in &record.tags {
+            out.push_str(&format!(" · #{}", escape(tag)));
+        }
+        out.push_str("</p>\n");
+
+        if record.is_sealed {
+            out.push_str(&format!(
+                "<p class=\"sealed\">{}</p>\n</article>\n",
+                escape(&sealed_notice(record))
+            ));
+            continue;
+        }
+        for paragraph in record
+            .content
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let lines: Vec<String> = paragraph.lines().map(escape).collect();
+            out.push_str(&format!("<p>{}</p>\n", lines.join("<br>\n")));
+        }
+        for image in record.images.iter().filter_map(|i| images(i)) {
+            out.push_str(&format!(
+                "<figure><img src=\"{}\" alt=\"\"></figure>\n",
+                image
+            ));
+        }
+        if let Some(music_title) = &record.music_title {
+            out.push_str(&format!(
+                "<p class=\"music\">♪ {}</p>\n",
+                escape(music_title)
+            ));
+        }
+        out.push_str("</article>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub fn data_url(media_ref: &str, data: &[u8]) -> Option<String> {
+    let extension = media_ref.rsplit_once('.')?.1;
+    let mime = mime_for_extension(extension)?;
+    Some(format!("data:{};base64,{}", mime, STANDARD.encode(data)))
+}
+
+pub fn display_title(record: &EmotionalRecord) -> &str {
+    match record.title.trim() {
+        "" if record.is_sealed => SEALED_TITLE,
+        "" => UNTITLED,
+        title => title,
+    }
+}
+
+pub fn sealed_notice(record: &EmotionalRecord) -> String {
+    match &record.seal_until {
+        Some(until) => format!(
+            "这段记忆仍在尘封中，将于 {} 解封。",
+            local_time(until, "%Y-%m-%d %H:%M")
+        ),
+        None => "这段记忆仍在尘封中。".to_string(),
+    }
+}
+
+// 心情的显示名称，色板中已删除的心情显示其 id
+pub fn mood_label(settings: &Settings, id: &str) -> String {
+    settings
+        .mood_palette
+        .iter()
+        .find(|option| option.id == id)
+        .map_or_else(|| id.to_string(), |option| option.label.clone())
+}
+
+// 按本地时区格式化 ISO 时间，无法解析时原样返回
+pub fn local_time(timestamp: &str, format: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.with_timezone(&Local).format(format).to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+// 文件系统不允许的字符与空白替换为连字符，并去掉首尾的连字符与点
+fn sanitize(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .trim_matches(['-', '.'])
+        .to_string()
+}
+
+// JSON 字符串同时是合法的 YAML 双引号字符串
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+const STYLE: &str = "body{max-width:42em;margin:2em auto;padding:0 1em;\
+font-family:-apple-system,'PingFang SC','Microsoft YaHei',sans-serif;line-height:1.7;color:#333}\
+nav ol{padding-left:1.5em}nav a{text-decoration:none;color:inherit}\
+nav time,.meta{color:#888;font-size:.9em}\
+article{border-top:1px solid #eee;margin-top:2em;padding-top:1em;page-break-before:always}\
+figure{margin:1em 0}img{max-width:100%;border-radius:4px}\
+.sealed{font-style:italic;color:#777}.music{color:#666}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(created_at: &str, title: &str) -> EmotionalRecord {
+        EmotionalRecord {
+            id: "record_1_a".to_string(),
+            title: title.to_string(),
+            content: "正文".to_string(),
+            created_at: created_at.to_string(),
+            updated_at: created_at.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replaces_characters_the_file_system_rejects() {
+        let mut used = HashSet::new();
+        let name = file_name(&record("2024-03-05T12:00:00Z", "a/b: c?"), &mut used);
+        assert!(name.ends_with("-a-b-c.md"), "{}", name);
+    }
+
+    #[test]
+    fn sanitizes_unparseable_dates() {
+        let mut used = HashSet::new();
+        let name = file_name(&record("2024-03-05 12:00:00", "标题"), &mut used);
+        assert_eq!(name, "2024-03-05-12-00-00-标题.md");
+        let name = file_name(&record("", ""), &mut used);
+        assert!(!name.contains(':') && !name.starts_with('.'), "{}", name);
+    }
+
+    #[test]
+    fn numbers_duplicate_names() {
+        let mut used = HashSet::new();
+        let a = file_name(&record("2024-03-05T12:00:00Z", "同名"), &mut used);
+        let b = file_name(&record("2024-03-05T12:00:00Z", "同名"), &mut used);
+        assert_ne!(a, b);
+        assert!(b.ends_with("-2.md"), "{}", b);
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::{DateTime, Days, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{self, DiffLine, Edit};
+use crate::settings::Settings;
+
+// 加密前的历史版本内容：标题完整保存，正文只保存由后一版本还原出本版本的差异。
+// 最新的版本就是记录本身，因此删除最旧的版本不会影响其余版本的还原
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionBody {
+    pub title: String,
+    pub content: Vec<Edit>,
+}
+
+// 写入数据库的历史版本，body 以记录的数据密钥加密，密钥销毁后随记录一同不可恢复。
+// saved_at 为该版本当初保存的时间，replaced_at 为它被新内容替代的时间
+#[derive(Debug, Clone)]
+pub struct StoredRevision {
+    pub record_id: String,
+    pub number: u32,
+    pub saved_at: String,
+    pub replaced_at: String,
+    pub body: String,
+}
+
+// 保留策略：每条记录最多保留 keep 个版本，并删除在 replaced_before 之前被替代的版本
+#[derive(Debug, Clone)]
+pub struct Retention {
+    pub keep: u32,
+    pub replaced_before: Option<String>,
+}
+
+impl Retention {
+    pub fn new(settings: &Settings, now: DateTime<Utc>) -> Self {
+        let replaced_before = (settings.revision_max_age_days > 0)
+            .then(|| now.checked_sub_days(Days::new(u64::from(settings.revision_max_age_days))))
+            .flatten()
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true));
+        Self {
+            keep: settings.revision_limit,
+            replaced_before,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub number: u32,
+    pub saved_at: String,
+    pub title: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionSummary {
+    pub number: u32,
+    pub saved_at: String,
+    pub title: String,
+    // 正文字数
+    pub length: usize,
+}
+
+// 两个版本的对比，from / to 为 None 时表示记录的当前内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionDiff {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+    pub old_title: String,
+    pub new_title: String,
+    pub lines: Vec<DiffLine>,
+}
+
+// 被替代的旧内容，newer_content 为替代它的正文
+pub fn body(title: String, content: &str, newer_content: &str) -> RevisionBody {
+    RevisionBody {
+        title,
+        content: diff::diff(newer_content, content),
+    }
+}
+
+// 从记录的当前正文出发，按版本号从新到旧依次还原出每个版本的完整内容
+pub fn reconstruct(
+    current_content: &str,
+    revisions: Vec<(StoredRevision, RevisionBody)>,
+) -> Result<Vec<Revision>, String> {
+    let mut newer = current_content.to_string();
+    let mut result = Vec::with_capacity(revisions.len());
+    for (stored, body) in revisions {
+        let content = diff::apply(&newer, &body.content)?;
+        newer.clone_from(&content);
+        result.push(Revision {
+            number: stored.number,
+            saved_at: stored.saved_at,
+            title: body.title,
+            content,
+        });
+    }
+    Ok(result)
+}
+
+impl From<&Revision> for RevisionSummary {
+    fn from(revision: &Revision) -> Self {
+        Self {
+            number: revision.number,
+            saved_at: revision.saved_at.clone(),
+            title: revision.title.clone(),
+            length: revision.content.chars().count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(number: u32) -> StoredRevision {
+        StoredRevision {
+            record_id: "a".to_string(),
+            number,
+            saved_at: format!("2024-01-0{}T00:00:00Z", number),
+            replaced_at: format!("2024-01-0{}T00:00:00Z", number + 1),
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn revisions_are_rebuilt_from_the_current_content() {
+        // 版本 1 -> 版本 2 -> 当前内容
+        let v1 = "first line\n";
+        let v2 = "first line\nsecond line\n";
+        let current = "second line\nthird line";
+        let revisions = vec![
+            (stored(2), body("Two".to_string(), v2, current)),
+            (stored(1), body("One".to_string(), v1, v2)),
+        ];
+        let rebuilt = reconstruct(current, revisions).unwrap();
+        let contents: Vec<(u32, &str, &str)> = rebuilt
+            .iter()
+            .map(|r| (r.number, r.title.as_str(), r.content.as_str()))
+            .collect();
+        assert_eq!(contents, [(2, "Two", v2), (1, "One", v1)]);
+        assert_eq!(RevisionSummary::from(&rebuilt[1]).length, 11);
+    }
+
+    #[test]
+    fn dropping_the_oldest_revision_keeps_the_rest_readable() {
+        let revisions = vec![(stored(2), body("Two".to_string(), "old\n", "new\n"))];
+        let rebuilt = reconstruct("new\n", revisions).unwrap();
+        assert_eq!(rebuilt[0].content, "old\n");
+    }
+
+    #[test]
+    fn broken_chains_are_reported() {
+        let revisions = vec![(stored(1), body("One".to_string(), "a\n", "b\nc\n"))];
+        assert!(reconstruct("unrelated\n", revisions).is_err());
+    }
+
+    #[test]
+    fn retention_uses_the_configured_age() {
+        let now = "2024-03-10T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let settings = |days| Settings {
+            revision_max_age_days: days,
+            ..Settings::default()
+        };
+        let retention = Retention::new(&settings(10), now);
+        assert_eq!(
+            retention.replaced_before.as_deref(),
+            Some("2024-02-29T00:00:00.000Z")
+        );
+        assert_eq!(Retention::new(&settings(0), now).replaced_before, None);
+    }
+}
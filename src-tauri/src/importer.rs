@@ -0,0 +1,1092 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::archive::{ArchiveOperation, ArchiveProgress};
+use crate::document;
+use crate::media::{mime_for_extension, PreparedImage};
+use crate::models::Mood;
+use crate::settings::Settings;
+use crate::store;
+use crate::tags;
+
+// 单个文件的读取上限，防止损坏的导出文件占满内存
+const MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+// 扫描文件夹时的最大深度
+const MAX_DEPTH: usize = 8;
+const TEXT_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+// 依次尝试的无时区日期格式，按本地时间解释
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+];
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JournalFormat {
+    // Day One 导出的 JSON，照片在同目录的 photos/ 下，以 md5 命名
+    DayOne,
+    // Journey 导出的 zip：每篇日记一个 JSON 文件，照片与之同目录
+    Journey,
+    // .md / .txt 文件，日期、标题与标签写在 front matter 中，键名与 Markdown 导出相同
+    Markdown,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct JournalImportOptions {
+    // 未指定时按内容自动识别
+    pub format: Option<JournalFormat>,
+    // 与已有记录重复的日记也照样导入
+    pub include_duplicates: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntryPreview {
+    pub source: String,
+    pub title: String,
+    pub created_at: String,
+    pub tags: Vec<String>,
+    pub photos: usize,
+    // 引用了但在导出中找不到的照片
+    pub missing_photos: Vec<String>,
+    // 与已有记录或前面的日记创建时间相同、文字相同
+    pub duplicate: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedEntry {
+    pub source: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalPreview {
+    pub format: JournalFormat,
+    pub entries: Vec<JournalEntryPreview>,
+    pub duplicates: usize,
+    // 无法解析的文件或日记
+    pub skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalImportReport {
+    #[serde(flatten)]
+    pub preview: JournalPreview,
+    pub imported: usize,
+    pub photos: usize,
+    // 找不到或格式不受支持的照片
+    pub missing_photos: Vec<String>,
+}
+
+// 从导出中解析出的一篇日记，时间已换成 UTC 的 ISO 格式
+pub struct ForeignEntry {
+    pub source: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+    pub captured_at: Option<String>,
+    pub tags: Vec<String>,
+    pub mood: Option<Mood>,
+    pub music_title: Option<String>,
+    // 导出中的照片文件
+    pub photos: Vec<String>,
+    pub missing_photos: Vec<String>,
+}
+
+pub struct ParsedJournal {
+    pub format: JournalFormat,
+    pub entries: Vec<ForeignEntry>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+// 在仓库锁外读出的待导入日记，照片已准备好，加锁后只需写入
+pub struct PreparedJournal {
+    pub preview: JournalPreview,
+    pub entries: Vec<PreparedEntry>,
+}
+
+pub struct PreparedEntry {
+    pub entry: ForeignEntry,
+    // 与 entry.photos 一一对应，找不到或格式不受支持的照片为 None
+    pub photos: Vec<(String, Option<PreparedImage>)>,
+}
+
+// 导出内容：zip 包或文件夹，其中的文件以 / 分隔的相对路径表示
+pub struct Bundle {
+    source: Source,
+    names: Vec<String>,
+}
+
+enum Source {
+    Dir(PathBuf),
+    Zip(ZipArchive<File>),
+}
+
+impl Bundle {
+    // 可以选择 zip 包、文件夹或单个导出文件；选择单个文件时附件在同一文件夹中查找
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("无法打开导出文件: {}", e))?;
+        if metadata.is_dir() {
+            let mut names = Vec::new();
+            list_dir(path, "", 0, &mut names)?;
+            names.sort();
+            return Ok(Self {
+                source: Source::Dir(path.to_path_buf()),
+                names,
+            });
+        }
+        if has_extension(&path.to_string_lossy(), &["zip"]) {
+            let file = File::open(path).map_err(|e| format!("无法打开导出文件: {}", e))?;
+            let zip = ZipArchive::new(file).map_err(|e| format!("无法读取 zip 文件: {}", e))?;
+            let mut names: Vec<String> = zip
+                .file_names()
+                .filter(|name| !name.ends_with('/') && !is_hidden(name))
+                .map(String::from)
+                .collect();
+            names.sort();
+            return Ok(Self {
+                source: Source::Zip(zip),
+                names,
+            });
+        }
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| "无法打开导出文件".to_string())?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Ok(Self {
+            source: Source::Dir(dir.to_path_buf()),
+            names: vec![name.to_string()],
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        match &self.source {
+            Source::Dir(dir) => dir.join(name).is_file(),
+            Source::Zip(_) => self
+                .names
+                .binary_search_by(|n| n.as_str().cmp(name))
+                .is_ok(),
+        }
+    }
+
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let read = match &mut self.source {
+            Source::Dir(dir) => File::open(dir.join(name))
+                .and_then(|file| file.take(MAX_FILE_BYTES + 1).read_to_end(&mut data)),
+            Source::Zip(zip) => zip
+                .by_name(name)
+                .map_err(|e| std::io::Error::other(e.to_string()))
+                .and_then(|file| file.take(MAX_FILE_BYTES + 1).read_to_end(&mut data)),
+        };
+        read.map_err(|e| format!("读取 {} 失败: {}", name, e))?;
+        if data.len() as u64 > MAX_FILE_BYTES {
+            return Err(format!("文件过大: {}", name));
+        }
+        Ok(data)
+    }
+}
+
+// 解析导出中的全部日记。单个文件或单篇日记无法解析时记入 skipped，不影响其余部分
+pub fn parse(
+    bundle: &mut Bundle,
+    format: Option<JournalFormat>,
+    settings: &Settings,
+) -> Result<ParsedJournal, String> {
+    let format = match format {
+        Some(format) => format,
+        None => detect(bundle)?,
+    };
+    let extensions: &[&str] = match format {
+        JournalFormat::DayOne | JournalFormat::Journey => &["json"],
+        JournalFormat::Markdown => TEXT_EXTENSIONS,
+    };
+    let files: Vec<String> = bundle
+        .names
+        .iter()
+        .filter(|name| has_extension(name, extensions))
+        .cloned()
+        .collect();
+
+    let mut parsed = ParsedJournal {
+        format,
+        entries: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for name in files {
+        let data = match bundle.read(&name) {
+            Ok(data) => data,
+            Err(reason) => {
+                parsed.skipped.push(SkippedEntry {
+                    source: name,
+                    reason,
+                });
+                continue;
+            }
+        };
+        let result = match format {
+            JournalFormat::DayOne => day_one(&name, &data, bundle, &mut parsed.skipped),
+            JournalFormat::Journey => journey(&name, &data, bundle).map(|e| vec![e]),
+            JournalFormat::Markdown => markdown(&name, &data, bundle, settings).map(|e| vec![e]),
+        };
+        match result {
+            Ok(entries) => parsed.entries.extend(entries),
+            Err(reason) => parsed.skipped.push(SkippedEntry {
+                source: name,
+                reason,
+            }),
+        }
+    }
+    if parsed.entries.is_empty() && parsed.skipped.is_empty() {
+        return Err("导出中没有找到日记".to_string());
+    }
+    parsed.entries.sort_by_cached_key(|e| e.created_at.clone());
+    Ok(parsed)
+}
+
+// 读取选中日记的照片，去除元数据并生成缩略图，不占用仓库锁。
+// 找不到或格式不受支持的照片记为缺失，其他读取或处理错误使整个导入失败
+pub fn prepare(
+    bundle: &mut Bundle,
+    parsed: ParsedJournal,
+    preview: JournalPreview,
+    options: &JournalImportOptions,
+    settings: &Settings,
+    progress: &mut dyn FnMut(ArchiveProgress),
+) -> Result<PreparedJournal, String> {
+    let selected: Vec<ForeignEntry> = parsed
+        .entries
+        .into_iter()
+        .zip(&preview.entries)
+        .filter(|(_, p)| options.include_duplicates || !p.duplicate)
+        .map(|(entry, _)| entry)
+        .collect();
+    let total = import_steps(selected.len());
+    let mut entries = Vec::with_capacity(selected.len());
+    for (done, entry) in selected.into_iter().enumerate() {
+        let mut photos = Vec::new();
+        for name in &entry.photos {
+            let photo = if is_image(name) && bundle.contains(name) {
+                Some(store::prepare_image(&bundle.read(name)?, settings)?)
+            } else {
+                None
+            };
+            photos.push((name.clone(), photo));
+        }
+        entries.push(PreparedEntry { entry, photos });
+        progress(ArchiveProgress {
+            operation: ArchiveOperation::ImportJournal,
+            done: done + 1,
+            total,
+        });
+    }
+    Ok(PreparedJournal { preview, entries })
+}
+
+// 导入的总步数：逐篇准备照片、逐篇写入，最后一步写入数据库
+pub fn import_steps(entries: usize) -> usize {
+    entries * 2 + 1
+}
+
+// 判断重复的依据：创建时间（精确到秒）与去掉空白后的标题和正文
+pub fn fingerprint(created_at: &str, title: &str, content: &str) -> Option<(i64, String)> {
+    let time = DateTime::parse_from_rfc3339(created_at).ok()?.timestamp();
+    let text = title
+        .chars()
+        .chain(content.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    Some((time, text))
+}
+
+fn detect(bundle: &mut Bundle) -> Result<JournalFormat, String> {
+    let json: Vec<String> = bundle
+        .names
+        .iter()
+        .filter(|name| has_extension(name, &["json"]))
+        .cloned()
+        .collect();
+    for name in json {
+        let Ok(data) = bundle.read(&name) else {
+            continue;
+        };
+        match serde_json::from_slice::<Value>(&data) {
+            Ok(value) if value.get("entries").is_some_and(Value::is_array) => {
+                return Ok(JournalFormat::DayOne)
+            }
+            Ok(value) if value.get("date_journal").is_some() => return Ok(JournalFormat::Journey),
+            _ => {}
+        }
+    }
+    if bundle
+        .names
+        .iter()
+        .any(|name| has_extension(name, TEXT_EXTENSIONS))
+    {
+        return Ok(JournalFormat::Markdown);
+    }
+    Err(
+        "无法识别的导出格式，请选择 Day One 或 Journey 的导出文件，或包含 Markdown 文件的文件夹"
+            .to_string(),
+    )
+}
+
+#[derive(Deserialize)]
+struct DayOneExport {
+    entries: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DayOneEntry {
+    creation_date: String,
+    modified_date: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    photos: Vec<DayOnePhoto>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DayOnePhoto {
+    md5: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    order_in_entry: Option<i64>,
+}
+
+fn day_one(
+    name: &str,
+    data: &[u8],
+    bundle: &Bundle,
+    skipped: &mut Vec<SkippedEntry>,
+) -> Result<Vec<ForeignEntry>, String> {
+    let export: DayOneExport =
+        serde_json::from_slice(data).map_err(|_| "不是 Day One 导出文件".to_string())?;
+    let dir = parent(name);
+    let mut entries = Vec::new();
+    for (index, value) in export.entries.into_iter().enumerate() {
+        let id = value.get("uuid").and_then(Value::as_str).map(String::from);
+        let source = format!("{}#{}", name, id.unwrap_or_else(|| (index + 1).to_string()));
+        let entry = serde_json::from_value::<DayOneEntry>(value)
+            .map_err(|e| format!("解析日记失败: {}", e))
+            .and_then(|entry| {
+                let created_at = parse_date(&entry.creation_date)
+                    .ok_or_else(|| format!("无法识别的日期: {}", entry.creation_date))?;
+                Ok((entry, created_at))
+            });
+        let (mut entry, created_at) = match entry {
+            Ok(entry) => entry,
+            Err(reason) => {
+                skipped.push(SkippedEntry { source, reason });
+                continue;
+            }
+        };
+
+        // 正文中的照片以 dayone-moment:// 链接占位，照片按 orderInEntry 附在记录上
+        entry
+            .photos
+            .sort_by_key(|p| p.order_in_entry.unwrap_or(i64::MAX));
+        let mut photos = Vec::new();
+        let mut missing_photos = Vec::new();
+        for photo in &entry.photos {
+            let Some(md5) = &photo.md5 else {
+                continue;
+            };
+            let kind = photo.kind.as_deref().unwrap_or("jpeg");
+            let candidates = [kind, if kind == "jpeg" { "jpg" } else { kind }];
+            match candidates
+                .iter()
+                .filter_map(|ext| resolve(&dir, &format!("photos/{}.{}", md5, ext)))
+                .find(|path| bundle.contains(path))
+            {
+                Some(path) => photos.push(path),
+                None => missing_photos.push(format!("photos/{}.{}", md5, kind)),
+            }
+        }
+        let text = remove_images(&entry.text, |target| {
+            target.starts_with("dayone-moment:").then_some(())
+        })
+        .0;
+        let (title, content) = split_title(&unescape_markdown(&text));
+        entries.push(ForeignEntry {
+            source: source.clone(),
+            title: title.unwrap_or_default(),
+            content,
+            created_at,
+            updated_at: entry.modified_date.as_deref().and_then(parse_date),
+            captured_at: None,
+            tags: normalize_tags(&entry.tags),
+            mood: None,
+            music_title: None,
+            photos,
+            missing_photos,
+        });
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+struct JourneyEntry {
+    #[serde(default)]
+    text: String,
+    date_journal: i64,
+    date_modified: Option<i64>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    photos: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    music_title: Option<String>,
+    music_artist: Option<String>,
+}
+
+fn journey(name: &str, data: &[u8], bundle: &Bundle) -> Result<ForeignEntry, String> {
+    let entry: JourneyEntry =
+        serde_json::from_slice(data).map_err(|_| "不是 Journey 导出文件".to_string())?;
+    let created_at = from_millis(entry.date_journal)
+        .ok_or_else(|| format!("无法识别的日期: {}", entry.date_journal))?;
+    let text = match entry.kind.as_deref() {
+        Some("html") => html_to_text(&entry.text),
+        _ => entry.text,
+    };
+    let (title, content) = split_title(&text);
+    let dir = parent(name);
+    let mut photos = Vec::new();
+    let mut missing_photos = Vec::new();
+    for photo in &entry.photos {
+        match resolve(&dir, photo).filter(|path| bundle.contains(path)) {
+            Some(path) => photos.push(path),
+            None => missing_photos.push(photo.clone()),
+        }
+    }
+    let music_title = match (entry.music_title, entry.music_artist) {
+        (Some(title), Some(artist)) if !artist.trim().is_empty() => {
+            Some(format!("{} - {}", title.trim(), artist.trim()))
+        }
+        (title, _) => title.filter(|t| !t.trim().is_empty()),
+    };
+    Ok(ForeignEntry {
+        source: name.to_string(),
+        title: title.unwrap_or_default(),
+        content,
+        created_at,
+        updated_at: entry.date_modified.and_then(from_millis),
+        captured_at: None,
+        tags: normalize_tags(&entry.tags),
+        mood: None,
+        music_title,
+        photos,
+        missing_photos,
+    })
+}
+
+fn markdown(
+    name: &str,
+    data: &[u8],
+    bundle: &Bundle,
+    settings: &Settings,
+) -> Result<ForeignEntry, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "不是 UTF-8 编码的文本".to_string())?;
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let (fields, body) = front_matter(&text);
+    let field = |key: &str| fields.get(key).and_then(|values| values.first());
+    if field("sealed").is_some_and(|v| v == "true") {
+        return Err("尘封中的记录没有可导入的内容".to_string());
+    }
+
+    let stem = Path::new(name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let file_date = stem.get(..10).and_then(|prefix| {
+        NaiveDate::parse_from_str(prefix, "%Y-%m-%d")
+            .ok()
+            .and_then(|_| parse_date(prefix))
+    });
+    let created_at = match field("date").or_else(|| field("created")) {
+        Some(value) => parse_date(value).ok_or_else(|| format!("无法识别的日期: {}", value))?,
+        None => file_date
+            .clone()
+            .ok_or_else(|| "缺少日期，请在 front matter 中填写 date".to_string())?,
+    };
+
+    // 正文中指向导出内本地文件的图片链接改为附件
+    let dir = parent(name);
+    let (body, links) = remove_images(body, |target| {
+        let local = !target.contains("://") && !target.starts_with("data:");
+        local
+            .then(|| resolve(&dir, &target.replace("%20", " ")))
+            .flatten()
+    });
+    let (photos, missing_photos) = links.into_iter().partition(|path| bundle.contains(path));
+
+    let (heading, content) = split_title(&body);
+    let (title, content) = match (field("title"), heading) {
+        // Markdown 导出在 front matter 之后重复了一遍标题
+        (Some(title), Some(heading)) if *title == heading => (title.clone(), content),
+        (Some(title), Some(heading)) => (title.clone(), format!("# {}\n\n{}", heading, content)),
+        (Some(title), None) => (title.clone(), content),
+        (None, Some(heading)) => (heading, content),
+        (None, None) => {
+            let name = stem
+                .get(10..)
+                .filter(|_| file_date.is_some())
+                .unwrap_or(stem);
+            let title = name.trim_matches(['-', '_', ' ']).replace(['-', '_'], " ");
+            (title, content)
+        }
+    };
+
+    // Markdown 导出把空标题写作“无标题”
+    let title = match title.as_str() {
+        document::UNTITLED => String::new(),
+        _ => title,
+    };
+
+    // 心情按色板中的名称或 id 匹配，找不到时忽略
+    let mood = field("mood").and_then(|value| {
+        let option = settings
+            .mood_palette
+            .iter()
+            .find(|o| o.label == *value || o.id == *value)?;
+        let levels = settings.mood_intensity_levels;
+        let intensity = field("mood_intensity")
+            .and_then(|v| v.parse::<u8>().ok())
+            .map_or(levels.div_ceil(2), |v| v.clamp(1, levels));
+        Some(Mood {
+            id: option.id.clone(),
+            intensity,
+        })
+    });
+    Ok(ForeignEntry {
+        source: name.to_string(),
+        title,
+        content,
+        created_at,
+        updated_at: field("updated").and_then(|v| parse_date(v)),
+        captured_at: field("captured").and_then(|v| parse_date(v)),
+        tags: normalize_tags(fields.get("tags").map_or(&[], Vec::as_slice)),
+        mood,
+        music_title: field("music").cloned(),
+        photos,
+        missing_photos,
+    })
+}
+
+// 解析 --- 包围的 YAML front matter，只支持标量、行内列表与 "- " 开头的块列表
+fn front_matter(text: &str) -> (HashMap<String, Vec<String>>, &str) {
+    let mut fields = HashMap::new();
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return (fields, text);
+    };
+    let mut header_end = None;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            header_end = Some((offset, offset + line.len()));
+            break;
+        }
+        offset += line.len();
+    }
+    let Some((header_end, body_start)) = header_end else {
+        return (fields, text);
+    };
+
+    let mut current: Option<String> = None;
+    for line in rest[..header_end].lines() {
+        if let (Some(key), Some(item)) = (&current, line.trim_start().strip_prefix("- ")) {
+            fields
+                .entry(key.clone())
+                .or_insert_with(Vec::new)
+                .push(unquote(item));
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let values = if value.starts_with('[') && value.ends_with(']') {
+            serde_json::from_str::<Vec<String>>(value).unwrap_or_else(|_| {
+                value[1..value.len() - 1]
+                    .split(',')
+                    .map(unquote)
+                    .filter(|v| !v.is_empty())
+                    .collect()
+            })
+        } else if value.is_empty() {
+            Vec::new()
+        } else {
+            vec![unquote(value)]
+        };
+        fields.insert(key.clone(), values);
+        current = Some(key);
+    }
+    (fields, &rest[body_start..])
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.starts_with('"') {
+        if let Ok(value) = serde_json::from_str::<String>(value) {
+            return value;
+        }
+    }
+    match value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        Some(inner) => inner.replace("''", "'"),
+        None => value.trim_matches('"').to_string(),
+    }
+}
+
+// 去掉正文中的 ![...](...) 图片，locate 返回 Some 的链接被去掉并收集其结果
+fn remove_images<T>(text: &str, locate: impl Fn(&str) -> Option<T>) -> (String, Vec<T>) {
+    let mut out = String::with_capacity(text.len());
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("![") {
+        let image = &rest[start..];
+        let target = image
+            .find("](")
+            .filter(|i| !image[2..*i].contains('\n'))
+            .and_then(|open| {
+                let close = image[open..].find(')')? + open;
+                Some((&image[open + 2..close], close))
+            });
+        let Some((target, close)) = target else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            continue;
+        };
+        // 链接可以带标题，也可以用尖括号包围
+        let path = target.split(" \"").next().unwrap_or_default().trim();
+        let path = path.trim_start_matches('<').trim_end_matches('>');
+        out.push_str(&rest[..start]);
+        match locate(path) {
+            Some(item) => found.push(item),
+            None => out.push_str(&image[..=close]),
+        }
+        rest = &image[close + 1..];
+    }
+    out.push_str(rest);
+    (tidy(&out), found)
+}
+
+// 第一行是 Markdown 标题时作为记录标题
+fn split_title(text: &str) -> (Option<String>, String) {
+    let text = text.trim_start();
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+    match first.strip_prefix('#') {
+        Some(heading) if heading.starts_with(['#', ' ']) => {
+            let title = heading.trim_start_matches('#').trim().to_string();
+            (Some(title), tidy(rest))
+        }
+        _ => (None, tidy(text)),
+    }
+}
+
+// Day One 导出时会转义 Markdown 的标点
+fn unescape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|next| next.is_ascii_punctuation()) {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Journey 的富文本日记：块级标签换行，其余标签去掉，再解码常见的字符实体
+fn html_to_text(html: &str) -> String {
+    const BLOCKS: &[&str] = &[
+        "br",
+        "p",
+        "div",
+        "li",
+        "tr",
+        "blockquote",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+    ];
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if BLOCKS.contains(&tag.as_str()) {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    tidy(&decode_entities(&text))
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let entity = &rest[start..];
+        let decoded = entity.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let c = match &entity[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                name => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => name.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &entity[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &entity[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// 去掉行尾空白，连续空行合并为一行，去掉首尾空行
+fn tidy(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        blank = 0;
+        out.push_str(line);
+    }
+    out
+}
+
+// 过长或空白的标签略去，不因个别标签放弃整篇日记
+fn normalize_tags(names: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .iter()
+        .filter_map(|name| tags::normalize(name).ok().flatten())
+        .filter(|name| seen.insert(name.to_lowercase()))
+        .collect()
+}
+
+// RFC 3339 时间，或按本地时区解释的日期与时间
+fn parse_date(value: &str) -> Option<String> {
+    let value = value.trim();
+    let time = match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time.with_timezone(&Utc),
+        Err(_) => {
+            let naive = DATE_TIME_FORMATS
+                .iter()
+                .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+                .or_else(|| {
+                    DATE_FORMATS
+                        .iter()
+                        .find_map(|f| NaiveDate::parse_from_str(value, f).ok())
+                        .and_then(|date| date.and_hms_opt(0, 0, 0))
+                })?;
+            Local
+                .from_local_datetime(&naive)
+                .earliest()?
+                .with_timezone(&Utc)
+        }
+    };
+    Some(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn from_millis(millis: i64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn list_dir(dir: &Path, prefix: &str, depth: usize, names: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("无法读取文件夹: {}", e))?;
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if is_hidden(&name) {
+            continue;
+        }
+        let path = entry.path();
+        let relative = format!("{}{}", prefix, name);
+        if path.is_dir() {
+            if depth < MAX_DEPTH {
+                list_dir(&path, &format!("{}/", relative), depth + 1, names)?;
+            }
+        } else {
+            names.push(relative);
+        }
+    }
+    Ok(())
+}
+
+// 隐藏文件与 macOS 打包 zip 时附带的 __MACOSX 目录
+fn is_hidden(name: &str) -> bool {
+    name.split('/')
+        .any(|part| part.starts_with('.') || part == "__MACOSX")
+}
+
+// 与媒体库入库时的判断一致：扩展名须为支持的图片格式
+fn is_image(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| mime_for_extension(&ext.to_ascii_lowercase()))
+        .is_some_and(|mime| mime.starts_with("image/"))
+}
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+// 文件所在的目录，以 / 结尾；位于根目录时为空
+fn parent(name: &str) -> String {
+    name.rsplit_once('/')
+        .map(|(dir, _)| format!("{}/", dir))
+        .unwrap_or_default()
+}
+
+// 解析相对链接中的 . 与 ..，绝对路径或越出导出根目录时返回 None
+fn resolve(dir: &str, link: &str) -> Option<String> {
+    if link.starts_with('/') || link.contains('\\') || link.contains(':') {
+        return None;
+    }
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in link.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRONT_MATTER: &str = "---\n\
+title: \"雨后: 散步\"\n\
+Date: 2024-03-05 08:30\n\
+tags: [旅行, \"家人\"]\n\
+mood: 开心\n\
+mood_intensity: 4\n\
+aliases:\n\
+  - 'it''s'\n\
+  - \"b\"\n\
+---\n\
+# 雨后: 散步\n\n正文\n";
+
+    const JOURNEY_HTML: &str = "<p>第一段<br/>第二行</p><div class=\"x\">A &amp; B &lt;c&gt; &#20320;&#x597D;</div><ul><li>一</li><li>二</li></ul>";
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("import-{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn local(value: &str) -> String {
+        let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap();
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    #[test]
+    fn reads_scalars_and_lists_from_front_matter() {
+        let (fields, body) = front_matter(FRONT_MATTER);
+        assert_eq!(fields["title"], vec!["雨后: 散步"]);
+        assert_eq!(fields["date"], vec!["2024-03-05 08:30"]);
+        assert_eq!(fields["tags"], vec!["旅行", "家人"]);
+        assert_eq!(fields["mood_intensity"], vec!["4"]);
+        assert_eq!(fields["aliases"], vec!["it's", "b"]);
+        assert_eq!(body, "# 雨后: 散步\n\n正文\n");
+    }
+
+    #[test]
+    fn text_without_closed_front_matter_is_all_body() {
+        let (fields, body) = front_matter("正文\n---\n");
+        assert!(fields.is_empty());
+        assert_eq!(body, "正文\n---\n");
+        let (fields, body) = front_matter("---\ntitle: x\n正文");
+        assert!(fields.is_empty());
+        assert_eq!(body, "---\ntitle: x\n正文");
+    }
+
+    #[test]
+    fn parses_dates_with_and_without_time_zones() {
+        assert_eq!(
+            parse_date("2024-03-05T08:30:00+08:00").as_deref(),
+            Some("2024-03-05T00:30:00.000Z")
+        );
+        let expected = local("2024-03-05 08:30:00");
+        assert_eq!(parse_date("2024-03-05 08:30").unwrap(), expected);
+        assert_eq!(parse_date("2024/03/05 08:30").unwrap(), expected);
+        assert_eq!(
+            parse_date(" 2024.03.05 ").unwrap(),
+            local("2024-03-05 00:00:00")
+        );
+        assert_eq!(parse_date("昨天"), None);
+        assert_eq!(parse_date("2024-02-30"), None);
+    }
+
+    #[test]
+    fn converts_journey_html_to_text() {
+        assert_eq!(
+            html_to_text(JOURNEY_HTML),
+            "第一段\n第二行\n\nA & B <c> 你好\n\n一\n\n二"
+        );
+        assert_eq!(html_to_text("未闭合 <b"), "未闭合");
+    }
+
+    #[test]
+    fn decodes_known_entities_and_keeps_the_rest() {
+        assert_eq!(decode_entities("&quot;a&apos;&nbsp;&#65;&#x42;"), "\"a' AB");
+        assert_eq!(
+            decode_entities("AT&T &unknown; &#xZZ; &"),
+            "AT&T &unknown; &#xZZ; &"
+        );
+        assert_eq!(decode_entities("&#1114112;"), "&#1114112;");
+    }
+
+    #[test]
+    fn removes_only_the_images_that_were_located() {
+        let text = "开头 ![a](photos/a.jpg \"标题\")\n\n![b](<my photo.png>) ![远程](https://x/y.png)\n![未闭合";
+        let (body, found) = remove_images(text, |path| {
+            (!path.contains("://")).then(|| path.to_string())
+        });
+        assert_eq!(found, vec!["photos/a.jpg", "my photo.png"]);
+        assert_eq!(body, "开头\n\n ![远程](https://x/y.png)\n![未闭合");
+    }
+
+    #[test]
+    fn resolves_relative_links_inside_the_export() {
+        assert_eq!(
+            resolve("entries/", "../photos/a.jpg").as_deref(),
+            Some("photos/a.jpg")
+        );
+        assert_eq!(resolve("", "./a/./b.png").as_deref(), Some("a/b.png"));
+        assert_eq!(resolve("", "../a.png"), None);
+        assert_eq!(resolve("a/", "/etc/passwd"), None);
+        assert_eq!(resolve("a/", "C:/x.png"), None);
+        assert_eq!(resolve("a/", "..\\x.png"), None);
+    }
+
+    #[test]
+    fn fingerprints_ignore_whitespace_but_not_seconds() {
+        let a = fingerprint("2024-03-05T08:30:00.400Z", "标题", "第一行\n 第二行");
+        let b = fingerprint("2024-03-05T16:30:00.900+08:00", "标 题", "第一行第二行");
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        assert_ne!(
+            a,
+            fingerprint("2024-03-05T08:30:01Z", "标题", "第一行第二行")
+        );
+        assert_eq!(fingerprint("not a date", "标题", ""), None);
+    }
+
+    #[test]
+    fn parses_a_markdown_folder() {
+        let dir = temp_dir();
+        fs::create_dir_all(dir.join("entries")).unwrap();
+        fs::create_dir_all(dir.join("photos")).unwrap();
+        fs::write(
+            dir.join("entries/2024-03-05-雨后.md"),
+            FRONT_MATTER.replace(
+                "正文\n",
+                "正文\n\n![](../photos/a.jpg)\n![](../photos/missing.jpg)\n",
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join("entries/2024-03-06-晴天.md"), "只有正文").unwrap();
+        fs::write(
+            dir.join("entries/.hidden.md"),
+            "---\ndate: 2024-01-01\n---\n",
+        )
+        .unwrap();
+        fs::write(dir.join("photos/a.jpg"), b"jpeg").unwrap();
+
+        let mut bundle = Bundle::open(&dir).unwrap();
+        let parsed = parse(&mut bundle, None, &Settings::default()).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(parsed.format, JournalFormat::Markdown);
+        assert!(parsed.skipped.is_empty());
+        assert_eq!(parsed.entries.len(), 2);
+        let first = &parsed.entries[0];
+        assert_eq!(first.title, "雨后: 散步");
+        assert_eq!(first.content, "正文");
+        assert_eq!(first.created_at, local("2024-03-05 08:30:00"));
+        assert_eq!(first.tags, vec!["旅行", "家人"]);
+        assert_eq!(first.mood.as_ref().map(|m| m.intensity), Some(4));
+        assert_eq!(first.photos, vec!["photos/a.jpg"]);
+        assert_eq!(first.missing_photos, vec!["photos/missing.jpg"]);
+        let second = &parsed.entries[1];
+        assert_eq!(second.title, "晴天");
+        assert_eq!(second.created_at, local("2024-03-06 00:00:00"));
+    }
+}
@@ -0,0 +1,386 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::models::EmotionalRecord;
+use crate::resurface::{self, local_date, OnThisDay};
+
+// 补齐空桶时最多生成的桶数，防止传入过大的时间范围
+const MAX_BUCKETS: usize = 5000;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Granularity {
+    #[default]
+    Day,
+    // 以周一为一周的开始
+    Week,
+    Month,
+}
+
+// 统计范围，起止为本地日历日（含首尾），缺省时不限
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InsightRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub granularity: Granularity,
+}
+
+impl InsightRange {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Insights {
+    pub buckets: Vec<Bucket>,
+    pub moods: Vec<MoodCount>,
+    pub streaks: Streaks,
+    // 未尘封记录正文的平均字数，尘封记录的正文不可读
+    pub average_length: f64,
+    pub counts: Counts,
+    pub on_this_day: Vec<OnThisDay>,
+}
+
+// 一个时间桶内的记录数与心情分布，start 为桶的第一天
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    pub start: NaiveDate,
+    pub count: usize,
+    pub moods: BTreeMap<String, usize>,
+    pub average_intensity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoodCount {
+    pub id: String,
+    pub count: usize,
+    pub average_intensity: f64,
+}
+
+// 连续写作的天数：current 为截至今天（今天尚未写时截至昨天）的连续天数
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Streaks {
+    pub current: u32,
+    pub longest: u32,
+    pub longest_end: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Counts {
+    pub total: usize,
+    pub sealed: usize,
+    pub unsealed: usize,
+    // 已设置自动销毁时间、尚未销毁
+    pub pending_destruction: usize,
+    // 范围内已被自动销毁的记录数，只统计销毁时间
+    pub destroyed: usize,
+}
+
+// 在后端汇总统计，前端只需绘图而无需加载全部记录。
+// 记录按创建时间的本地日期归入范围与时间桶；连续写作天数与“那年今日”不受范围限制
+pub fn compute(
+    records: &[EmotionalRecord],
+    destructions: &[String],
+    range: &InsightRange,
+    today: NaiveDate,
+) -> Result<Insights, String> {
+    let dated: Vec<(NaiveDate, &EmotionalRecord)> = records
+        .iter()
+        .filter_map(|r| local_date(&r.created_at).map(|date| (date, r)))
+        .collect();
+    let selected: Vec<(NaiveDate, &EmotionalRecord)> = dated
+        .iter()
+        .copied()
+        .filter(|(date, _)| range.contains(*date))
+        .collect();
+
+    let unsealed: Vec<&EmotionalRecord> = selected
+        .iter()
+        .map(|(_, r)| *r)
+        .filter(|r| !r.is_sealed)
+        .collect();
+    let average_length = if unsealed.is_empty() {
+        0.0
+    } else {
+        let total: usize = unsealed.iter().map(|r| r.content.chars().count()).sum();
+        total as f64 / unsealed.len() as f64
+    };
+
+    let counts = Counts {
+        total: selected.len(),
+        sealed: selected.len() - unsealed.len(),
+        unsealed: unsealed.len(),
+        pending_destruction: selected
+            .iter()
+            .filter(|(_, r)| r.auto_destroy_at.is_some())
+            .count(),
+        destroyed: destructions
+            .iter()
+            .filter_map(|t| local_date(t))
+            .filter(|date| range.contains(*date))
+            .count(),
+    };
+
+    Ok(Insights {
+        buckets: buckets(&selected, range)?,
+        moods: mood_counts(selected.iter().map(|(_, r)| *r)),
+        streaks: streaks(dated.iter().map(|(date, _)| *date).collect(), today),
+        average_length,
+        counts,
+        on_this_day: resurface::on_this_day(records, today),
+    })
+}
+
+// 按粒度分桶，范围内没有记录的桶同样输出，便于直接绘制连续的时间轴
+fn buckets(
+    selected: &[(NaiveDate, &EmotionalRecord)],
+    range: &InsightRange,
+) -> Result<Vec<Bucket>, String> {
+    let first = range
+        .from
+        .or_else(|| selected.iter().map(|(d, _)| *d).min());
+    let last = range.to.or_else(|| selected.iter().map(|(d, _)| *d).max());
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) if first <= last => (first, last),
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut grouped: BTreeMap<NaiveDate, Vec<&EmotionalRecord>> = BTreeMap::new();
+    for (date, record) in selected {
+        grouped
+            .entry(bucket_start(*date, range.granularity))
+            .or_default()
+            .push(record);
+    }
+
+    let mut buckets = Vec::new();
+    let mut start = bucket_start(first, range.granularity);
+    while start <= last {
+        if buckets.len() >= MAX_BUCKETS {
+            return Err("统计范围过大，请缩小范围或改用更粗的粒度".to_string());
+        }
+        let records = grouped.remove(&start).unwrap_or_default();
+        let moods = mood_counts(records.iter().copied());
+        let rated: usize = moods.iter().map(|m| m.count).sum();
+        buckets.push(Bucket {
+            start,
+            count: records.len(),
+            average_intensity: (rated > 0).then(|| {
+                moods
+                    .iter()
+                    .map(|m| m.average_intensity * m.count as f64)
+                    .sum::<f64>()
+                    / rated as f64
+            }),
+            moods: moods.into_iter().map(|m| (m.id, m.count)).collect(),
+        });
+        start = next_bucket(start, range.granularity);
+    }
+    Ok(buckets)
+}
+
+// 心情分布，次数多的在前
+fn mood_counts<'a>(records: impl Iterator<Item = &'a EmotionalRecord>) -> Vec<MoodCount> {
+    let mut totals: BTreeMap<&str, (usize, u32)> = BTreeMap::new();
+    for mood in records.filter_map(|r| r.mood.as_ref()) {
+        let entry = totals.entry(&mood.id).or_default();
+        entry.0 += 1;
+        entry.1 += u32::from(mood.intensity);
+    }
+    let mut counts: Vec<MoodCount> = totals
+        .into_iter()
+        .map(|(id, (count, intensity))| MoodCount {
+            id: id.to_string(),
+            count,
+            average_intensity: f64::from(intensity) / count as f64,
+        })
+        .collect();
+    counts.sort_by_key(|m| Reverse(m.count));
+    counts
+}
+
+fn streaks(dates: BTreeSet<NaiveDate>, today: NaiveDate) -> Streaks {
+    let mut result = Streaks::default();
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &date in &dates {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(date) => run + 1,
+            _ => 1,
+        };
+        if run > result.longest {
+            result.longest = run;
+            result.longest_end = Some(date);
+        }
+        previous = Some(date);
+    }
+
+    let mut day = if dates.contains(&today) {
+        Some(today)
+    } else {
+        today.pred_opt()
+    };
+    while let Some(d) = day.filter(|d| dates.contains(d)) {
+        result.current += 1;
+        day = d.pred_opt();
+    }
+    result
+}
+
+fn bucket_start(date: NaiveDate, granularity: Granularity) -> NaiveDate {
+    match granularity {
+        Granularity::Day => date,
+        Granularity::Week => date - Days::new(u64::from(date.weekday().num_days_from_monday())),
+        Granularity::Month => date.with_day(1).unwrap_or(date),
+    }
+}
+
+fn next_bucket(start: NaiveDate, granularity: Granularity) -> NaiveDate {
+    let next = match granularity {
+        Granularity::Day => start.checked_add_days(Days::new(1)),
+        Granularity::Week => start.checked_add_days(Days::new(7)),
+        Granularity::Month => start.checked_add_months(Months::new(1)),
+    };
+    next.unwrap_or(NaiveDate::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Mood;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    // 取当天正午，本地时区偏移不会改变日期
+    fn record(day: &str, mood: Option<(&str, u8)>) -> EmotionalRecord {
+        EmotionalRecord {
+            id: day.to_string(),
+            created_at: format!("{}T12:00:00Z", day),
+            updated_at: format!("{}T12:00:00Z", day),
+            mood: mood.map(|(id, intensity)| Mood {
+                id: id.to_string(),
+                intensity,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn dates(days: &[&str]) -> BTreeSet<NaiveDate> {
+        days.iter().map(|d| date(d)).collect()
+    }
+
+    #[test]
+    fn weeks_start_on_monday_and_months_on_the_first() {
+        assert_eq!(
+            bucket_start(date("2024-01-03"), Granularity::Week),
+            date("2024-01-01")
+        );
+        assert_eq!(
+            bucket_start(date("2024-01-07"), Granularity::Week),
+            date("2024-01-01")
+        );
+        assert_eq!(
+            bucket_start(date("2024-02-29"), Granularity::Month),
+            date("2024-02-01")
+        );
+        assert_eq!(
+            next_bucket(date("2024-12-01"), Granularity::Month),
+            date("2025-01-01")
+        );
+    }
+
+    #[test]
+    fn empty_buckets_are_filled_across_the_range() {
+        let records = [
+            record("2024-01-02", Some(("happy", 4))),
+            record("2024-01-02", Some(("happy", 2))),
+            record("2024-01-20", None),
+        ];
+        let selected: Vec<_> = records
+            .iter()
+            .map(|r| (local_date(&r.created_at).unwrap(), r))
+            .collect();
+        let range = InsightRange {
+            from: Some(date("2023-12-30")),
+            to: Some(date("2024-01-20")),
+            granularity: Granularity::Week,
+        };
+        let buckets = buckets(&selected, &range).unwrap();
+        let starts: Vec<NaiveDate> = buckets.iter().map(|b| b.start).collect();
+        assert_eq!(
+            starts,
+            ["2023-12-25", "2024-01-01", "2024-01-08", "2024-01-15"].map(date)
+        );
+        let counts: Vec<usize> = buckets.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [0, 2, 0, 1]);
+        assert_eq!(buckets[1].moods.get("happy"), Some(&2));
+        assert_eq!(buckets[1].average_intensity, Some(3.0));
+        assert_eq!(buckets[3].average_intensity, None);
+    }
+
+    #[test]
+    fn oversized_ranges_are_rejected() {
+        let range = InsightRange {
+            from: Some(date("1900-01-01")),
+            to: Some(date("2100-01-01")),
+            granularity: Granularity::Day,
+        };
+        assert!(buckets(&[], &range).is_err());
+    }
+
+    #[test]
+    fn current_streak_counts_back_from_today_or_yesterday() {
+        let days = dates(&["2024-03-01", "2024-03-02", "2024-03-04", "2024-03-05"]);
+        assert_eq!(streaks(days.clone(), date("2024-03-05")).current, 2);
+        assert_eq!(streaks(days.clone(), date("2024-03-06")).current, 2);
+        assert_eq!(streaks(days, date("2024-03-07")).current, 0);
+    }
+
+    #[test]
+    fn longest_streak_spans_month_and_leap_day_boundaries() {
+        let days = dates(&[
+            "2024-01-10",
+            "2024-02-28",
+            "2024-02-29",
+            "2024-03-01",
+            "2024-03-03",
+        ]);
+        let streaks = streaks(days, date("2024-06-01"));
+        assert_eq!(streaks.longest, 3);
+        assert_eq!(streaks.longest_end, Some(date("2024-03-01")));
+        assert_eq!(streaks.current, 0);
+    }
+
+    #[test]
+    fn sealed_records_are_counted_but_not_measured() {
+        let mut sealed = record("2024-05-01", None);
+        sealed.is_sealed = true;
+        let mut open = record("2024-05-02", Some(("calm", 1)));
+        open.content = "四个汉字".to_string();
+        let insights = compute(
+            &[sealed, open],
+            &["2024-05-03T12:00:00Z".to_string()],
+            &InsightRange::default(),
+            date("2024-05-02"),
+        )
+        .unwrap();
+        assert_eq!(insights.counts.total, 2);
+        assert_eq!(insights.counts.sealed, 1);
+        assert_eq!(insights.counts.destroyed, 1);
+        assert_eq!(insights.average_length, 4.0);
+        assert_eq!(insights.moods[0].id, "calm");
+        assert_eq!(insights.streaks.current, 2);
+    }
+}
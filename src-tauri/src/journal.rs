@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, Key};
+use crate::keyring::StoredRecord;
+
+// 帧头：4 字节小端长度，其后为主密钥加密的 JSON 条目
+const FRAME_HEADER: usize = 4;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum JournalOp {
+    // 记录以密钥环中的数据密钥加密，销毁密钥后日志中的副本同样无法解开
+    Upsert { record: StoredRecord },
+    Delete { id: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub op: JournalOp,
+}
+
+// 读取旧版预写日志 records.journal。日志已由 records.db 取代，不再写入，
+// 只在迁移时重放一次，迁移完成后删除。
+// 末尾被截断或无法解密的条目视为未完成的写入，读到此处为止
+pub fn read_entries(path: &Path, key: &Key) -> Result<Vec<JournalEntry>, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("读取日志失败: {}", e)),
+    };
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some((entry, next)) = read_frame(key, &data, offset) {
+        entries.push(entry);
+        offset = next;
+    }
+    Ok(entries)
+}
+
+fn read_frame(key: &Key, data: &[u8], offset: usize) -> Option<(JournalEntry, usize)> {
+    let header = data.get(offset..offset + FRAME_HEADER)?;
+    let len = u32::from_le_bytes(header.try_into().ok()?) as usize;
+    let start = offset + FRAME_HEADER;
+    let body = data.get(start..start.checked_add(len)?)?;
+    let plaintext = Zeroizing::new(crypto::decrypt(key, body).ok()?);
+    let entry = serde_json::from_slice(&plaintext).ok()?;
+    Some((entry, start + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(key: &Key, json: &str) -> Vec<u8> {
+        let body = crypto::encrypt(key, json.as_bytes()).unwrap();
+        let mut out = (body.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(&body);
+        out
+    }
+
+    fn write_journal(data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("journal-{:016x}", rand::random::<u64>()));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_entries_until_truncated_tail() {
+        let key = crypto::generate_key();
+        let mut data = frame(&key, r#"{"sequence":1,"op":"delete","id":"a"}"#);
+        data.extend(frame(&key, r#"{"sequence":2,"op":"delete","id":"b"}"#));
+        let third = frame(&key, r#"{"sequence":3,"op":"delete","id":"c"}"#);
+        data.extend_from_slice(&third[..third.len() - 1]);
+        let path = write_journal(&data);
+
+        let entries = read_entries(&path, &key).unwrap();
+        let sequences: Vec<u64> = entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, [1, 2]);
+        assert!(matches!(&entries[1].op, JournalOp::Delete { id } if id == "b"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stops_at_entries_it_cannot_decrypt() {
+        let key = crypto::generate_key();
+        let mut data = frame(&key, r#"{"sequence":1,"op":"delete","id":"a"}"#);
+        data.extend(frame(
+            &crypto::generate_key(),
+            r#"{"sequence":2,"op":"delete","id":"b"}"#,
+        ));
+        let path = write_journal(&data);
+        assert_eq!(read_entries(&path, &key).unwrap().len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_journal_has_no_entries() {
+        let path = std::env::temp_dir().join("journal-missing");
+        let entries = read_entries(&path, &crypto::generate_key()).unwrap();
+        assert!(entries.is_empty());
+    }
+}
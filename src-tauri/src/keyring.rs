@@ -0,0 +1,443 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::crypto::{self, Key, KEY_LEN};
+use crate::models::{EmotionalRecord, Mood};
+use crate::schema;
+use crate::tags::StoredTag;
+
+const KEYRING_VERSION: u32 = 2;
+
+// 每条记录的数据密钥（DEK），以及每个媒体文件与每个标签各自的密钥。密钥环单独保存为 keyring.bin，
+// 不随导出一起复制：销毁某条记录的密钥后，任何副本中的密文都无法再解开；
+// 媒体与标签的密钥在最后一条引用它们的记录被销毁时一并销毁。
+// 备份中的密钥环副本随后由 backup::shred 删除同样的密钥
+pub struct Keyring {
+    path: PathBuf,
+    keys: HashMap<String, Zeroizing<Key>>,
+    // 以媒体内容哈希为键，同一内容的媒体只有一个密钥
+    media: HashMap<String, Zeroizing<Key>>,
+    // 以标签的查找键为键
+    tags: HashMap<String, Zeroizing<Key>>,
+    // 已销毁但可能仍留在备份中的密钥，从各份备份中删除后清除
+    shredded: Shredded,
+    dirty: bool,
+}
+
+// 已销毁密钥的 id，按记录、媒体与标签分开
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shredded {
+    pub records: BTreeSet<String>,
+    pub media: BTreeSet<String>,
+    pub tags: BTreeSet<String>,
+}
+
+impl Shredded {
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.media.is_empty() && self.tags.is_empty()
+    }
+}
+
+// keyring.bin 解密后的内容，密钥以 base64 保存。第 1 版只有记录密钥，整个文件就是 id -> 密钥的映射
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum KeyringFile {
+    Current {
+        version: u32,
+        records: HashMap<String, String>,
+        media: HashMap<String, String>,
+        tags: HashMap<String, String>,
+        #[serde(default)]
+        shredded: Shredded,
+    },
+    Legacy(HashMap<String, String>),
+}
+
+// 加密后写入保险库的记录：只有调度所需的元数据保持可读，其余内容由 DEK 加密
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredRecord {
+    pub id: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub is_sealed: bool,
+    pub seal_until: Option<String>,
+    pub auto_destroy_at: Option<String>,
+    pub body: String,
+    // 标签保存在数据库的 tags / record_tags 表中，由记录仓库填充与解密
+    #[serde(skip)]
+    pub tags: Vec<StoredTag>,
+    // 移入回收站的时间，保存在数据库的 trash 表中
+    #[serde(skip)]
+    pub deleted_at: Option<String>,
+}
+
+// 加密的记录内容带有结构版本，解密时经 schema 迁移到当前版本
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordBody {
+    schema_version: u32,
+    title: String,
+    content: String,
+    images: Vec<String>,
+    music_url: Option<String>,
+    music_title: Option<String>,
+    captured_at: Option<String>,
+    mood: Option<Mood>,
+    sealed_payload: Option<String>,
+}
+
+impl Keyring {
+    pub fn load(path: PathBuf, master_key: &Key) -> Result<Self, String> {
+        let mut keyring = Self {
+            path,
+            keys: HashMap::new(),
+            media: HashMap::new(),
+            tags: HashMap::new(),
+            shredded: Shredded::default(),
+            dirty: false,
+        };
+        if keyring.path.exists() {
+            let data = fs::read(&keyring.path).map_err(|e| format!("读取密钥环失败: {}", e))?;
+            let plaintext = Zeroizing::new(crypto::decrypt(master_key, &data)?);
+            let file: KeyringFile =
+                serde_json::from_slice(&plaintext).map_err(|e| format!("密钥环已损坏: {}", e))?;
+            match file {
+                KeyringFile::Current {
+                    version,
+                    records,
+                    media,
+                    tags,
+                    shredded,
+                } => {
+                    if version > KEYRING_VERSION {
+                        return Err("密钥环由更新版本的应用创建，请升级后再打开".to_string());
+                    }
+                    keyring.keys = decode_keys(records)?;
+                    keyring.media = decode_keys(media)?;
+                    keyring.tags = decode_keys(tags)?;
+                    keyring.shredded = shredded;
+                }
+                KeyringFile::Legacy(records) => keyring.keys = decode_keys(records)?,
+            }
+        }
+        Ok(keyring)
+    }
+
+    // 有变化时用主密钥加密后整体覆盖写回
+    pub fn save(&mut self, master_key: &Key) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let file = KeyringFile::Current {
+            version: KEYRING_VERSION,
+            records: encode_keys(&self.keys),
+            media: encode_keys(&self.media),
+            tags: encode_keys(&self.tags),
+            shredded: self.shredded.clone(),
+        };
+        let plaintext = Zeroizing::new(serde_json::to_vec(&file).map_err(|e| e.to_string())?);
+        let data = crypto::encrypt(master_key, &plaintext)?;
+        atomic::write_file(&self.path, &data).map_err(|e| format!("保存密钥环失败: {}", e))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // 销毁记录的数据密钥，此后该记录的所有密文副本都不可恢复
+    pub fn destroy(&mut self, id: &str) {
+        if self.keys.remove(id).is_some() {
+            self.shredded.records.insert(id.to_string());
+            self.dirty = true;
+        }
+    }
+
+    pub fn shredded(&self) -> &Shredded {
+        &self.shredded
+    }
+
+    // 已从全部备份中删除的密钥不再记录
+    pub fn forget_shredded(&mut self, done: &Shredded) {
+        for (shredded, done) in [
+            (&mut self.shredded.records, &done.records),
+            (&mut self.shredded.media, &done.media),
+            (&mut self.shredded.tags, &done.tags),
+        ] {
+            for id in done {
+                self.dirty |= shredded.remove(id);
+            }
+        }
+    }
+
+    pub fn encrypt_record(&mut self, record: &EmotionalRecord) -> Result<StoredRecord, String> {
+        let key = self.key_for(&record.id);
+        let body = RecordBody {
+            schema_version: schema::SCHEMA_VERSION,
+            title: record.title.clone(),
+            content: record.content.clone(),
+            images: record.images.clone(),
+            music_url: record.music_url.clone(),
+            music_title: record.music_title.clone(),
+            captured_at: record.captured_at.clone(),
+            mood: record.mood.clone(),
+            sealed_payload: record.sealed_payload.clone(),
+        };
+        let plaintext = serde_json::to_vec(&body).map_err(|e| e.to_string())?;
+
+        Ok(StoredRecord {
+            id: record.id.clone(),
+            created_at: record.created_at.clone(),
+            updated_at: record.updated_at.clone(),
+            is_sealed: record.is_sealed,
+            seal_until: record.seal_until.clone(),
+            auto_destroy_at: record.auto_destroy_at.clone(),
+            body: crypto::encrypt_to_string(&key, &plaintext)?,
+            tags: Vec::new(),
+            deleted_at: None,
+        })
+    }
+
+    // 密钥已被销毁时返回 None
+    pub fn decrypt_record(&self, stored: StoredRecord) -> Result<Option<EmotionalRecord>, String> {
+        let key = match self.keys.get(&stored.id) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let plaintext = crypto::decrypt_from_string(key, &stored.body)?;
+        let mut body: Map<String, Value> =
+            serde_json::from_slice(&plaintext).map_err(|e| format!("记录内容已损坏: {}", e))?;
+        // 引入结构版本之前写入的内容为第 1 版
+        let version = body
+            .remove("schemaVersion")
+            .and_then(|v| v.as_u64())
+            .and_then(|v| u32::try_from(v).ok())
+            .unwrap_or(1);
+
+        // 明文元数据与解密后的内容合并为一条完整记录
+        body.insert("id".to_string(), stored.id.into());
+        body.insert("createdAt".to_string(), stored.created_at.into());
+        body.insert("updatedAt".to_string(), stored.updated_at.into());
+        body.insert("isSealed".to_string(), stored.is_sealed.into());
+        body.insert("sealUntil".to_string(), stored.seal_until.into());
+        body.insert("autoDestroyAt".to_string(), stored.auto_destroy_at.into());
+        schema::upgrade_record(Value::Object(body), version).map(Some)
+    }
+
+    // 以记录的数据密钥加密附属于该记录的数据（如历史版本）
+    pub fn encrypt_for(&mut self, id: &str, plaintext: &[u8]) -> Result<String, String> {
+        let key = self.key_for(id);
+        crypto::encrypt_to_string(&key, plaintext)
+    }
+
+    // 密钥已被销毁时返回 None
+    pub fn decrypt_for(&self, id: &str, data: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.keys.get(id) {
+            Some(key) => crypto::decrypt_from_string(key, data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // 媒体文件的密钥，首次使用时生成
+    pub fn media_key(&mut self, content_id: &str) -> Zeroizing<Key> {
+        self.shredded.media.remove(content_id);
+        entry(&mut self.media, &mut self.dirty, content_id)
+    }
+
+    // 密钥已被销毁（或尚未生成）时返回 None
+    pub fn find_media_key(&self, content_id: &str) -> Option<Zeroizing<Key>> {
+        self.media.get(content_id).cloned()
+    }
+
+    // 标签名的密钥，首次使用时生成
+    pub fn tag_key(&mut self, lookup: &str) -> Zeroizing<Key> {
+        self.shredded.tags.remove(lookup);
+        entry(&mut self.tags, &mut self.dirty, lookup)
+    }
+
+    pub fn find_tag_key(&self, lookup: &str) -> Option<Zeroizing<Key>> {
+        self.tags.get(lookup).cloned()
+    }
+
+    // 只保留仍被引用的媒体与标签密钥，其余的随之销毁
+    pub fn retain_shared(&mut self, media: &HashSet<String>, tags: &HashSet<String>) {
+        for (id, _) in self.media.extract_if(|id, _| !media.contains(id)) {
+            self.shredded.media.insert(id);
+            self.dirty = true;
+        }
+        for (lookup, _) in self.tags.extract_if(|lookup, _| !tags.contains(lookup)) {
+            self.shredded.tags.insert(lookup);
+            self.dirty = true;
+        }
+    }
+
+    fn key_for(&mut self, id: &str) -> Zeroizing<Key> {
+        entry(&mut self.keys, &mut self.dirty, id)
+    }
+}
+
+// 从备份中的 keyring.bin 删除已销毁的密钥后重新加密。无法解开（如来自另一个保险库）
+// 或其中没有已销毁的密钥时返回 None
+pub fn drop_keys(
+    data: &[u8],
+    master_key: &Key,
+    shredded: &Shredded,
+) -> Result<Option<Vec<u8>>, String> {
+    let Ok(plaintext) = crypto::decrypt(master_key, data).map(Zeroizing::new) else {
+        return Ok(None);
+    };
+    let mut file: KeyringFile =
+        serde_json::from_slice(&plaintext).map_err(|e| format!("密钥环已损坏: {}", e))?;
+    let mut changed = false;
+    let mut remove = |keys: &mut HashMap<String, String>, ids: &BTreeSet<String>| {
+        for id in ids {
+            changed |= keys.remove(id).is_some();
+        }
+    };
+    match &mut file {
+        KeyringFile::Current {
+            records,
+            media,
+            tags,
+            ..
+        } => {
+            remove(records, &shredded.records);
+            remove(media, &shredded.media);
+            remove(tags, &shredded.tags);
+        }
+        KeyringFile::Legacy(records) => remove(records, &shredded.records),
+    }
+    if !changed {
+        return Ok(None);
+    }
+    let plaintext = Zeroizing::new(serde_json::to_vec(&file).map_err(|e| e.to_string())?);
+    crypto::encrypt(master_key, &plaintext).map(Some)
+}
+
+fn entry(keys: &mut HashMap<String, Zeroizing<Key>>, dirty: &mut bool, id: &str) -> Zeroizing<Key> {
+    let key = keys.entry(id.to_string()).or_insert_with(|| {
+        *dirty = true;
+        Zeroizing::new(crypto::generate_key())
+    });
+    key.clone()
+}
+
+fn encode_keys(keys: &HashMap<String, Zeroizing<Key>>) -> HashMap<String, String> {
+    keys.iter()
+        .map(|(id, key)| (id.clone(), STANDARD.encode(key.as_ref())))
+        .collect()
+}
+
+fn decode_keys(
+    encoded: HashMap<String, String>,
+) -> Result<HashMap<String, Zeroizing<Key>>, String> {
+    let mut keys = HashMap::with_capacity(encoded.len());
+    for (id, value) in encoded {
+        let bytes = Zeroizing::new(
+            STANDARD
+                .decode(value)
+                .map_err(|e| format!("密钥环已损坏: {}", e))?,
+        );
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        if bytes.len() != KEY_LEN {
+            return Err("密钥环已损坏".to_string());
+        }
+        key.copy_from_slice(&bytes);
+        keys.insert(id, key);
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("keyring-{:016x}.bin", rand::random::<u64>()))
+    }
+
+    fn record(id: &str) -> EmotionalRecord {
+        EmotionalRecord {
+            id: id.to_string(),
+            title: "标题".to_string(),
+            content: "正文".to_string(),
+            created_at: "2024-05-01T12:00:00Z".to_string(),
+            updated_at: "2024-05-01T12:00:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn destroyed_record_key_makes_its_ciphertext_unreadable() {
+        let path = temp_path();
+        let master_key = crypto::generate_key();
+        let mut keyring = Keyring::load(path.clone(), &master_key).unwrap();
+        let stored = keyring.encrypt_record(&record("record_1_a")).unwrap();
+        let revision = keyring.encrypt_for("record_1_a", b"old").unwrap();
+        let decrypted = keyring.decrypt_record(stored.clone()).unwrap().unwrap();
+        assert_eq!(decrypted.content, "正文");
+
+        keyring.destroy("record_1_a");
+        keyring.save(&master_key).unwrap();
+        assert!(keyring.shredded().records.contains("record_1_a"));
+
+        let reloaded = Keyring::load(path.clone(), &master_key).unwrap();
+        assert!(reloaded.decrypt_record(stored).unwrap().is_none());
+        assert!(reloaded
+            .decrypt_for("record_1_a", &revision)
+            .unwrap()
+            .is_none());
+        assert!(reloaded.shredded().records.contains("record_1_a"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn drop_keys_removes_only_the_shredded_ids() {
+        let path = temp_path();
+        let master_key = crypto::generate_key();
+        let mut keyring = Keyring::load(path.clone(), &master_key).unwrap();
+        for id in ["record_1_a", "record_2_b"] {
+            keyring.encrypt_record(&record(id)).unwrap();
+        }
+        keyring.media_key("media-a");
+        keyring.media_key("media-b");
+        keyring.tag_key("tag-a");
+        keyring.save(&master_key).unwrap();
+        let data = fs::read(&path).unwrap();
+
+        // 没有需要删除的密钥，或密钥环来自另一个保险库
+        let absent = Shredded {
+            records: BTreeSet::from(["record_9_z".to_string()]),
+            ..Shredded::default()
+        };
+        assert!(drop_keys(&data, &master_key, &Shredded::default())
+            .unwrap()
+            .is_none());
+        assert!(drop_keys(&data, &master_key, &absent).unwrap().is_none());
+        let shredded = Shredded {
+            records: BTreeSet::from(["record_1_a".to_string()]),
+            media: BTreeSet::from(["media-b".to_string()]),
+            tags: BTreeSet::from(["tag-a".to_string()]),
+        };
+        assert!(drop_keys(&data, &crypto::generate_key(), &shredded)
+            .unwrap()
+            .is_none());
+
+        let dropped = drop_keys(&data, &master_key, &shredded).unwrap().unwrap();
+        fs::write(&path, dropped).unwrap();
+        let reloaded = Keyring::load(path.clone(), &master_key).unwrap();
+        assert!(reloaded.decrypt_for("record_1_a", "").unwrap().is_none());
+        assert!(reloaded.keys.contains_key("record_2_b"));
+        assert!(reloaded.find_media_key("media-a").is_some());
+        assert!(reloaded.find_media_key("media-b").is_none());
+        assert!(reloaded.find_tag_key("tag-a").is_none());
+        fs::remove_file(path).unwrap();
+    }
+}
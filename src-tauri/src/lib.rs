@@ -1,26 +1,67 @@
-use serde::{Deserialize, Serialize};
+use std::thread;
 
-// 数据结构定义
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EmotionalRecord {
-    pub id: String,
-    pub title: String,
-    pub content: String,
-    pub images: Vec<String>,
-    pub music_url: Option<String>,
-    pub music_title: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
-    pub is_sealed: bool,
-    pub seal_until: Option<String>,
-    pub auto_destroy_at: Option<String>,
-}
+use tauri::Manager;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SealConfig {
-    pub seal_until: Option<String>,
-    pub auto_destroy_at: Option<String>,
-}
+mod archive;
+mod atomic;
+mod background;
+mod backup;
+mod book;
+mod commands;
+mod crypto;
+mod database;
+mod diff;
+mod document;
+mod history;
+mod importer;
+mod insights;
+mod journal;
+mod keyring;
+mod media;
+mod metadata;
+mod models;
+mod paths;
+mod protocol;
+mod resurface;
+mod scheduler;
+mod schema;
+mod seal;
+mod search;
+mod session;
+mod settings;
+mod store;
+mod tags;
+mod thumbnail;
+mod vault;
+
+pub use archive::{
+    ArchiveProgress, ExportOptions, ExportSummary, ImportPreview, ImportReport, ImportStrategy,
+    Manifest,
+};
+pub use backup::{BackupInfo, RestoreReport};
+pub use book::{BookOptions, BookSummary};
+pub use diff::{DiffLine, LineKind};
+pub use document::{DocumentFormat, DocumentOptions, DocumentSummary};
+pub use history::{Revision, RevisionDiff, RevisionSummary};
+pub use importer::{
+    JournalEntryPreview, JournalFormat, JournalImportOptions, JournalImportReport, JournalPreview,
+    SkippedEntry,
+};
+pub use insights::{Granularity, InsightRange, Insights};
+pub use media::{MediaKind, MediaStore};
+pub use models::{
+    CreateRecordInput, EmotionalRecord, EventKind, MemoryEvent, Mood, RecordFilter, SealConfig,
+    TagSummary, TrashedRecord, UpdateRecordInput,
+};
+pub use paths::{AppDataDirInfo, AppPaths};
+pub use resurface::OnThisDay;
+pub use schema::{parse_records, upgrade_record, Envelope, SCHEMA_VERSION};
+pub use seal::{SealEngine, TrustedClock};
+pub use search::{SearchHit, SearchIndex};
+pub use session::{Session, VaultStatus};
+pub use settings::{MoodOption, Settings, SettingsStore};
+pub use store::RecordStore;
+pub use thumbnail::{ImageInfo, ThumbnailInfo};
 
 // Tauri 命令
 #[tauri::command]
@@ -29,9 +70,8 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn get_app_data_dir() -> Result<String, String> {
-    // 简化版本，返回固定路径
-    Ok("pick-up-memories".to_string())
+async fn get_app_data_dir(paths: tauri::State<'_, AppPaths>) -> Result<AppDataDirInfo, String> {
+    Ok(paths.info())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -41,7 +81,67 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_notification::init())
-        .invoke_handler(tauri::generate_handler![greet, get_app_data_dir])
+        .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, |ctx, request, responder| {
+            // 解密媒体文件可能较慢，放到独立线程处理
+            let app = ctx.app_handle().clone();
+            thread::spawn(move || responder.respond(protocol::handle(&app, &request)));
+        })
+        .setup(|app| {
+            // 记录仓库在用户输入口令解锁后才会打开
+            let paths = AppPaths::resolve(&app.path().app_data_dir()?)?;
+            app.manage(Session::new(paths.clone()));
+            app.manage(SettingsStore::open(paths.settings_file()));
+
+            // 后台调度解封、销毁与提醒事件，锁定期间暂停
+            let waker = background::start_scheduler(app.handle(), &paths);
+            app.manage(waker);
+            app.manage(paths);
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            get_app_data_dir,
+            commands::list_records,
+            commands::get_record,
+            commands::create_record,
+            commands::update_record,
+            commands::delete_record,
+            commands::list_trash,
+            commands::restore_record,
+            commands::empty_trash,
+            commands::list_revisions,
+            commands::get_revision,
+            commands::diff_revisions,
+            commands::restore_revision,
+            commands::search_records,
+            commands::get_insights,
+            commands::get_on_this_day,
+            commands::list_tags,
+            commands::add_tag,
+            commands::remove_tag,
+            commands::rename_tag,
+            commands::seal_record,
+            commands::unseal_record,
+            commands::get_vault_status,
+            commands::unlock,
+            commands::lock,
+            commands::change_passphrase,
+            commands::import_media,
+            commands::import_media_file,
+            commands::import_legacy_records,
+            commands::get_thumbnail,
+            commands::export_archive,
+            commands::export_document,
+            commands::export_book,
+            commands::preview_archive,
+            commands::import_archive,
+            commands::preview_journal,
+            commands::import_journal,
+            commands::list_backups,
+            commands::restore_backup,
+            commands::get_settings,
+            commands::update_settings
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
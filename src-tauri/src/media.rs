@@ -0,0 +1,783 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, FixedOffset};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::crypto::{self, Key, NONCE_LEN, TAG_LEN};
+use crate::keyring::Keyring;
+use crate::paths::AppPaths;
+use crate::thumbnail::{self, ImageInfo, DEFAULT_THUMBNAIL_SIZE};
+
+// 媒体原文件分块加密，读取 Range 时只需解密涉及的块：
+// 头部为 MAGIC || nonce 前缀 || 明文长度（u64 小端），其后每 CHUNK_LEN 字节明文一块密文（各带认证标签）。
+// 每块的 nonce 为 前缀 || 块序号（u32 大端）|| 是否末块，头部作为附加认证数据，块无法被重排、截断或替换
+const CHUNK_MAGIC: &[u8; 4] = b"PUM\x01";
+const CHUNK_LEN: usize = 64 * 1024;
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 5;
+const HEADER_LEN: usize = CHUNK_MAGIC.len() + NONCE_PREFIX_LEN + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Image,
+    Music,
+}
+
+impl MediaKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image",
+            MediaKind::Music => "music",
+        }
+    }
+
+    // 由引用的目录判断媒体类型，不是媒体库引用时返回 None
+    pub fn of_ref(media_ref: &str) -> Option<Self> {
+        match media_ref.split_once('/')?.0 {
+            "images" => Some(MediaKind::Image),
+            "music" => Some(MediaKind::Music),
+            _ => None,
+        }
+    }
+
+    fn dir_name(self) -> &'static str {
+        match self {
+            MediaKind::Image => "images",
+            MediaKind::Music => "music",
+        }
+    }
+
+    fn mime_prefix(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image/",
+            MediaKind::Music => "audio/",
+        }
+    }
+}
+
+// 在仓库锁外准备好的图片：已按设置去除元数据，缩略图也已生成，入库时只需加密写入
+pub struct PreparedImage {
+    pub data: Vec<u8>,
+    // 去除元数据前读出的拍摄时间
+    pub captured_at: Option<DateTime<FixedOffset>>,
+    thumbnail: Option<(Vec<u8>, ImageInfo)>,
+}
+
+impl PreparedImage {
+    // 无法解码的图片照常导入，请求缩略图时再报错
+    pub fn new(data: Vec<u8>, captured_at: Option<DateTime<FixedOffset>>) -> Self {
+        let thumbnail = thumbnail::decode(&data)
+            .and_then(|image| {
+                Ok((
+                    thumbnail::render(&image, DEFAULT_THUMBNAIL_SIZE)?,
+                    thumbnail::info(&image)?,
+                ))
+            })
+            .ok();
+        Self {
+            data,
+            captured_at,
+            thumbnail,
+        }
+    }
+}
+
+// 内容寻址的媒体库：文件以内容哈希命名，相同文件只保存一份。
+// 每个文件（及其缩略图缓存）以密钥环中该文件各自的密钥加密，不再被任何记录引用时密钥随之销毁。
+// 记录中保存的引用形如 images/<哈希>.jpg，与 memories:// 协议的路径一致
+pub struct MediaStore {
+    images_dir: PathBuf,
+    music_dir: PathBuf,
+    thumbs_dir: PathBuf,
+    // 由主密钥派生，只用于计算内容哈希
+    id_key: Zeroizing<Key>,
+}
+
+impl MediaStore {
+    pub fn new(paths: &AppPaths, master_key: &Key) -> Self {
+        Self {
+            images_dir: paths.images_dir.clone(),
+            music_dir: paths.music_dir.clone(),
+            thumbs_dir: paths.thumbs_dir.clone(),
+            id_key: Zeroizing::new(crypto::keyed_hash(master_key, b"media-id")),
+        }
+    }
+
+    // 保存媒体文件并返回引用，已存在相同内容时直接复用
+    pub fn ingest(
+        &self,
+        kind: MediaKind,
+        data: &[u8],
+        file_name: &str,
+        keyring: &mut Keyring,
+    ) -> Result<String, String> {
+        let media_ref = self.store(kind, data, file_name, keyring)?;
+        if kind == MediaKind::Image {
+            // 预先生成首页卡片所需的缩略图；无法解码的图片照常导入，请求缩略图时再报错
+            let _ = self.thumbnail(&media_ref, DEFAULT_THUMBNAIL_SIZE, keyring);
+        }
+        Ok(media_ref)
+    }
+
+    // 保存在仓库锁外准备好的图片，缩略图直接写入缓存
+    pub fn ingest_prepared(
+        &self,
+        image: &PreparedImage,
+        file_name: &str,
+        keyring: &mut Keyring,
+    ) -> Result<String, String> {
+        let media_ref = self.store(MediaKind::Image, &image.data, file_name, keyring)?;
+        if let Some((data, info)) = &image.thumbnail {
+            let key = self.key(&media_ref, keyring)?;
+            let path = self.thumb_file(&media_ref, &format!("_{}.jpg", DEFAULT_THUMBNAIL_SIZE))?;
+            write_encrypted(&path, data, &key)?;
+            let content = serde_json::to_vec(info).map_err(|e| e.to_string())?;
+            write_encrypted(&self.thumb_file(&media_ref, ".json")?, &content, &key)?;
+        }
+        Ok(media_ref)
+    }
+
+    // 文件入库后的引用，不写入任何内容
+    pub fn reference(
+        &self,
+        kind: MediaKind,
+        data: &[u8],
+        file_name: &str,
+    ) -> Result<String, String> {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .filter(|ext| {
+                mime_for_extension(ext).is_some_and(|mime| mime.starts_with(kind.mime_prefix()))
+            })
+            .ok_or_else(|| format!("不支持的文件类型: {}", file_name))?;
+
+        let media_ref = format!(
+            "{}/{}.{}",
+            kind.dir_name(),
+            self.content_id(data),
+            extension
+        );
+        Ok(media_ref)
+    }
+
+    pub fn read(&self, media_ref: &str, keyring: &Keyring) -> Result<Vec<u8>, String> {
+        self.open(media_ref, keyring)?.read_all()
+    }
+
+    // 打开媒体文件以便按范围读取；解密在调用方读取时才进行，可在释放仓库锁之后完成
+    pub fn open(&self, media_ref: &str, keyring: &Keyring) -> Result<MediaReader, String> {
+        let key = self.key(media_ref, keyring)?;
+        let path = self.resolve(media_ref)?;
+        MediaReader::open(&path, key)
+    }
+
+    // 只定位文件并取出密钥，打开与解密留到释放仓库锁之后
+    pub fn locate(&self, media_ref: &str, keyring: &Keyring) -> Result<MediaFile, String> {
+        Ok(MediaFile {
+            key: self.key(media_ref, keyring)?,
+            path: self.resolve(media_ref)?,
+        })
+    }
+
+    fn store(
+        &self,
+        kind: MediaKind,
+        data: &[u8],
+        file_name: &str,
+        keyring: &mut Keyring,
+    ) -> Result<String, String> {
+        let media_ref = self.reference(kind, data, file_name)?;
+        let path = self.resolve(&media_ref)?;
+        // 没有密钥时磁盘上的同名文件是已销毁或尚未清理的残留，重新写入
+        let id = self.content_id_of(&media_ref)?;
+        let existing = keyring.find_media_key(&id);
+        let key = keyring.media_key(&id);
+        if existing.is_none() || !path.exists() {
+            write_chunked(&path, data, &key)?;
+        }
+        Ok(media_ref)
+    }
+
+    // 读取缩略图，缓存缺失或无法解密时从原图重新生成
+    pub fn thumbnail(
+        &self,
+        media_ref: &str,
+        size: u32,
+        keyring: &Keyring,
+    ) -> Result<Vec<u8>, String> {
+        let key = self.key(media_ref, keyring)?;
+        let path = self.thumb_file(media_ref, &format!("_{}.jpg", size))?;
+        if let Some(cached) = read_cached(&path, &key) {
+            return Ok(cached);
+        }
+        let image = thumbnail::decode(&self.read(media_ref, keyring)?)?;
+        let data = thumbnail::render(&image, size)?;
+        write_encrypted(&path, &data, &key)?;
+        self.cache_info(media_ref, &image, &key)?;
+        Ok(data)
+    }
+
+    // 原图尺寸与模糊占位符，缓存缺失时重新计算
+    pub fn image_info(&self, media_ref: &str, keyring: &Keyring) -> Result<ImageInfo, String> {
+        let key = self.key(media_ref, keyring)?;
+        let path = self.thumb_file(media_ref, ".json")?;
+        if let Some(info) =
+            read_cached(&path, &key).and_then(|data| serde_json::from_slice(&data).ok())
+        {
+            return Ok(info);
+        }
+        let image = thumbnail::decode(&self.read(media_ref, keyring)?)?;
+        self.cache_info(media_ref, &image, &key)
+    }
+
+    // 媒体文件在密钥环中的标识，即文件名中的内容哈希
+    pub fn content_id_of(&self, media_ref: &str) -> Result<String, String> {
+        let path = self.resolve(media_ref)?;
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("无效的媒体引用: {}", media_ref))
+    }
+
+    // 把以 from 整体加密的旧文件改用 to 分块加密，文件无法以 from 解开（已改过）时返回 false
+    pub fn rekey(&self, media_ref: &str, from: &Key, to: &Key) -> Result<bool, String> {
+        let path = self.resolve(media_ref)?;
+        let data = fs::read(&path).map_err(|e| format!("读取媒体文件失败: {}", e))?;
+        let plaintext = match crypto::decrypt(from, &data) {
+            Ok(plaintext) => Zeroizing::new(plaintext),
+            Err(_) => return Ok(false),
+        };
+        write_chunked(&path, &plaintext, to)?;
+        Ok(true)
+    }
+
+    // 清空缩略图缓存，缺失的缩略图会按需重新生成
+    pub fn clear_thumbnails(&self) -> Result<(), String> {
+        let entries = match fs::read_dir(&self.thumbs_dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries.flatten() {
+            fs::remove_file(entry.path()).map_err(|e| format!("删除缩略图失败: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn key(&self, media_ref: &str, keyring: &Keyring) -> Result<Zeroizing<Key>, String> {
+        keyring
+            .find_media_key(&self.content_id_of(media_ref)?)
+            .ok_or_else(|| format!("媒体文件的密钥已销毁: {}", media_ref))
+    }
+
+    fn cache_info(
+        &self,
+        media_ref: &str,
+        image: &DynamicImage,
+        key: &Key,
+    ) -> Result<ImageInfo, String> {
+        let path = self.thumb_file(media_ref, ".json")?;
+        let info = thumbnail::info(image)?;
+        let content = serde_json::to_vec(&info).map_err(|e| e.to_string())?;
+        write_encrypted(&path, &content, key)?;
+        Ok(info)
+    }
+
+    // 缩略图缓存以原图哈希命名：thumbs/<哈希><后缀>
+    fn thumb_file(&self, media_ref: &str, suffix: &str) -> Result<PathBuf, String> {
+        if !media_ref.starts_with("images/") {
+            return Err(format!("不是图片: {}", media_ref));
+        }
+        let path = self.resolve(media_ref)?;
+        let id = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("无效的媒体引用: {}", media_ref))?;
+        Ok(self.thumbs_dir.join(format!("{}{}", id, suffix)))
+    }
+
+    // 是否为媒体库引用（而非外部链接等）
+    pub fn is_media_ref(&self, value: &str) -> bool {
+        self.resolve(value).is_ok()
+    }
+
+    pub fn exists(&self, media_ref: &str) -> bool {
+        self.resolve(media_ref).is_ok_and(|path| path.exists())
+    }
+
+    // 媒体库中全部文件的引用，忽略无法识别的文件（如写入中断留下的临时文件）
+    pub fn list(&self) -> Result<Vec<String>, String> {
+        let mut refs = Vec::new();
+        for kind in [MediaKind::Image, MediaKind::Music] {
+            let dir = match kind {
+                MediaKind::Image => &self.images_dir,
+                MediaKind::Music => &self.music_dir,
+            };
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("读取媒体目录失败: {}", e)),
+            };
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("读取媒体目录失败: {}", e))?;
+                if let Some(name) = entry.file_name().to_str() {
+                    let media_ref = format!("{}/{}", kind.dir_name(), name);
+                    if self.resolve(&media_ref).is_ok() {
+                        refs.push(media_ref);
+                    }
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    // 删除媒体文件及其缩略图缓存
+    pub fn remove(&self, media_refs: &[String]) -> Result<(), String> {
+        let mut stems = Vec::new();
+        for media_ref in media_refs {
+            let path = self.resolve(media_ref)?;
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(format!("删除媒体文件失败: {}", e))
+                }
+                _ => {}
+            }
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                stems.push(stem.to_string());
+            }
+        }
+
+        let entries = match fs::read_dir(&self.thumbs_dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let is_orphan = name
+                .to_str()
+                .is_some_and(|name| stems.iter().any(|stem| name.starts_with(stem.as_str())));
+            if is_orphan {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+
+    // 校验引用格式并映射到磁盘路径，拒绝任何目录穿越
+    fn resolve(&self, media_ref: &str) -> Result<PathBuf, String> {
+        let invalid = || format!("无效的媒体引用: {}", media_ref);
+        let (dir, file) = media_ref.split_once('/').ok_or_else(invalid)?;
+        let (id, extension) = file.split_once('.').ok_or_else(invalid)?;
+        if id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        mime_for_extension(extension).ok_or_else(invalid)?;
+
+        let base = match dir {
+            "images" => &self.images_dir,
+            "music" => &self.music_dir,
+            _ => return Err(invalid()),
+        };
+        Ok(base.join(file))
+    }
+
+    // 带密钥的内容哈希，避免通过已知文件的哈希推断库中内容。
+    // 已有文件的名称不变，只有新导入的文件使用派生密钥计算
+    fn content_id(&self, data: &[u8]) -> String {
+        crypto::keyed_hash(&self.id_key, data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// 尚未打开的媒体文件
+pub struct MediaFile {
+    path: PathBuf,
+    key: Zeroizing<Key>,
+}
+
+impl MediaFile {
+    pub fn open(self) -> Result<MediaReader, String> {
+        MediaReader::open(&self.path, self.key)
+    }
+}
+
+// 已打开的媒体文件，按需解密
+pub struct MediaReader {
+    key: Zeroizing<Key>,
+    source: Source,
+    len: u64,
+}
+
+enum Source {
+    Chunked {
+        file: File,
+        header: [u8; HEADER_LEN],
+    },
+    // 分块格式之前整体加密的文件，读取时整体解密
+    Whole(Vec<u8>),
+}
+
+impl MediaReader {
+    fn open(path: &Path, key: Zeroizing<Key>) -> Result<Self, String> {
+        let read_error = |e: std::io::Error| format!("读取媒体文件失败: {}", e);
+        let mut file = File::open(path).map_err(read_error)?;
+        let size = file.metadata().map_err(read_error)?.len();
+        let mut header = [0u8; HEADER_LEN];
+        if file.read_exact(&mut header).is_ok() && header.starts_with(CHUNK_MAGIC) {
+            let len = u64::from_le_bytes(header[HEADER_LEN - 8..].try_into().unwrap_or_default());
+            let tags = chunk_count(len) as u64 * TAG_LEN as u64;
+            if size == HEADER_LEN as u64 + len + tags {
+                return Ok(Self {
+                    key,
+                    source: Source::Chunked { file, header },
+                    len,
+                });
+            }
+        }
+
+        let data = fs::read(path).map_err(read_error)?;
+        let len = (data.len() as u64).saturating_sub((NONCE_LEN + TAG_LEN) as u64);
+        Ok(Self {
+            key,
+            source: Source::Whole(data),
+            len,
+        })
+    }
+
+    // 明文长度
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn read_all(&mut self) -> Result<Vec<u8>, String> {
+        match self.len {
+            0 => self.check_empty(),
+            len => self.read_range(0, len - 1),
+        }
+    }
+
+    // 读取闭区间 [start, end] 的明文，只解密涉及的块
+    pub fn read_range(&mut self, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        if start > end || end >= self.len {
+            return Err("读取范围超出文件长度".to_string());
+        }
+        let (file, header) = match &mut self.source {
+            Source::Whole(data) => {
+                let plaintext = Zeroizing::new(crypto::decrypt(&self.key, data)?);
+                return plaintext
+                    .get(start as usize..=end as usize)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| "媒体文件已损坏".to_string());
+            }
+            Source::Chunked { file, header } => (file, header),
+        };
+
+        let chunk = CHUNK_LEN as u64;
+        let mut out = Vec::with_capacity((end - start + 1) as usize);
+        for index in start / chunk..=end / chunk {
+            let plaintext = read_chunk(file, header, &self.key, self.len, index)?;
+            let offset = index * chunk;
+            let from = start.saturating_sub(offset) as usize;
+            let to = (end - offset).min(chunk - 1) as usize;
+            out.extend_from_slice(
+                plaintext
+                    .get(from..=to)
+                    .ok_or_else(|| "媒体文件已损坏".to_string())?,
+            );
+        }
+        Ok(out)
+    }
+
+    // 空文件同样须通过认证，以发现被截断的文件
+    fn check_empty(&mut self) -> Result<Vec<u8>, String> {
+        match &mut self.source {
+            Source::Whole(data) => crypto::decrypt(&self.key, data),
+            Source::Chunked { file, header } => {
+                read_chunk(file, header, &self.key, 0, 0).map(|plaintext| plaintext.to_vec())
+            }
+        }
+    }
+}
+
+fn read_chunk(
+    file: &mut File,
+    header: &[u8; HEADER_LEN],
+    key: &Key,
+    len: u64,
+    index: u64,
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let chunk = CHUNK_LEN as u64;
+    let plain_len = len.saturating_sub(index * chunk).min(chunk) as usize;
+    let mut ciphertext = vec![0u8; plain_len + TAG_LEN];
+    file.seek(SeekFrom::Start(
+        HEADER_LEN as u64 + index * (chunk + TAG_LEN as u64),
+    ))
+    .and_then(|_| file.read_exact(&mut ciphertext))
+    .map_err(|e| format!("读取媒体文件失败: {}", e))?;
+    let nonce = chunk_nonce(header, index, index + 1 == chunk_count(len) as u64)?;
+    crypto::decrypt_with(key, &nonce, header, &ciphertext).map(Zeroizing::new)
+}
+
+// 空文件也有一个（空的）末块
+fn chunk_count(len: u64) -> usize {
+    (len.div_ceil(CHUNK_LEN as u64) as usize).max(1)
+}
+
+fn chunk_nonce(header: &[u8], index: u64, last: bool) -> Result<[u8; NONCE_LEN], String> {
+    let index = u32::try_from(index).map_err(|_| "媒体文件过大".to_string())?;
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN]
+        .copy_from_slice(&header[CHUNK_MAGIC.len()..CHUNK_MAGIC.len() + NONCE_PREFIX_LEN]);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = u8::from(last);
+    Ok(nonce)
+}
+
+fn encrypt_chunked(data: &[u8], key: &Key) -> Result<Vec<u8>, String> {
+    let mut header = [0u8; HEADER_LEN];
+    header[..CHUNK_MAGIC.len()].copy_from_slice(CHUNK_MAGIC);
+    rand::RngCore::fill_bytes(
+        &mut rand::rngs::OsRng,
+        &mut header[CHUNK_MAGIC.len()..CHUNK_MAGIC.len() + NONCE_PREFIX_LEN],
+    );
+    header[HEADER_LEN - 8..].copy_from_slice(&(data.len() as u64).to_le_bytes());
+
+    let count = chunk_count(data.len() as u64);
+    let mut out = Vec::with_capacity(HEADER_LEN + data.len() + count * TAG_LEN);
+    out.extend_from_slice(&header);
+    for index in 0..count {
+        let chunk =
+            &data[(index * CHUNK_LEN).min(data.len())..((index + 1) * CHUNK_LEN).min(data.len())];
+        let nonce = chunk_nonce(&header, index as u64, index + 1 == count)?;
+        out.extend_from_slice(&crypto::encrypt_with(key, &nonce, &header, chunk)?);
+    }
+    Ok(out)
+}
+
+fn write_chunked(path: &Path, data: &[u8], key: &Key) -> Result<(), String> {
+    let encrypted = encrypt_chunked(data, key)?;
+    atomic::write_file(path, &encrypted).map_err(|e| format!("保存媒体文件失败: {}", e))
+}
+
+fn read_cached(path: &Path, key: &Key) -> Option<Vec<u8>> {
+    let data = fs::read(path).ok()?;
+    crypto::decrypt(key, &data).ok()
+}
+
+fn write_encrypted(path: &Path, data: &[u8], key: &Key) -> Result<(), String> {
+    let encrypted = crypto::encrypt(key, data)?;
+    atomic::write_file(path, &encrypted).map_err(|e| format!("保存媒体文件失败: {}", e))
+}
+
+// Range 请求头的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // 忽略请求头，返回完整内容
+    Full,
+    // 闭区间
+    Partial(u64, u64),
+    // 单段范围的起点超出文件末尾
+    Unsatisfiable,
+}
+
+// 解析单段 HTTP Range 请求头（bytes=start-end / bytes=start- / bytes=-suffix）。
+// 按 RFC 9110 §14.2，格式错误、多段或其他单位的请求头直接忽略；空文件总是返回完整内容
+pub fn parse_range(header: &str, len: u64) -> ByteRange {
+    parse_single_range(header, len).unwrap_or(ByteRange::Full)
+}
+
+// 无法识别的请求头返回 None
+fn parse_single_range(header: &str, len: u64) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if start > end {
+                return None;
+            }
+            (start, end.min(len - 1))
+        }
+    };
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Partial(start, end))
+}
+
+pub fn mime_for_extension(extension: &str) -> Option<&'static str> {
+    let mime = match extension {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "aac" => "audio/aac",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    let extension = match mime {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+// 解析旧版内嵌的 data:<mime>;base64,<数据> 图片，返回内容与入库用的文件名
+pub fn parse_data_url(value: &str) -> Option<(Vec<u8>, String)> {
+    let rest = value.strip_prefix("data:")?;
+    let (header, data) = rest.split_once(',')?;
+    let mime = header.strip_suffix(";base64")?;
+    let bytes = STANDARD.decode(data).ok()?;
+    let extension = extension_for_mime(mime).unwrap_or("jpg");
+    Some((bytes, format!("image.{}", extension)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_handles_open_and_suffix_ranges() {
+        assert_eq!(parse_range("bytes=0-", 100), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range("bytes=10-19", 100), ByteRange::Partial(10, 19));
+        assert_eq!(parse_range("bytes=-10", 100), ByteRange::Partial(90, 99));
+        assert_eq!(parse_range("bytes=-500", 100), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range("bytes=90-500", 100), ByteRange::Partial(90, 99));
+    }
+
+    #[test]
+    fn parse_range_rejects_ranges_past_the_end() {
+        assert_eq!(parse_range("bytes=100-", 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=150-200", 100), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_ignores_unsupported_headers() {
+        for header in [
+            "bytes=20-10",
+            "bytes=-0",
+            "bytes=0-1,5-9",
+            "bytes=abc",
+            "bytes=1-x",
+            "items=0-1",
+        ] {
+            assert_eq!(parse_range(header, 100), ByteRange::Full, "{}", header);
+        }
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Full);
+        assert_eq!(parse_range("bytes=-5", 0), ByteRange::Full);
+    }
+
+    #[test]
+    fn content_id_uses_a_key_derived_from_the_master_key() {
+        let paths = AppPaths::from_data_dir(std::env::temp_dir().join("media-ids"));
+        let master_key = crypto::generate_key();
+        let store = MediaStore::new(&paths, &master_key);
+        let id = store.content_id(b"photo");
+        assert_eq!(id, store.content_id(b"photo"));
+        assert_eq!(id.len(), 64);
+        assert_ne!(id, store.content_id(b"other"));
+        // 主密钥本身不直接参与哈希
+        let direct: String = crypto::keyed_hash(&master_key, b"photo")
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_ne!(id, direct);
+        let other = MediaStore::new(&paths, &crypto::generate_key());
+        assert_ne!(id, other.content_id(b"photo"));
+    }
+
+    fn write_temp(data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("media-{:016x}.bin", rand::random::<u64>()));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn chunked_reader_decrypts_ranges_across_chunks() {
+        let key = crypto::generate_key();
+        let data: Vec<u8> = (0..CHUNK_LEN * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let path = write_temp(&encrypt_chunked(&data, &key).unwrap());
+        let mut reader = MediaReader::open(&path, Zeroizing::new(key)).unwrap();
+
+        assert_eq!(reader.len(), data.len() as u64);
+        assert_eq!(reader.read_all().unwrap(), data);
+        let (start, end) = (CHUNK_LEN as u64 - 3, CHUNK_LEN as u64 * 2 + 5);
+        assert_eq!(
+            reader.read_range(start, end).unwrap(),
+            data[start as usize..=end as usize]
+        );
+        assert!(reader.read_range(0, data.len() as u64).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn chunked_reader_rejects_truncated_files() {
+        let key = crypto::generate_key();
+        let encrypted = encrypt_chunked(&[7u8; CHUNK_LEN * 2], &key).unwrap();
+        // 去掉末块并改写长度，末块标记不符时解密失败
+        let mut truncated = encrypted[..HEADER_LEN + CHUNK_LEN + TAG_LEN].to_vec();
+        truncated[HEADER_LEN - 8..HEADER_LEN].copy_from_slice(&(CHUNK_LEN as u64).to_le_bytes());
+        let path = write_temp(&truncated);
+        let mut reader = MediaReader::open(&path, Zeroizing::new(key)).unwrap();
+        assert!(reader.read_all().is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reader_falls_back_to_whole_file_encryption() {
+        let key = crypto::generate_key();
+        let path = write_temp(&crypto::encrypt(&key, b"legacy media").unwrap());
+        let mut reader = MediaReader::open(&path, Zeroizing::new(key)).unwrap();
+        assert_eq!(reader.len(), 12);
+        assert_eq!(reader.read_range(7, 11).unwrap(), b"media");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn empty_media_round_trips() {
+        let key = crypto::generate_key();
+        let path = write_temp(&encrypt_chunked(&[], &key).unwrap());
+        let mut reader = MediaReader::open(&path, Zeroizing::new(key)).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(reader.read_all().unwrap(), Vec::<u8>::new());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+use std::io::Cursor;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageFormat, ImageReader};
+
+const JPEG_QUALITY: u8 = 92;
+const VP8X_EXIF: u8 = 0x08;
+const VP8X_XMP: u8 = 0x04;
+
+// 去除 EXIF / XMP / IPTC 等元数据，只保留 ICC 色彩配置以免颜色偏差。
+// 无需旋转时直接删去容器中的元数据段，像素原样保留，没有元数据的图片字节不变；
+// 带有 EXIF 方向的图片须先旋转像素再以原格式重新编码。GIF 与 BMP 不携带这类元数据，原样返回
+pub fn strip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| format!("无法识别图片格式: {}", e))?;
+    let format = match reader.format() {
+        Some(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)) => format,
+        _ => return Ok(data.to_vec()),
+    };
+
+    let mut decoder = reader.into_decoder().map_err(decode_error)?;
+    let orientation = decoder.orientation().map_err(decode_error)?;
+    if orientation == Orientation::NoTransforms {
+        let stripped = match format {
+            ImageFormat::Jpeg => strip_jpeg(data),
+            ImageFormat::Png => strip_png(data),
+            _ => strip_webp(data),
+        };
+        // 结构无法解析的文件退回重新编码
+        if let Some(stripped) = stripped {
+            return Ok(stripped);
+        }
+    }
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let mut image = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    image.apply_orientation(orientation);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let result = match format {
+        ImageFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY);
+            encode(
+                DynamicImage::ImageRgb8(image.to_rgb8()),
+                encoder,
+                icc_profile,
+            )
+        }
+        ImageFormat::Png => encode(image, PngEncoder::new(&mut buffer), icc_profile),
+        _ => {
+            let encoder = WebPEncoder::new_lossless(&mut buffer);
+            encode(
+                DynamicImage::ImageRgba8(image.to_rgba8()),
+                encoder,
+                icc_profile,
+            )
+        }
+    };
+    result.map_err(|e| format!("重新编码图片失败: {}", e))?;
+    Ok(buffer.into_inner())
+}
+
+// 读取 EXIF 中的拍摄时间（DateTimeOriginal，缺失时退回 DateTime）。
+// 照片未记录时区时按本机时区解释
+pub fn capture_date(data: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+    let (field, offset_tag) = [
+        (exif::Tag::DateTimeOriginal, exif::Tag::OffsetTimeOriginal),
+        (exif::Tag::DateTime, exif::Tag::OffsetTime),
+    ]
+    .into_iter()
+    .find_map(|(tag, offset)| {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .map(|field| (field, offset))
+    })?;
+
+    let mut value = match &field.value {
+        exif::Value::Ascii(parts) => exif::DateTime::from_ascii(parts.first()?).ok()?,
+        _ => return None,
+    };
+    if let Some(exif::Value::Ascii(parts)) = exif
+        .get_field(offset_tag, exif::In::PRIMARY)
+        .map(|f| &f.value)
+    {
+        if let Some(offset) = parts.first() {
+            let _ = value.parse_offset(offset);
+        }
+    }
+
+    let naive = NaiveDate::from_ymd_opt(value.year.into(), value.month.into(), value.day.into())?
+        .and_hms_opt(value.hour.into(), value.minute.into(), value.second.into())?;
+    match value.offset {
+        Some(minutes) => FixedOffset::east_opt(i32::from(minutes) * 60)?
+            .from_local_datetime(&naive)
+            .single(),
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.fixed_offset()),
+    }
+}
+
+// 删去 APPn 与 COM 段，只保留 JFIF、ICC 配置与 Adobe 颜色变换段；EOI 之后附加的内容
+// （如多图格式的其余图片，各自带有 EXIF）一并丢弃
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    loop {
+        // 段之间允许填充的 0xFF
+        while data.get(pos + 1) == Some(&0xFF) && data[pos] == 0xFF {
+            pos += 1;
+        }
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                return Some(out);
+            }
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let len = usize::from(u16::from_be_bytes([
+            *data.get(pos + 2)?,
+            *data.get(pos + 3)?,
+        ]));
+        if len < 2 {
+            return None;
+        }
+        let end = pos + 2 + len;
+        let segment = data.get(pos..end)?;
+        let payload = &segment[4..];
+        let keep = match marker {
+            0xE0 => payload.starts_with(b"JFIF\0"),
+            0xE2 => payload.starts_with(b"ICC_PROFILE\0"),
+            0xEE => payload.starts_with(b"Adobe"),
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(segment);
+        }
+        pos = end;
+
+        // 扫描段之后是熵编码数据，直到遇到非填充、非复位的标记
+        if marker == 0xDA {
+            let start = pos;
+            while data.get(pos)? != &0xFF || matches!(data.get(pos + 1)?, 0x00 | 0xD0..=0xD7 | 0xFF)
+            {
+                pos += 1;
+            }
+            out.extend_from_slice(&data[start..pos]);
+        }
+    }
+}
+
+// 删去文本、时间与 EXIF 块，其余块（含 iCCP）原样保留
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    loop {
+        let len = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let end = pos.checked_add(len)?.checked_add(12)?;
+        let chunk = data.get(pos..end)?;
+        let kind = &chunk[4..8];
+        if !matches!(kind, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf" | b"tIME") {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IEND" {
+            return Some(out);
+        }
+        pos = end;
+    }
+}
+
+// 删去 EXIF 与 XMP 块并清除 VP8X 中对应的标志位
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(..4)? != b"RIFF" || data.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let riff_end = (u32::from_le_bytes(data[4..8].try_into().ok()?) as usize)
+        .checked_add(8)?
+        .min(data.len());
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..12]);
+    let mut pos = 12;
+    while pos + 8 <= riff_end {
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().ok()?) as usize;
+        // 块长度为奇数时补一个字节
+        let end = pos.checked_add(8 + len + len % 2)?.min(riff_end);
+        let chunk = data.get(pos..end)?;
+        match &chunk[..4] {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if chunk.len() > 8 => {
+                let flags = out.len() + 8;
+                out.extend_from_slice(chunk);
+                out[flags] &= !(VP8X_EXIF | VP8X_XMP);
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        pos = end;
+    }
+    let size = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&size.to_le_bytes());
+    Some(out)
+}
+
+fn encode(
+    image: DynamicImage,
+    mut encoder: impl ImageEncoder,
+    icc_profile: Option<Vec<u8>>,
+) -> image::ImageResult<()> {
+    if let Some(profile) = icc_profile {
+        let _ = encoder.set_icc_profile(profile);
+    }
+    image.write_with_encoder(encoder)
+}
+
+fn decode_error(e: image::ImageError) -> String {
+    format!("无法解码图片: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn jpeg() -> Vec<u8> {
+        let image = RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        let mut buffer = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut buffer, ImageFormat::Jpeg)
+            .unwrap();
+        buffer.into_inner()
+    }
+
+    // 在 SOI 之后插入一段
+    fn with_segment(data: &[u8], marker: u8, payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() + 2) as u16;
+        let mut out = data[..2].to_vec();
+        out.extend_from_slice(&[0xFF, marker]);
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&data[2..]);
+        out
+    }
+
+    #[test]
+    fn jpeg_without_metadata_is_unchanged() {
+        let data = jpeg();
+        assert_eq!(strip(&data).unwrap(), data);
+    }
+
+    #[test]
+    fn jpeg_metadata_is_removed_without_reencoding() {
+        let data = jpeg();
+        let tagged = with_segment(&data, 0xFE, b"secret comment");
+        let tagged = with_segment(&tagged, 0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>");
+        assert_eq!(strip(&tagged).unwrap(), data);
+    }
+
+    #[test]
+    fn png_text_chunks_are_removed() {
+        let mut buffer = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+            .write_to(&mut buffer, ImageFormat::Png)
+            .unwrap();
+        let data = buffer.into_inner();
+
+        // 在 IHDR（签名 8 字节 + 块 25 字节）之后插入 tEXt 块，校验值不影响剥离
+        let mut tagged = data[..33].to_vec();
+        tagged.extend_from_slice(&8u32.to_be_bytes());
+        tagged.extend_from_slice(b"tEXtAuthor\0x");
+        tagged.extend_from_slice(&[0; 4]);
+        tagged.extend_from_slice(&data[33..]);
+        assert_eq!(strip_png(&tagged).unwrap(), data);
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+// 数据结构定义，字段命名与前端 types/index.ts 保持一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmotionalRecord {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub images: Vec<String>,
+    pub music_url: Option<String>,
+    pub music_title: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub is_sealed: bool,
+    pub seal_until: Option<String>,
+    pub auto_destroy_at: Option<String>,
+    // 照片的拍摄时间，取自导入图片的 EXIF
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<String>,
+    // 标签与心情不在尘封载荷中，尘封期间仍可整理
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mood: Option<Mood>,
+    // 尘封期间的加密内容，由 seal 模块管理
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_payload: Option<String>,
+}
+
+// 心情取自设置中的心情色板，强度从 1 到设置的级数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mood {
+    pub id: String,
+    pub intensity: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealConfig {
+    pub seal_until: Option<String>,
+    pub auto_destroy_at: Option<String>,
+}
+
+// 创建记录时的输入
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRecordInput {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub images: Vec<String>,
+    pub music_url: Option<String>,
+    pub music_title: Option<String>,
+    // 未提供时取本次导入图片中最早的拍摄时间
+    pub captured_at: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub mood: Option<Mood>,
+}
+
+// 更新记录时的输入，未提供的字段保持不变
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRecordInput {
+    pub id: String,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub images: Option<Vec<String>>,
+    pub music_url: Option<String>,
+    pub music_title: Option<String>,
+    pub captured_at: Option<String>,
+    pub tags: Option<Vec<String>>,
+    // 传入 null 时清除心情
+    #[serde(default, deserialize_with = "present")]
+    pub mood: Option<Option<Mood>>,
+}
+
+// 字段出现即为 Some，其值为 null 时为 Some(None)；缺失时由 default 取 None
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// 列表与搜索的筛选条件：须带有全部指定标签，心情为其中之一且强度不低于下限
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecordFilter {
+    pub tags: Vec<String>,
+    pub moods: Vec<String>,
+    pub min_intensity: Option<u8>,
+}
+
+// 标签及使用它的记录数
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSummary {
+    pub name: String,
+    pub count: usize,
+}
+
+// 回收站中的记录，purge_at 之后彻底删除
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedRecord {
+    #[serde(flatten)]
+    pub record: EmotionalRecord,
+    pub deleted_at: String,
+    pub purge_at: String,
+}
+
+// 记录生命周期中的定时事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EventKind {
+    UnsealReminder,
+    Unseal,
+    DestroyWarning,
+    DestroyFinalWarning,
+    Destroy,
+    // 每日一次的“那年今日”，不属于某条记录的生命周期
+    OnThisDay,
+}
+
+// 推送给前端与系统通知的事件，尘封中的记录不携带标题
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryEvent {
+    pub kind: EventKind,
+    pub record_id: String,
+    pub title: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(json: &str) -> UpdateRecordInput {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn missing_mood_keeps_and_null_mood_clears() {
+        assert_eq!(update(r#"{"id":"a"}"#).mood, None);
+        assert_eq!(update(r#"{"id":"a","mood":null}"#).mood, Some(None));
+        let mood = Mood {
+            id: "calm".to_string(),
+            intensity: 2,
+        };
+        assert_eq!(
+            update(r#"{"id":"a","mood":{"id":"calm","intensity":2}}"#).mood,
+            Some(Some(mood))
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// 便携安装时可通过环境变量指定数据目录
+pub const DATA_DIR_ENV: &str = "PICK_UP_MEMORIES_DATA_DIR";
+// 或在可执行文件旁放置 portable.json：{ "dataDir": "data" }
+const PORTABLE_CONFIG: &str = "portable.json";
+const APP_DIR_NAME: &str = "pick-up-memories";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PortableConfig {
+    data_dir: PathBuf,
+}
+
+// 应用使用的各个数据目录
+#[derive(Debug, Clone)]
+pub struct AppPaths {
+    pub data_dir: PathBuf,
+    pub images_dir: PathBuf,
+    pub music_dir: PathBuf,
+    // 缩略图缓存，可随时删除，缺失时按需重新生成
+    pub thumbs_dir: PathBuf,
+}
+
+// 返回给前端的目录信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppDataDirInfo {
+    pub data_dir: String,
+    pub images_dir: String,
+    pub music_dir: String,
+    pub free_space: Option<u64>,
+}
+
+impl AppPaths {
+    // 按 环境变量 > portable.json > 平台数据目录 的顺序确定数据目录，并创建子目录
+    pub fn resolve(platform_data_dir: &Path) -> Result<Self, String> {
+        let data_dir = override_dir()?.unwrap_or_else(|| platform_data_dir.join(APP_DIR_NAME));
+        let paths = Self::from_data_dir(data_dir);
+        paths.ensure_dirs()?;
+        Ok(paths)
+    }
+
+    pub fn from_data_dir(data_dir: PathBuf) -> Self {
+        Self {
+            images_dir: data_dir.join("images"),
+            music_dir: data_dir.join("music"),
+            thumbs_dir: data_dir.join("thumbs"),
+            data_dir,
+        }
+    }
+
+    // 旧版前端读写的明文记录文件，每次解锁时导入其中新增的记录
+    pub fn records_file(&self) -> PathBuf {
+        self.data_dir.join("records.json")
+    }
+
+    pub fn vault_file(&self) -> PathBuf {
+        self.data_dir.join("records.vault")
+    }
+
+    pub fn database_file(&self) -> PathBuf {
+        self.data_dir.join("records.db")
+    }
+
+    // 旧版预写日志，仅用于迁移到数据库
+    pub fn journal_file(&self) -> PathBuf {
+        self.data_dir.join("records.journal")
+    }
+
+    pub fn keyring_file(&self) -> PathBuf {
+        self.data_dir.join("keyring.bin")
+    }
+
+    pub fn seal_key_file(&self) -> PathBuf {
+        self.data_dir.join("seal.key")
+    }
+
+    pub fn clock_file(&self) -> PathBuf {
+        self.data_dir.join("clock.json")
+    }
+
+    pub fn scheduler_file(&self) -> PathBuf {
+        self.data_dir.join("scheduler.json")
+    }
+
+    pub fn settings_file(&self) -> PathBuf {
+        self.data_dir.join("settings.json")
+    }
+
+    // 未在设置中指定备份目录时的默认位置
+    pub fn backups_dir(&self) -> PathBuf {
+        self.data_dir.join("backups")
+    }
+
+    pub fn info(&self) -> AppDataDirInfo {
+        AppDataDirInfo {
+            data_dir: self.data_dir.to_string_lossy().into_owned(),
+            images_dir: self.images_dir.to_string_lossy().into_owned(),
+            music_dir: self.music_dir.to_string_lossy().into_owned(),
+            free_space: fs2::available_space(&self.data_dir).ok(),
+        }
+    }
+
+    fn ensure_dirs(&self) -> Result<(), String> {
+        for dir in [
+            &self.data_dir,
+            &self.images_dir,
+            &self.music_dir,
+            &self.thumbs_dir,
+        ] {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("创建目录 {} 失败: {}", dir.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+fn override_dir() -> Result<Option<PathBuf>, String> {
+    if let Some(dir) = env::var_os(DATA_DIR_ENV).filter(|v| !v.is_empty()) {
+        // 相对路径会随启动方式落到不同的工作目录下
+        let dir = PathBuf::from(dir);
+        if !dir.is_absolute() {
+            return Err(format!("{} 须为绝对路径: {}", DATA_DIR_ENV, dir.display()));
+        }
+        return Ok(Some(dir));
+    }
+
+    let exe_dir = match env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+    {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let config_path = exe_dir.join(PORTABLE_CONFIG);
+    if !config_path.exists() {
+        return Ok(None);
+    }
+
+    let content =
+        fs::read_to_string(&config_path).map_err(|e| format!("读取 portable.json 失败: {}", e))?;
+    let config: PortableConfig =
+        serde_json::from_str(&content).map_err(|e| format!("解析 portable.json 失败: {}", e))?;
+    // 相对路径以可执行文件所在目录为基准
+    Ok(Some(exe_dir.join(config.data_dir)))
+}
@@ -0,0 +1,103 @@
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+use crate::media::{self, ByteRange, MediaReader};
+use crate::session::Session;
+use crate::thumbnail;
+
+// 媒体协议：memories://localhost/images/<哈希>.jpg
+// （Windows 与 Android 上为 http://memories.localhost/images/<哈希>.jpg）
+// 缩略图：memories://localhost/thumbs/<尺寸>/images/<哈希>.jpg
+pub const SCHEME: &str = "memories";
+
+// 缩略图很小，在锁内整体生成；原图只在锁内打开，按范围解密放到锁外进行
+enum Content {
+    Bytes(Vec<u8>),
+    Reader(MediaReader),
+}
+
+pub fn handle(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let path = request.uri().path().trim_start_matches('/');
+    let session = app.state::<Session>();
+
+    let thumb = thumbnail::parse_thumbnail_path(path);
+    let read = session.with_store(|store| match thumb {
+        Some((size, media_ref)) => Ok(store
+            .read_visible_thumbnail(media_ref, size)?
+            .map(Content::Bytes)),
+        None => Ok(store.open_visible_media(path)?.map(Content::Reader)),
+    });
+    let mut content = match read {
+        Ok(Some(content)) => content,
+        // 记录已尘封、已销毁或媒体从未被引用
+        Ok(None) => return error(StatusCode::FORBIDDEN, "媒体不可访问"),
+        // 锁定状态下一律拒绝
+        Err(message) if !session.status().is_ok_and(|s| s.unlocked) => {
+            return error(StatusCode::FORBIDDEN, &message)
+        }
+        Err(message) => return error(StatusCode::NOT_FOUND, &message),
+    };
+
+    let mime = match thumb {
+        Some(_) => "image/jpeg",
+        None => path
+            .rsplit_once('.')
+            .and_then(|(_, ext)| media::mime_for_extension(ext))
+            .unwrap_or("application/octet-stream"),
+    };
+    let len = match &content {
+        Content::Bytes(data) => data.len() as u64,
+        Content::Reader(reader) => reader.len(),
+    };
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-store")
+        .header("X-Content-Type-Options", "nosniff");
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(ByteRange::Full, |value| media::parse_range(value, len));
+    let body = match (&mut content, range) {
+        (_, ByteRange::Unsatisfiable) => Ok(Vec::new()),
+        (Content::Bytes(data), ByteRange::Full) => Ok(std::mem::take(data)),
+        (Content::Bytes(data), ByteRange::Partial(start, end)) => {
+            Ok(data[start as usize..=end as usize].to_vec())
+        }
+        (Content::Reader(reader), ByteRange::Full) => reader.read_all(),
+        (Content::Reader(reader), ByteRange::Partial(start, end)) => reader.read_range(start, end),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(message) => return error(StatusCode::INTERNAL_SERVER_ERROR, &message),
+    };
+    let response = match range {
+        ByteRange::Full => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, len)
+            .body(body),
+        ByteRange::Partial(start, end) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, len),
+            )
+            .header(header::CONTENT_LENGTH, end - start + 1)
+            .body(body),
+        ByteRange::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Vec::new()),
+    };
+    response.unwrap_or_default()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(message.as_bytes().to_vec())
+        .unwrap_or_default()
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::Serialize;
+
+use crate::models::EmotionalRecord;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnThisDay {
+    pub id: String,
+    pub title: String,
+    pub created_at: String,
+    pub years_ago: i32,
+}
+
+// “那年今日”：往年同月同日（按本地时区的日历日）创建的记录，最近的年份在前。
+// 尘封中与已设置自动销毁的记录一律不出现；平年的 2 月 28 日同时带出闰年 2 月 29 日的记录
+pub fn on_this_day(records: &[EmotionalRecord], date: NaiveDate) -> Vec<OnThisDay> {
+    let mut found: Vec<OnThisDay> = records
+        .iter()
+        .filter(|r| !r.is_sealed && r.auto_destroy_at.is_none())
+        .filter_map(|r| {
+            let created = local_date(&r.created_at)?;
+            (created.year() < date.year() && same_day(created, date)).then(|| OnThisDay {
+                id: r.id.clone(),
+                title: r.title.clone(),
+                created_at: r.created_at.clone(),
+                years_ago: date.year() - created.year(),
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| {
+        a.years_ago
+            .cmp(&b.years_ago)
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    found
+}
+
+// ISO 时间所在的本地日历日，随系统时区（含夏令时）换算
+pub fn local_date(timestamp: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|t| t.with_timezone(&Local).date_naive())
+}
+
+fn same_day(created: NaiveDate, date: NaiveDate) -> bool {
+    if created.month() == date.month() && created.day() == date.day() {
+        return true;
+    }
+    let leap_day = created.month() == 2 && created.day() == 29;
+    let last_of_february =
+        date.month() == 2 && date.day() == 28 && date.succ_opt().is_some_and(|d| d.month() == 3);
+    leap_day && last_of_february
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn record(day: &str) -> EmotionalRecord {
+        EmotionalRecord {
+            id: day.to_string(),
+            created_at: format!("{}T12:00:00Z", day),
+            updated_at: format!("{}T12:00:00Z", day),
+            ..Default::default()
+        }
+    }
+
+    fn found(records: &[EmotionalRecord], today: &str) -> Vec<(String, i32)> {
+        on_this_day(records, date(today))
+            .into_iter()
+            .map(|m| (m.id, m.years_ago))
+            .collect()
+    }
+
+    #[test]
+    fn leap_day_memories_surface_on_february_28_in_common_years() {
+        let records = [record("2020-02-29"), record("2021-02-28")];
+        assert_eq!(
+            found(&records, "2023-02-28"),
+            [("2021-02-28".to_string(), 2), ("2020-02-29".to_string(), 3)]
+        );
+        assert!(found(&records, "2023-03-01").is_empty());
+    }
+
+    #[test]
+    fn leap_years_keep_february_28_and_29_apart() {
+        let records = [record("2020-02-29"), record("2021-02-28")];
+        assert_eq!(
+            found(&records, "2024-02-28"),
+            [("2021-02-28".to_string(), 3)]
+        );
+        assert_eq!(
+            found(&records, "2024-02-29"),
+            [("2020-02-29".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn current_year_sealed_and_expiring_records_are_skipped() {
+        let mut sealed = record("2020-06-01");
+        sealed.id = "sealed".to_string();
+        sealed.is_sealed = true;
+        let mut expiring = record("2021-06-01");
+        expiring.id = "expiring".to_string();
+        expiring.auto_destroy_at = Some("2030-01-01T00:00:00Z".to_string());
+        let records = [sealed, expiring, record("2024-06-01"), record("2022-06-01")];
+        assert_eq!(
+            found(&records, "2024-06-01"),
+            [("2022-06-01".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::atomic;
+use crate::models::{EmotionalRecord, EventKind};
+
+// 自动备份失败后的重试间隔
+const BACKUP_RETRY_MINUTES: i64 = 15;
+
+// 提醒类事件在更晚的同类事件也已到期时不再补发
+fn superseding_kinds(kind: EventKind) -> &'static [EventKind] {
+    match kind {
+        EventKind::UnsealReminder => &[EventKind::Unseal],
+        EventKind::DestroyWarning => &[EventKind::DestroyFinalWarning, EventKind::Destroy],
+        EventKind::DestroyFinalWarning => &[EventKind::Destroy],
+        EventKind::Unseal | EventKind::Destroy | EventKind::OnThisDay => &[],
+    }
+}
+
+// 解封与销毁是记录状态的变化，由记录仓库保证只发生一次；其余为提醒，需要记录投递状态
+pub fn is_transition(kind: EventKind) -> bool {
+    matches!(kind, EventKind::Unseal | EventKind::Destroy)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledEvent {
+    pub due: DateTime<Utc>,
+    pub kind: EventKind,
+    pub record_id: String,
+    // 事件的唯一标识，记录的时间设置变化后会生成新的事件
+    pub key: String,
+}
+
+impl ScheduledEvent {
+    fn new(record_id: &str, kind: EventKind, target: DateTime<Utc>, offset: Duration) -> Self {
+        Self {
+            due: target - offset,
+            kind,
+            record_id: record_id.to_string(),
+            key: format!("{:?}:{}:{}", kind, record_id, target.timestamp_millis()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ledger {
+    delivered: HashSet<String>,
+    // 最近一次推送“那年今日”的本地日期
+    #[serde(default)]
+    on_this_day: Option<NaiveDate>,
+    // 最近一次自动备份的时间
+    #[serde(default)]
+    backup_at: Option<DateTime<Utc>>,
+}
+
+// 事件调度器：按到期时间排列所有未投递的事件，已投递的提醒持久化到 scheduler.json，
+// 保证每个事件只触发一次，并在睡眠唤醒或重启后补发错过的事件
+pub struct Scheduler {
+    path: PathBuf,
+    ledger: Ledger,
+    queue: BinaryHeap<Reverse<ScheduledEvent>>,
+    // 自动备份失败后的重试时间，只保存在内存中，重启后立即重试
+    backup_retry: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    pub fn open(path: PathBuf) -> Self {
+        let ledger = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            ledger,
+            queue: BinaryHeap::new(),
+            backup_retry: None,
+        }
+    }
+
+    // 根据当前记录重建队列，并清理已不存在事件的投递记录
+    pub fn rebuild(&mut self, records: &[EmotionalRecord]) -> Result<(), String> {
+        let events = plan_events(records);
+        let live: HashSet<&String> = events.iter().map(|e| &e.key).collect();
+        let before = self.ledger.delivered.len();
+        self.ledger.delivered.retain(|key| live.contains(key));
+        let pruned = self.ledger.delivered.len() != before;
+
+        self.queue = events
+            .into_iter()
+            .filter(|e| !self.ledger.delivered.contains(&e.key))
+            .map(Reverse)
+            .collect();
+
+        if pruned {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.queue.peek().map(|Reverse(event)| event.due)
+    }
+
+    // 取出所有已到期的事件；被更晚事件取代的提醒直接标记为已投递而不返回
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Result<Vec<ScheduledEvent>, String> {
+        let mut due = Vec::new();
+        while self.queue.peek().is_some_and(|Reverse(e)| e.due <= now) {
+            if let Some(Reverse(event)) = self.queue.pop() {
+                due.push(event);
+            }
+        }
+
+        let due_kinds: HashSet<(String, EventKind)> =
+            due.iter().map(|e| (e.record_id.clone(), e.kind)).collect();
+        let (superseded, fire): (Vec<_>, Vec<_>) = due.into_iter().partition(|e| {
+            superseding_kinds(e.kind)
+                .iter()
+                .any(|kind| due_kinds.contains(&(e.record_id.clone(), *kind)))
+        });
+        if !superseded.is_empty() {
+            for event in superseded {
+                self.ledger.delivered.insert(event.key);
+            }
+            self.persist()?;
+        }
+        Ok(fire)
+    }
+
+    // 今天的“那年今日”已到推送时间且尚未推送时返回今天的日期；错过推送时间的当天仍会补发
+    pub fn daily_due<Tz: TimeZone>(&self, now: DateTime<Tz>, hour: u32) -> Option<NaiveDate> {
+        let today = now.date_naive();
+        let slot = daily_slot(&now.timezone(), today, hour)?;
+        (now >= slot && self.ledger.on_this_day != Some(today)).then_some(today)
+    }
+
+    // 下一次“那年今日”的推送时间
+    pub fn next_daily<Tz: TimeZone>(&self, now: DateTime<Tz>, hour: u32) -> Option<DateTime<Utc>> {
+        let today = now.date_naive();
+        let date = if self.ledger.on_this_day == Some(today) {
+            today.succ_opt()?
+        } else {
+            today
+        };
+        daily_slot(&now.timezone(), date, hour).map(|slot| slot.with_timezone(&Utc))
+    }
+
+    pub fn mark_daily(&mut self, date: NaiveDate) -> Result<(), String> {
+        self.ledger.on_this_day = Some(date);
+        self.persist()
+    }
+
+    // 从未备份或距上次备份已满间隔时到期
+    pub fn backup_due(&self, now: DateTime<Utc>, interval_hours: u32) -> bool {
+        self.next_backup(interval_hours)
+            .is_none_or(|due| now >= due)
+    }
+
+    // 下一次自动备份的时间，从未备份时为 None（立即备份）；失败后为重试时间
+    pub fn next_backup(&self, interval_hours: u32) -> Option<DateTime<Utc>> {
+        self.backup_retry.or_else(|| {
+            self.ledger
+                .backup_at
+                .map(|at| at + Duration::hours(i64::from(interval_hours)))
+        })
+    }
+
+    // 只记录成功的备份
+    pub fn mark_backup(&mut self, now: DateTime<Utc>) -> Result<(), String> {
+        self.ledger.backup_at = Some(now);
+        self.backup_retry = None;
+        self.persist()
+    }
+
+    // 备份失败后隔一小段时间重试，返回是否为连续失败中的第一次
+    pub fn defer_backup(&mut self, now: DateTime<Utc>) -> bool {
+        let first = self.backup_retry.is_none();
+        self.backup_retry = Some(now + Duration::minutes(BACKUP_RETRY_MINUTES));
+        first
+    }
+
+    pub fn mark_delivered(&mut self, event: &ScheduledEvent) -> Result<(), String> {
+        self.ledger.delivered.insert(event.key.clone());
+        self.persist()
+    }
+
+    fn persist(&self) -> Result<(), String> {
+        let content = serde_json::to_string(&self.ledger).map_err(|e| e.to_string())?;
+        atomic::write_file(&self.path, content.as_bytes())
+            .map_err(|e| format!("保存调度状态失败: {}", e))
+    }
+}
+
+// 为每条记录生成解封、解封提醒、销毁预警与销毁事件
+pub fn plan_events(records: &[EmotionalRecord]) -> Vec<ScheduledEvent> {
+    let mut events = Vec::new();
+    for record in records {
+        if record.is_sealed {
+            if let Some(until) = parse(record.seal_until.as_deref()) {
+                events.push(ScheduledEvent::new(
+                    &record.id,
+                    EventKind::UnsealReminder,
+                    until,
+                    Duration::hours(1),
+                ));
+                events.push(ScheduledEvent::new(
+                    &record.id,
+                    EventKind::Unseal,
+                    until,
+                    Duration::zero(),
+                ));
+            }
+        }
+        if let Some(destroy_at) = parse(record.auto_destroy_at.as_deref()) {
+            events.push(ScheduledEvent::new(
+                &record.id,
+                EventKind::DestroyWarning,
+                destroy_at,
+                Duration::hours(24),
+            ));
+            events.push(ScheduledEvent::new(
+                &record.id,
+                EventKind::DestroyFinalWarning,
+                destroy_at,
+                Duration::hours(1),
+            ));
+            events.push(ScheduledEvent::new(
+                &record.id,
+                EventKind::Destroy,
+                destroy_at,
+                Duration::zero(),
+            ));
+        }
+    }
+    events
+}
+
+// 本地时间某日的整点；夏令时跳过该整点时取其后最早的有效时间
+fn daily_slot<Tz: TimeZone>(tz: &Tz, date: NaiveDate, hour: u32) -> Option<DateTime<Tz>> {
+    let time = date.and_hms_opt(hour, 0, 0)?;
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) => Some(t),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        LocalResult::None => tz
+            .from_local_datetime(&(time + Duration::hours(1)))
+            .earliest(),
+    }
+}
+
+fn parse(value: Option<&str>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDateTime};
+
+    use super::*;
+
+    // 测试用时区：标准时间 UTC+1，2024-03-31 01:00 UTC 起为夏令时 UTC+2，
+    // 当地时间当天 02:00 直接跳到 03:00
+    #[derive(Debug, Clone, Copy)]
+    struct SpringForward;
+
+    fn dst_start() -> NaiveDateTime {
+        date(2024, 3, 31).and_hms_opt(1, 0, 0).unwrap()
+    }
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    impl TimeZone for SpringForward {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            SpringForward
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let standard = *local - Duration::hours(1) < dst_start();
+            let summer = *local - Duration::hours(2) >= dst_start();
+            match (standard, summer) {
+                (true, true) => LocalResult::Ambiguous(offset(1), offset(2)),
+                (true, false) => LocalResult::Single(offset(1)),
+                (false, true) => LocalResult::Single(offset(2)),
+                (false, false) => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            if *utc < dst_start() {
+                offset(1)
+            } else {
+                offset(2)
+            }
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn local(day: u32, hour: u32) -> DateTime<SpringForward> {
+        SpringForward
+            .from_local_datetime(&date(2024, 3, day).and_hms_opt(hour, 0, 0).unwrap())
+            .single()
+            .unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("scheduler-{:016x}.json", rand::random::<u64>()))
+    }
+
+    fn doomed(id: &str, destroy_at: &str) -> EmotionalRecord {
+        EmotionalRecord {
+            id: id.to_string(),
+            auto_destroy_at: Some(destroy_at.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(events: &[ScheduledEvent]) -> Vec<EventKind> {
+        events.iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn later_events_supersede_earlier_warnings() {
+        let path = temp_path();
+        let records = [doomed("record_1_a", "2024-06-02T12:00:00Z")];
+        let mut scheduler = Scheduler::open(path.clone());
+        scheduler.rebuild(&records).unwrap();
+
+        let warned = scheduler.take_due(utc("2024-06-01T13:00:00Z")).unwrap();
+        assert_eq!(kinds(&warned), [EventKind::DestroyWarning]);
+        scheduler.mark_delivered(&warned[0]).unwrap();
+        assert_eq!(scheduler.next_due(), Some(utc("2024-06-02T11:00:00Z")));
+
+        // 睡眠后醒来时最后预警与销毁都已到期，只投递销毁
+        let due = scheduler.take_due(utc("2024-06-02T12:30:00Z")).unwrap();
+        assert_eq!(kinds(&due), [EventKind::Destroy]);
+        scheduler.rebuild(&records).unwrap();
+        assert_eq!(scheduler.next_due(), Some(utc("2024-06-02T12:00:00Z")));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn delivered_reminders_survive_reopening() {
+        let path = temp_path();
+        let records = [doomed("record_1_a", "2024-06-02T12:00:00Z")];
+        let mut scheduler = Scheduler::open(path.clone());
+        scheduler.rebuild(&records).unwrap();
+        for event in scheduler.take_due(utc("2024-06-01T13:00:00Z")).unwrap() {
+            scheduler.mark_delivered(&event).unwrap();
+        }
+        scheduler.mark_daily(date(2024, 6, 1)).unwrap();
+
+        let mut reopened = Scheduler::open(path.clone());
+        reopened.rebuild(&records).unwrap();
+        assert!(reopened
+            .take_due(utc("2024-06-01T13:00:00Z"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(reopened.ledger.on_this_day, Some(date(2024, 6, 1)));
+
+        // 记录删除后投递记录随之清理
+        reopened.rebuild(&[]).unwrap();
+        assert!(Scheduler::open(path.clone()).ledger.delivered.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn on_this_day_fires_once_a_day_after_the_hour() {
+        let path = temp_path();
+        let mut scheduler = Scheduler::open(path.clone());
+        assert_eq!(scheduler.daily_due(local(30, 8), 9), None);
+        assert_eq!(
+            scheduler.next_daily(local(30, 8), 9),
+            Some(utc("2024-03-30T08:00:00Z"))
+        );
+
+        assert_eq!(
+            scheduler.daily_due(local(30, 10), 9),
+            Some(date(2024, 3, 30))
+        );
+        scheduler.mark_daily(date(2024, 3, 30)).unwrap();
+        assert_eq!(scheduler.daily_due(local(30, 23), 9), None);
+        // 次日已是夏令时
+        assert_eq!(
+            scheduler.next_daily(local(30, 23), 9),
+            Some(utc("2024-03-31T07:00:00Z"))
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn on_this_day_in_a_skipped_hour_moves_to_the_next_valid_time() {
+        let scheduler = Scheduler::open(temp_path());
+        // 当地时间 02:00 不存在，改在 03:00（UTC 01:00）推送
+        assert_eq!(
+            scheduler.next_daily(local(31, 0), 2),
+            Some(utc("2024-03-31T01:00:00Z"))
+        );
+        assert_eq!(scheduler.daily_due(local(31, 1), 2), None);
+        assert_eq!(
+            scheduler.daily_due(local(31, 3), 2),
+            Some(date(2024, 3, 31))
+        );
+    }
+
+    #[test]
+    fn failed_backups_retry_after_a_short_backoff() {
+        let path = temp_path();
+        let mut scheduler = Scheduler::open(path.clone());
+        let start = utc("2024-06-01T00:00:00Z");
+        assert!(scheduler.backup_due(start, 24));
+        assert_eq!(scheduler.next_backup(24), None);
+
+        scheduler.mark_backup(start).unwrap();
+        assert!(!scheduler.backup_due(start + Duration::hours(23), 24));
+        let due = start + Duration::hours(24);
+        assert!(scheduler.backup_due(due, 24));
+
+        // 连续失败只有第一次返回 true
+        assert!(scheduler.defer_backup(due));
+        let retry = due + Duration::minutes(BACKUP_RETRY_MINUTES);
+        assert_eq!(scheduler.next_backup(24), Some(retry));
+        assert!(!scheduler.backup_due(due + Duration::minutes(1), 24));
+        assert!(scheduler.backup_due(retry, 24));
+        assert!(!scheduler.defer_backup(retry));
+
+        let done = retry + Duration::minutes(BACKUP_RETRY_MINUTES);
+        scheduler.mark_backup(done).unwrap();
+        assert_eq!(scheduler.next_backup(24), Some(done + Duration::hours(24)));
+        // 重试时间不持久化，上次成功的时间持久化
+        assert_eq!(
+            Scheduler::open(path.clone()).next_backup(24),
+            Some(done + Duration::hours(24))
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
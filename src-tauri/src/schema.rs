@@ -0,0 +1,114 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::models::EmotionalRecord;
+
+// 记录结构的当前版本，EmotionalRecord 增减字段时加一并追加迁移函数
+// 0：旧版前端的 records.json / localStorage / 导出文件
+// 1：尘封内容改为加密载荷（sealedPayload）
+// 2：新增照片拍摄时间（capturedAt）
+// 3：新增标签（tags）与心情（mood）
+pub const SCHEMA_VERSION: u32 = 3;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+// MIGRATIONS[i] 把第 i 版的单条记录升级到第 i + 1 版，已发布的迁移不可修改
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+// 磁盘上的版本化信封：{ "schemaVersion": 2, "records": [...] }
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope<'a> {
+    pub schema_version: u32,
+    pub records: &'a [EmotionalRecord],
+}
+
+impl<'a> Envelope<'a> {
+    pub fn new(records: &'a [EmotionalRecord]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            records,
+        }
+    }
+}
+
+// 解析任意历史版本的记录文档：信封、旧版导出文件（version 为字符串）或不带信封的记录数组
+pub fn parse_records(content: &[u8]) -> Result<Vec<EmotionalRecord>, String> {
+    let document: Value =
+        serde_json::from_slice(content).map_err(|e| format!("解析记录文件失败: {}", e))?;
+    let (version, records) = match document {
+        Value::Array(records) => (0, records),
+        Value::Object(mut map) => {
+            let version = match map.get("schemaVersion") {
+                Some(value) => value
+                    .as_u64()
+                    .and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| "无效的 schemaVersion".to_string())?,
+                None => 0,
+            };
+            match map.remove("records") {
+                Some(Value::Array(records)) => (version, records),
+                _ => return Err("记录文件缺少 records 数组".to_string()),
+            }
+        }
+        _ => return Err("无法识别的记录文件格式".to_string()),
+    };
+    upgrade_records(records, version)
+}
+
+pub fn upgrade_records(records: Vec<Value>, version: u32) -> Result<Vec<EmotionalRecord>, String> {
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            upgrade_record(record, version).map_err(|e| format!("第 {} 条记录: {}", index + 1, e))
+        })
+        .collect()
+}
+
+// 把单条记录从 version 逐版升级到当前版本，较新版本的数据直接拒绝
+pub fn upgrade_record(record: Value, version: u32) -> Result<EmotionalRecord, String> {
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "数据来自更新版本的应用（结构版本 {}，当前支持到 {}），请升级后再打开",
+            version, SCHEMA_VERSION
+        ));
+    }
+    let mut map = match record {
+        Value::Object(map) => map,
+        _ => return Err("记录不是对象".to_string()),
+    };
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(&mut map)?;
+    }
+    serde_json::from_value(Value::Object(map)).map_err(|e| format!("记录格式错误: {}", e))
+}
+
+// 旧版前端允许缺省 images / isSealed，并可能用空字符串表示未设置的可选字段
+fn v0_to_v1(record: &mut Map<String, Value>) -> Result<(), String> {
+    if !record.get("images").is_some_and(Value::is_array) {
+        record.insert("images".to_string(), Value::Array(Vec::new()));
+    }
+    if !record.get("isSealed").is_some_and(Value::is_boolean) {
+        record.insert("isSealed".to_string(), Value::Bool(false));
+    }
+    for field in ["musicUrl", "musicTitle", "sealUntil", "autoDestroyAt"] {
+        if record.get(field).and_then(Value::as_str) == Some("") {
+            record.insert(field.to_string(), Value::Null);
+        }
+    }
+    // 旧版尘封记录的明文内容由 SealEngine::protect_legacy 在打开时加密
+    record.entry("sealedPayload").or_insert(Value::Null);
+    Ok(())
+}
+
+fn v1_to_v2(record: &mut Map<String, Value>) -> Result<(), String> {
+    record.entry("capturedAt").or_insert(Value::Null);
+    Ok(())
+}
+
+fn v2_to_v3(record: &mut Map<String, Value>) -> Result<(), String> {
+    record.entry("tags").or_insert(Value::Array(Vec::new()));
+    record.entry("mood").or_insert(Value::Null);
+    Ok(())
+}
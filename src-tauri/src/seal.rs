@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::crypto::{self, Key, KEY_LEN};
+use crate::media::MediaKind;
+use crate::models::{EmotionalRecord, SealConfig};
+
+// 尘封期间从记录中移走并加密保存的内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SealedPayload {
+    title: String,
+    content: String,
+    images: Vec<String>,
+}
+
+// 系统时间最多可领先单调时钟推算值的幅度，用于吸收 NTP 校时等小幅修正
+const MAX_FORWARD_SKEW_SECS: i64 = 300;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClockState {
+    high_water: Option<DateTime<Utc>>,
+    // 保存时仅由单调时钟推算出的时间（不含系统时间的领先部分），
+    // 连同开机标识与开机以来的毫秒数，同一次开机内重启时据此继续推算
+    #[serde(default)]
+    estimate: Option<DateTime<Utc>>,
+    #[serde(default)]
+    boot_id: Option<String>,
+    #[serde(default)]
+    boot_millis: Option<i64>,
+}
+
+// 单调安全时钟：以启动时刻 + 单调时钟（含系统休眠时间）流逝量推算当前时间，
+// 系统时间只能在有限幅度内领先该推算值，且领先部分不会累积到推算值中。
+// 同一次开机内的重启沿用上次的推算值；真正重启后无从推算，取系统时间与已持久化的最大观测时间中的较大者。
+// 因此调整系统时间既不能让时间倒退，也不能让时间大幅跳跃前进
+pub struct TrustedClock {
+    anchor_wall: DateTime<Utc>,
+    anchor_millis: i64,
+    high_water: Mutex<DateTime<Utc>>,
+    state_path: PathBuf,
+}
+
+impl TrustedClock {
+    pub fn open(state_path: PathBuf) -> Self {
+        let state: ClockState = fs::read_to_string(&state_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let wall = Utc::now();
+        let anchor_millis = boot_millis();
+        let anchor_wall = match (state.estimate, state.boot_millis) {
+            (Some(estimate), Some(saved)) if same_boot(&state.boot_id, saved, anchor_millis) => {
+                estimate + Duration::milliseconds(anchor_millis - saved)
+            }
+            _ => state.high_water.map_or(wall, |hw| hw.max(wall)),
+        };
+        let high_water = state
+            .high_water
+            .map_or(anchor_wall, |hw| hw.max(anchor_wall))
+            .max(bounded(anchor_wall, wall));
+
+        Self {
+            anchor_wall,
+            anchor_millis,
+            high_water: Mutex::new(high_water),
+            state_path,
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        let now = bounded(self.estimate(), Utc::now());
+        if let Ok(mut high_water) = self.high_water.lock() {
+            if now > *high_water {
+                *high_water = now;
+            }
+            return *high_water;
+        }
+        now
+    }
+
+    // 持久化当前观测到的最大时间，供下次启动使用
+    pub fn persist(&self) -> Result<(), String> {
+        let state = ClockState {
+            high_water: Some(self.now()),
+            estimate: Some(self.estimate()),
+            boot_id: boot_id(),
+            boot_millis: Some(boot_millis()),
+        };
+        let content = serde_json::to_string(&state).map_err(|e| e.to_string())?;
+        atomic::write_file(&self.state_path, content.as_bytes())
+            .map_err(|e| format!("保存时钟状态失败: {}", e))
+    }
+
+    fn estimate(&self) -> DateTime<Utc> {
+        let elapsed = (boot_millis() - self.anchor_millis).max(0);
+        self.anchor_wall + Duration::milliseconds(elapsed)
+    }
+}
+
+// 尘封引擎：负责尘封内容的加密与按时解封
+pub struct SealEngine {
+    key: Key,
+    clock: TrustedClock,
+}
+
+impl SealEngine {
+    // 尘封密钥以保险库主密钥加密保存
+    pub fn open(key_path: PathBuf, clock: TrustedClock, master_key: &Key) -> Result<Self, String> {
+        let key = load_or_create_key(&key_path, master_key)?;
+        Ok(Self { key, clock })
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn now_iso(&self) -> String {
+        self.now().to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    pub fn persist_clock(&self) -> Result<(), String> {
+        self.clock.persist()
+    }
+
+    // 将标题、正文和图片移入加密载荷，记录本身只保留尘封信息
+    pub fn seal(&self, record: &mut EmotionalRecord, config: SealConfig) -> Result<(), String> {
+        if record.is_sealed {
+            return Err("记录已处于尘封状态".to_string());
+        }
+        let now = self.now();
+        let until = config.seal_until.as_deref().map(parse_time).transpose()?;
+        if until.is_some_and(|until| until <= now) {
+            return Err("解封时间必须晚于当前时间".to_string());
+        }
+        // 无法解析的销毁时间永远不会到期，须在尘封时拒绝
+        if let Some(destroy_at) = config
+            .auto_destroy_at
+            .as_deref()
+            .map(parse_time)
+            .transpose()?
+        {
+            if destroy_at <= now {
+                return Err("销毁时间必须晚于当前时间".to_string());
+            }
+            if until.is_some_and(|until| destroy_at < until) {
+                return Err("销毁时间不能早于解封时间".to_string());
+            }
+        }
+
+        record.sealed_payload = Some(self.encrypt_payload(take_payload(record))?);
+        record.is_sealed = true;
+        record.seal_until = config.seal_until;
+        record.auto_destroy_at = config.auto_destroy_at;
+        record.updated_at = self.now_iso();
+        Ok(())
+    }
+
+    // 解封时间已到（或未设置解封时间）时才恢复内容
+    pub fn unseal(&self, record: &mut EmotionalRecord) -> Result<(), String> {
+        if !record.is_sealed {
+            return Ok(());
+        }
+        if !self.is_releasable(record) {
+            return Err("尚未到解封时间".to_string());
+        }
+
+        if let Some(sealed) = record.sealed_payload.take() {
+            let plaintext = crypto::decrypt_from_string(&self.key, &sealed)?;
+            let payload: SealedPayload =
+                serde_json::from_slice(&plaintext).map_err(|e| format!("尘封内容已损坏: {}", e))?;
+            record.title = payload.title;
+            record.content = payload.content;
+            record.images = payload.images;
+        }
+        record.is_sealed = false;
+        record.seal_until = None;
+        record.updated_at = self.now_iso();
+        Ok(())
+    }
+
+    // 旧版前端尘封的记录内容仍是明文，补做加密
+    pub fn protect_legacy(&self, records: &mut [EmotionalRecord]) -> Result<bool, String> {
+        let mut changed = false;
+        for record in records
+            .iter_mut()
+            .filter(|r| r.is_sealed && r.sealed_payload.is_none())
+        {
+            record.sealed_payload = Some(self.encrypt_payload(take_payload(record))?);
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    pub fn is_releasable(&self, record: &EmotionalRecord) -> bool {
+        match record.seal_until.as_deref() {
+            None => true,
+            Some(until) => parse_time(until).is_ok_and(|until| until <= self.now()),
+        }
+    }
+
+    // 到期的定时尘封自动解封，返回被解封的记录 id
+    pub fn release_due(&self, records: &mut [EmotionalRecord]) -> Result<Vec<String>, String> {
+        let mut released = Vec::new();
+        for record in records
+            .iter_mut()
+            .filter(|r| r.is_sealed && r.seal_until.is_some())
+        {
+            if self.is_releasable(record) {
+                self.unseal(record)?;
+                released.push(record.id.clone());
+            }
+        }
+        Ok(released)
+    }
+
+    // 尘封载荷中的图片引用，只用于判断媒体文件是否仍被引用、能否访问
+    pub fn sealed_images(&self, record: &EmotionalRecord) -> Result<Vec<String>, String> {
+        Ok(self
+            .open_payload(record, &self.key)?
+            .map(|payload| payload.images)
+            .unwrap_or_default())
+    }
+
+    // 两条记录的内容是否相同，尘封载荷按解密后的内容比较
+    pub fn same_content(&self, a: &EmotionalRecord, b: &EmotionalRecord) -> Result<bool, String> {
+        let plain = |record: &EmotionalRecord| EmotionalRecord {
+            sealed_payload: None,
+            ..record.clone()
+        };
+        let (plain_a, plain_b) = (
+            serde_json::to_value(plain(a)).map_err(|e| e.to_string())?,
+            serde_json::to_value(plain(b)).map_err(|e| e.to_string())?,
+        );
+        Ok(plain_a == plain_b
+            && self.open_payload(a, &self.key)? == self.open_payload(b, &self.key)?)
+    }
+
+    // 导出归档时把尘封载荷改用归档自带的密钥加密，记录在归档中仍保持尘封
+    pub fn export_payload(&self, record: &mut EmotionalRecord, key: &Key) -> Result<(), String> {
+        if let Some(sealed) = record.sealed_payload.as_deref() {
+            let plaintext = Zeroizing::new(crypto::decrypt_from_string(&self.key, sealed)?);
+            record.sealed_payload = Some(crypto::encrypt_to_string(key, &plaintext)?);
+        }
+        Ok(())
+    }
+
+    // 导入归档时用归档的密钥解开尘封载荷，按新的媒体引用改写图片后以本机密钥重新加密
+    pub fn import_payload(
+        &self,
+        record: &mut EmotionalRecord,
+        key: &Key,
+        media: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        let mut payload = match self.open_payload(record, key)? {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+        for image in payload.images.iter_mut() {
+            if let Some(local) = media.get(image) {
+                *image = local.clone();
+            } else if MediaKind::of_ref(image).is_some() {
+                return Err(format!(
+                    "记录 {} 引用的媒体不在归档中: {}",
+                    record.id, image
+                ));
+            }
+        }
+        record.sealed_payload = Some(self.encrypt_payload(payload)?);
+        Ok(())
+    }
+
+    fn open_payload(
+        &self,
+        record: &EmotionalRecord,
+        key: &Key,
+    ) -> Result<Option<SealedPayload>, String> {
+        let sealed = match record.sealed_payload.as_deref() {
+            Some(sealed) => sealed,
+            None => return Ok(None),
+        };
+        let plaintext = Zeroizing::new(crypto::decrypt_from_string(key, sealed)?);
+        serde_json::from_slice(&plaintext)
+            .map(Some)
+            .map_err(|e| format!("尘封内容已损坏: {}", e))
+    }
+
+    fn encrypt_payload(&self, payload: SealedPayload) -> Result<String, String> {
+        let plaintext = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        crypto::encrypt_to_string(&self.key, &plaintext)
+    }
+}
+
+fn take_payload(record: &mut EmotionalRecord) -> SealedPayload {
+    SealedPayload {
+        title: std::mem::take(&mut record.title),
+        content: std::mem::take(&mut record.content),
+        images: std::mem::take(&mut record.images),
+    }
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("时间格式错误 {}: {}", value, e))
+}
+
+// 以推算值为下限，系统时间只在有限幅度内被采纳
+fn bounded(estimate: DateTime<Utc>, wall: DateTime<Utc>) -> DateTime<Utc> {
+    wall.min(estimate + Duration::seconds(MAX_FORWARD_SKEW_SECS))
+        .max(estimate)
+}
+
+// 没有开机标识的平台无法确认是否重启过，不做推算
+fn same_boot(saved_id: &Option<String>, saved_millis: i64, millis: i64) -> bool {
+    match (saved_id, boot_id()) {
+        (Some(saved), Some(current)) => *saved == current && millis >= saved_millis,
+        _ => false,
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn boot_id() -> Option<String> {
+    None
+}
+
+// 开机以来的毫秒数，系统休眠期间同样计时（Instant 在休眠时会停止）
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+))]
+fn boot_millis() -> i64 {
+    // Linux 的 CLOCK_BOOTTIME 与 Darwin 的 CLOCK_MONOTONIC 都包含休眠时间
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_BOOTTIME;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts 为有效的可写 timespec
+    unsafe { libc::clock_gettime(CLOCK, &mut ts) };
+    std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32).as_millis() as i64
+}
+
+// Windows 的 Instant 基于 QueryPerformanceCounter，休眠期间继续计时
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+fn boot_millis() -> i64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_millis() as i64
+}
+
+// 旧版本以明文保存的 32 字节密钥在读取后改为加密保存
+fn load_or_create_key(path: &Path, master_key: &Key) -> Result<Key, String> {
+    if let Ok(bytes) = fs::read(path) {
+        if bytes.len() != KEY_LEN {
+            let plaintext = Zeroizing::new(
+                crypto::decrypt(master_key, &bytes).map_err(|_| "尘封密钥无法解密".to_string())?,
+            );
+            return plaintext
+                .as_slice()
+                .try_into()
+                .map_err(|_| format!("尘封密钥长度应为 {} 字节", KEY_LEN));
+        }
+        let key: Key = bytes
+            .try_into()
+            .map_err(|_| format!("尘封密钥长度应为 {} 字节", KEY_LEN))?;
+        save_key(path, &key, master_key)?;
+        return Ok(key);
+    }
+    let key = crypto::generate_key();
+    save_key(path, &key, master_key)?;
+    Ok(key)
+}
+
+fn save_key(path: &Path, key: &Key, master_key: &Key) -> Result<(), String> {
+    let wrapped = crypto::encrypt(master_key, key)?;
+    atomic::write_file(path, &wrapped).map_err(|e| format!("保存尘封密钥失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("seal-{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn engine(dir: &Path) -> SealEngine {
+        let clock = TrustedClock::open(dir.join("clock.json"));
+        SealEngine::open(dir.join("seal.key"), clock, &crypto::generate_key()).unwrap()
+    }
+
+    fn record() -> EmotionalRecord {
+        EmotionalRecord {
+            id: "record_1_a".to_string(),
+            title: "标题".to_string(),
+            content: "正文".to_string(),
+            images: vec!["images/a.jpg".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn config(seal_until: Option<String>, auto_destroy_at: Option<String>) -> SealConfig {
+        SealConfig {
+            seal_until,
+            auto_destroy_at,
+        }
+    }
+
+    fn iso(time: DateTime<Utc>) -> String {
+        time.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    #[test]
+    fn sealed_content_is_released_only_after_seal_until() {
+        let dir = temp_dir();
+        let engine = engine(&dir);
+        let mut sealed = record();
+        let until = iso(engine.now() + Duration::days(1));
+        engine.seal(&mut sealed, config(Some(until), None)).unwrap();
+        assert!(sealed.is_sealed);
+        assert!(sealed.title.is_empty() && sealed.images.is_empty());
+        assert_eq!(engine.sealed_images(&sealed).unwrap(), ["images/a.jpg"]);
+        assert!(engine
+            .seal(&mut sealed.clone(), config(None, None))
+            .is_err());
+
+        assert_eq!(engine.unseal(&mut sealed).unwrap_err(), "尚未到解封时间");
+        assert!(engine
+            .release_due(std::slice::from_mut(&mut sealed))
+            .unwrap()
+            .is_empty());
+
+        // 解封时间已过
+        sealed.seal_until = Some(iso(engine.now() - Duration::seconds(1)));
+        engine.unseal(&mut sealed).unwrap();
+        assert!(!sealed.is_sealed);
+        assert_eq!(sealed.title, "标题");
+        assert_eq!(sealed.images, ["images/a.jpg"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn seal_rejects_invalid_times() {
+        let dir = temp_dir();
+        let engine = engine(&dir);
+        let now = engine.now();
+        let rejected = [
+            config(Some(iso(now - Duration::hours(1))), None),
+            config(Some("明天".to_string()), None),
+            config(None, Some("not a time".to_string())),
+            config(None, Some(iso(now - Duration::hours(1)))),
+            config(
+                Some(iso(now + Duration::days(2))),
+                Some(iso(now + Duration::days(1))),
+            ),
+        ];
+        for config in rejected {
+            let mut unchanged = record();
+            assert!(engine.seal(&mut unchanged, config).is_err());
+            assert!(!unchanged.is_sealed);
+            assert_eq!(unchanged.title, "标题");
+        }
+
+        let mut sealed = record();
+        let destroy_at = iso(now + Duration::days(2));
+        engine
+            .seal(
+                &mut sealed,
+                config(Some(iso(now + Duration::days(1))), Some(destroy_at.clone())),
+            )
+            .unwrap();
+        assert_eq!(sealed.auto_destroy_at, Some(destroy_at));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn system_time_can_only_lead_the_estimate_by_the_skew_bound() {
+        let estimate = Utc::now();
+        let skew = Duration::seconds(MAX_FORWARD_SKEW_SECS);
+        assert_eq!(
+            bounded(estimate, estimate + Duration::days(30)),
+            estimate + skew
+        );
+        assert_eq!(
+            bounded(estimate, estimate + Duration::seconds(10)),
+            estimate + Duration::seconds(10)
+        );
+        assert_eq!(bounded(estimate, estimate - Duration::days(30)), estimate);
+    }
+
+    #[test]
+    fn clock_never_falls_behind_the_persisted_high_water() {
+        let dir = temp_dir();
+        let path = dir.join("clock.json");
+        // 上次运行时观测到的时间远晚于当前系统时间（如系统时间被调回）
+        let future = Utc::now() + Duration::days(10);
+        let state = ClockState {
+            high_water: Some(future),
+            ..ClockState::default()
+        };
+        fs::write(&path, serde_json::to_string(&state).unwrap()).unwrap();
+        let clock = TrustedClock::open(path.clone());
+        assert!(clock.now() >= future);
+
+        clock.persist().unwrap();
+        let saved: ClockState = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(saved.high_water.unwrap() >= future);
+        let reopened = TrustedClock::open(path);
+        assert!(reopened.now() >= saved.high_water.unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
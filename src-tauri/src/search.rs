@@ -0,0 +1,669 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::models::EmotionalRecord;
+
+// BM25 参数与字段权重，标题命中比正文更重要
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+const TITLE_WEIGHT: f64 = 2.0;
+const CONTENT_WEIGHT: f64 = 1.0;
+// 前缀查询最多展开的词项数
+const MAX_PREFIX_TERMS: usize = 64;
+// 摘要长度与第一处匹配之前保留的字符数
+const SNIPPET_CHARS: usize = 80;
+const SNIPPET_LEAD: usize = 20;
+
+pub const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub id: String,
+    pub score: f64,
+    pub title: String,
+    // 高亮区间为 [起, 止)，按 UTF-16 码元计数，可直接用于前端的 slice
+    pub title_highlights: Vec<[usize; 2]>,
+    pub snippet: String,
+    pub snippet_highlights: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    // 拉丁字母、数字等组成的词
+    Word,
+    // CJK 单字
+    Char,
+    // 相邻的两个 CJK 字
+    Pair,
+}
+
+struct Token {
+    term: String,
+    kind: Kind,
+    position: u32,
+    // 在原文中的字节区间
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Title,
+    Content,
+}
+
+#[derive(Default)]
+struct Positions {
+    title: Vec<u32>,
+    content: Vec<u32>,
+}
+
+impl Positions {
+    fn get(&self, field: Field) -> &[u32] {
+        match field {
+            Field::Title => &self.title,
+            Field::Content => &self.content,
+        }
+    }
+
+    fn get_mut(&mut self, field: Field) -> &mut Vec<u32> {
+        match field {
+            Field::Title => &mut self.title,
+            Field::Content => &mut self.content,
+        }
+    }
+}
+
+// 已索引记录的词项（删除时据此清理倒排表）与字段长度
+struct Document {
+    terms: Vec<String>,
+    lengths: [u32; 2],
+}
+
+// 查询子句：一个词或一个带引号的短语，其中的词项须按相对位置依次出现
+struct Clause {
+    terms: Vec<QueryTerm>,
+}
+
+struct QueryTerm {
+    term: String,
+    offset: u32,
+    prefix: bool,
+}
+
+impl QueryTerm {
+    fn matches(&self, term: &str) -> bool {
+        if self.prefix {
+            term.starts_with(&self.term)
+        } else {
+            term == self.term
+        }
+    }
+}
+
+// 标题与正文的内存倒排索引，只在解锁期间存在，不写入磁盘。
+// CJK 文本不做词典分词，逐字并按相邻两字建立词项，查询时用两字词项拼出短语
+#[derive(Default)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, HashMap<String, Positions>>,
+    documents: HashMap<String, Document>,
+    total_lengths: [u64; 2],
+}
+
+impl SearchIndex {
+    pub fn build(records: &[EmotionalRecord]) -> Self {
+        let mut index = Self::default();
+        for record in records {
+            index.insert(record);
+        }
+        index
+    }
+
+    // 写入或替换一条记录。尘封记录的标题与正文位于加密载荷中，解封后重新写入时才进入索引
+    pub fn insert(&mut self, record: &EmotionalRecord) {
+        self.remove(&record.id);
+        if record.is_sealed {
+            return;
+        }
+
+        let mut terms = HashSet::new();
+        let mut lengths = [0; 2];
+        for (slot, (field, text)) in [
+            (Field::Title, &record.title),
+            (Field::Content, &record.content),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let tokens = tokenize(text);
+            lengths[slot] = tokens.last().map_or(0, |t| t.position + 1);
+            for token in tokens {
+                terms.insert(token.term.clone());
+                self.postings
+                    .entry(token.term)
+                    .or_default()
+                    .entry(record.id.clone())
+                    .or_default()
+                    .get_mut(field)
+                    .push(token.position);
+            }
+        }
+
+        for (total, length) in self.total_lengths.iter_mut().zip(lengths) {
+            *total += u64::from(length);
+        }
+        self.documents.insert(
+            record.id.clone(),
+            Document {
+                terms: terms.into_iter().collect(),
+                lengths,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let document = match self.documents.remove(id) {
+            Some(document) => document,
+            None => return,
+        };
+        for (total, length) in self.total_lengths.iter_mut().zip(document.lengths) {
+            *total -= u64::from(length);
+        }
+        for term in document.terms {
+            if let Some(documents) = self.postings.get_mut(&term) {
+                documents.remove(id);
+                if documents.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    // 查询语法：空格分隔的词须全部命中；"引号内" 为短语；词尾的 * 表示前缀匹配，
+    // 最后一个词在输入过程中同样按前缀匹配。只返回 records 中的记录，结果按 BM25 分数排序
+    pub fn search<'a>(
+        &self,
+        query: &str,
+        records: impl IntoIterator<Item = &'a EmotionalRecord>,
+        limit: usize,
+    ) -> Vec<SearchHit> {
+        let clauses = parse_query(query);
+        let mut scores: Option<HashMap<&str, f64>> = None;
+        for clause in &clauses {
+            let matches = self.match_clause(clause);
+            let total = self.documents.len() as f64;
+            let found = matches.len() as f64;
+            let idf = (1.0 + (total - found + 0.5) / (found + 0.5)).ln();
+            let clause_scores: HashMap<&str, f64> = matches
+                .into_iter()
+                .map(|(id, counts)| (id, idf * self.field_score(id, counts)))
+                .collect();
+            scores = Some(match scores {
+                None => clause_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(id, score)| clause_scores.get(id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        }
+
+        let records: HashMap<&str, &EmotionalRecord> =
+            records.into_iter().map(|r| (r.id.as_str(), r)).collect();
+        let mut ranked: Vec<(&EmotionalRecord, f64)> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, score)| records.get(id).map(|record| (*record, score)))
+            .collect();
+        // 分数相同时较新的记录在前
+        ranked.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| b.0.created_at.cmp(&a.0.created_at))
+        });
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(record, score)| hit(record, score, &clauses))
+            .collect()
+    }
+
+    // 命中该子句的记录及其在标题、正文中的出现次数
+    fn match_clause(&self, clause: &Clause) -> HashMap<&str, [u32; 2]> {
+        let postings: Vec<Vec<&HashMap<String, Positions>>> = clause
+            .terms
+            .iter()
+            .map(|term| self.term_postings(term))
+            .collect();
+        let mut matches = HashMap::new();
+        for documents in &postings[0] {
+            for id in documents.keys() {
+                if matches.contains_key(id.as_str()) {
+                    continue;
+                }
+                let counts = [Field::Title, Field::Content].map(|field| {
+                    let lists: Vec<(u32, Vec<u32>)> = clause
+                        .terms
+                        .iter()
+                        .zip(&postings)
+                        .map(|(term, documents)| {
+                            (term.offset, merged_positions(documents, id, field))
+                        })
+                        .collect();
+                    phrase_starts(&lists).len() as u32
+                });
+                if counts != [0, 0] {
+                    matches.insert(id.as_str(), counts);
+                }
+            }
+        }
+        matches
+    }
+
+    fn term_postings(&self, term: &QueryTerm) -> Vec<&HashMap<String, Positions>> {
+        if term.prefix {
+            self.postings
+                .range(term.term.clone()..)
+                .take_while(|(key, _)| key.starts_with(&term.term))
+                .take(MAX_PREFIX_TERMS)
+                .map(|(_, documents)| documents)
+                .collect()
+        } else {
+            self.postings.get(&term.term).into_iter().collect()
+        }
+    }
+
+    // 各字段的 BM25 词频部分按权重相加
+    fn field_score(&self, id: &str, counts: [u32; 2]) -> f64 {
+        let total = self.documents.len().max(1) as f64;
+        let lengths = self.documents.get(id).map_or([0; 2], |d| d.lengths);
+        [TITLE_WEIGHT, CONTENT_WEIGHT]
+            .into_iter()
+            .enumerate()
+            .map(|(slot, weight)| {
+                let tf = f64::from(counts[slot]);
+                let average = (self.total_lengths[slot] as f64 / total).max(1.0);
+                let norm = 1.0 - B + B * f64::from(lengths[slot]) / average;
+                weight * tf * (K1 + 1.0) / (tf + K1 * norm)
+            })
+            .sum()
+    }
+}
+
+// 把文本切分为词项：拉丁字母与数字按词切分并转为小写，全角字母数字视同半角；
+// 连续的 CJK 字符逐字生成单字词项，并为每对相邻字生成两字词项，二者共用前一个字的位置
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let folded = fold_width(c);
+        if is_cjk(folded) {
+            let mut run = vec![(start, c)];
+            while let Some(&(i, next)) = chars.peek() {
+                if !is_cjk(next) {
+                    break;
+                }
+                run.push((i, next));
+                chars.next();
+            }
+            for (k, &(i, c)) in run.iter().enumerate() {
+                tokens.push(Token {
+                    term: c.to_string(),
+                    kind: Kind::Char,
+                    position,
+                    start: i,
+                    end: i + c.len_utf8(),
+                });
+                if let Some(&(j, next)) = run.get(k + 1) {
+                    tokens.push(Token {
+                        term: [c, next].iter().collect(),
+                        kind: Kind::Pair,
+                        position,
+                        start: i,
+                        end: j + next.len_utf8(),
+                    });
+                }
+                position += 1;
+            }
+        } else if folded.is_alphanumeric() {
+            let mut term: String = folded.to_lowercase().collect();
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, next)) = chars.peek() {
+                let next_folded = fold_width(next);
+                if is_cjk(next_folded) || !next_folded.is_alphanumeric() {
+                    break;
+                }
+                term.extend(next_folded.to_lowercase());
+                end = i + next.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token {
+                term,
+                kind: Kind::Word,
+                position,
+                start,
+                end,
+            });
+            position += 1;
+        }
+    }
+    tokens
+}
+
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    let mut rest = query;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            clauses.extend(clause(&quoted[..end], false));
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..end];
+            let prefix = word.ends_with('*') || end == rest.len();
+            clauses.extend(clause(word.trim_end_matches('*'), prefix));
+            rest = &rest[end..];
+        }
+    }
+    clauses
+}
+
+// 两字以上的 CJK 片段用两字词项拼成短语，单字片段才使用单字词项；前缀匹配只作用于末尾的拉丁词
+fn clause(text: &str, prefix: bool) -> Option<Clause> {
+    let tokens = tokenize(text);
+    let pairs: HashSet<u32> = tokens
+        .iter()
+        .filter(|t| t.kind == Kind::Pair)
+        .map(|t| t.position)
+        .collect();
+    let selected: Vec<Token> = tokens
+        .into_iter()
+        .filter(|t| match t.kind {
+            Kind::Word | Kind::Pair => true,
+            Kind::Char => {
+                !pairs.contains(&t.position)
+                    && !t
+                        .position
+                        .checked_sub(1)
+                        .is_some_and(|p| pairs.contains(&p))
+            }
+        })
+        .collect();
+
+    let base = selected.first()?.position;
+    let last = selected.len() - 1;
+    let terms = selected
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| QueryTerm {
+            prefix: prefix && i == last && t.kind == Kind::Word,
+            offset: t.position - base,
+            term: t.term,
+        })
+        .collect();
+    Some(Clause { terms })
+}
+
+// 前缀查询会展开为多个词项，合并它们在某条记录某个字段中的位置
+fn merged_positions(documents: &[&HashMap<String, Positions>], id: &str, field: Field) -> Vec<u32> {
+    let mut positions: Vec<u32> = documents
+        .iter()
+        .filter_map(|d| d.get(id))
+        .flat_map(|p| p.get(field).iter().copied())
+        .collect();
+    if documents.len() > 1 {
+        positions.sort_unstable();
+        positions.dedup();
+    }
+    positions
+}
+
+// 各词项的相对偏移与已排序的位置列表，返回短语完整出现的起始位置
+fn phrase_starts(lists: &[(u32, Vec<u32>)]) -> Vec<u32> {
+    let (first, rest) = match lists.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    first
+        .1
+        .iter()
+        .copied()
+        .filter(|&p| {
+            rest.iter()
+                .all(|(offset, positions)| positions.binary_search(&(p + offset)).is_ok())
+        })
+        .collect()
+}
+
+fn hit(record: &EmotionalRecord, score: f64, clauses: &[Clause]) -> SearchHit {
+    let title_spans = highlight(&record.title, clauses);
+    let content_spans = highlight(&record.content, clauses);
+    let (snippet, snippet_spans) = snippet(&record.content, &content_spans);
+    SearchHit {
+        id: record.id.clone(),
+        score,
+        title: record.title.clone(),
+        title_highlights: to_utf16(&record.title, &title_spans),
+        snippet_highlights: to_utf16(&snippet, &snippet_spans),
+        snippet,
+    }
+}
+
+// 在原文中定位各子句的匹配，返回按顺序合并后的字节区间
+fn highlight(text: &str, clauses: &[Clause]) -> Vec<(usize, usize)> {
+    let tokens = tokenize(text);
+    let mut spans = Vec::new();
+    for clause in clauses {
+        let lists: Vec<(u32, Vec<u32>)> = clause
+            .terms
+            .iter()
+            .map(|term| {
+                let positions = tokens
+                    .iter()
+                    .filter(|t| term.matches(&t.term))
+                    .map(|t| t.position)
+                    .collect();
+                (term.offset, positions)
+            })
+            .collect();
+        let (first, last) = (&clause.terms[0], &clause.terms[clause.terms.len() - 1]);
+        for start in phrase_starts(&lists) {
+            let begin = tokens
+                .iter()
+                .find(|t| t.position == start && first.matches(&t.term));
+            let end = tokens
+                .iter()
+                .find(|t| t.position == start + last.offset && last.matches(&t.term));
+            if let (Some(begin), Some(end)) = (begin, end) {
+                spans.push((begin.start, end.end));
+            }
+        }
+    }
+
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(previous) if start <= previous.1 => previous.1 = previous.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// 以第一处匹配为中心截取正文摘要，换行替换为空格，高亮区间换算到摘要内
+fn snippet(content: &str, spans: &[(usize, usize)]) -> (String, Vec<(usize, usize)>) {
+    let anchor = spans.first().map_or(0, |s| s.0);
+    let start = content[..anchor]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_LEAD - 1)
+        .map_or(0, |(i, _)| i);
+    let end = content[start..]
+        .char_indices()
+        .nth(SNIPPET_CHARS)
+        .map_or(content.len(), |(i, _)| start + i);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let shift = snippet.len();
+    // 替换前后字节长度相同，区间无需调整
+    snippet.push_str(&content[start..end].replace(['\n', '\r'], " "));
+    if end < content.len() {
+        snippet.push('…');
+    }
+
+    let spans = spans
+        .iter()
+        .filter(|(s, e)| *s < end && *e > start)
+        .map(|&(s, e)| (s.max(start) - start + shift, e.min(end) - start + shift))
+        .collect();
+    (snippet, spans)
+}
+
+fn to_utf16(text: &str, spans: &[(usize, usize)]) -> Vec<[usize; 2]> {
+    let units = |byte: usize| text[..byte].encode_utf16().count();
+    spans.iter().map(|&(s, e)| [units(s), units(e)]).collect()
+}
+
+// 全角 ASCII（输入法常见）折算为半角
+fn fold_width(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // 平假名、片假名
+        | '\u{3400}'..='\u{4DBF}'   // 扩展 A
+        | '\u{4E00}'..='\u{9FFF}'   // 基本汉字
+        | '\u{AC00}'..='\u{D7AF}'   // 谚文音节
+        | '\u{F900}'..='\u{FAFF}'   // 兼容汉字
+        | '\u{20000}'..='\u{2FFFF}' // 扩展 B 及以后
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, title: &str, content: &str) -> EmotionalRecord {
+        EmotionalRecord {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(index: &SearchIndex, records: &[EmotionalRecord], query: &str) -> Vec<String> {
+        index
+            .search(query, records, DEFAULT_LIMIT)
+            .into_iter()
+            .map(|hit| hit.id)
+            .collect()
+    }
+
+    #[test]
+    fn cjk_runs_produce_chars_and_pairs() {
+        let terms: Vec<(String, u32)> = tokenize("今天天气")
+            .into_iter()
+            .map(|t| (t.term, t.position))
+            .collect();
+        let expected = [
+            ("今", 0),
+            ("今天", 0),
+            ("天", 1),
+            ("天天", 1),
+            ("天", 2),
+            ("天气", 2),
+            ("气", 3),
+        ];
+        assert_eq!(
+            terms,
+            expected.map(|(term, position)| (term.to_string(), position))
+        );
+    }
+
+    #[test]
+    fn cjk_phrases_must_appear_in_order() {
+        let records = vec![record("a", "", "今天天气很好")];
+        let index = SearchIndex::build(&records);
+        assert_eq!(ids(&index, &records, "天气"), ["a"]);
+        assert_eq!(ids(&index, &records, "天气很好"), ["a"]);
+        assert!(ids(&index, &records, "气天").is_empty());
+        assert_eq!(ids(&index, &records, "好"), ["a"]);
+    }
+
+    #[test]
+    fn last_word_and_starred_words_match_prefixes() {
+        let records = vec![record("a", "", "Walking the dog")];
+        let index = SearchIndex::build(&records);
+        assert_eq!(ids(&index, &records, "walk"), ["a"]);
+        assert!(ids(&index, &records, "walk dog").is_empty());
+        assert_eq!(ids(&index, &records, "walk* dog"), ["a"]);
+        assert_eq!(ids(&index, &records, "\"the dog\""), ["a"]);
+        assert!(ids(&index, &records, "\"dog the\"").is_empty());
+    }
+
+    #[test]
+    fn full_width_letters_fold_to_ascii() {
+        let records = vec![record("a", "ＡＢＣ notes", "")];
+        let index = SearchIndex::build(&records);
+        assert_eq!(ids(&index, &records, "abc"), ["a"]);
+    }
+
+    #[test]
+    fn title_matches_rank_above_content_matches() {
+        let records = vec![
+            record("content", "Monday", "a trip to the sea"),
+            record("title", "Sea trip", "Monday"),
+        ];
+        let index = SearchIndex::build(&records);
+        assert_eq!(ids(&index, &records, "sea"), ["title", "content"]);
+    }
+
+    #[test]
+    fn sealed_and_removed_records_are_not_found() {
+        let mut sealed = record("sealed", "secret", "");
+        sealed.is_sealed = true;
+        let records = vec![sealed, record("open", "secret", "")];
+        let mut index = SearchIndex::build(&records);
+        assert_eq!(ids(&index, &records, "secret"), ["open"]);
+        index.remove("open");
+        assert!(ids(&index, &records, "secret").is_empty());
+        assert_eq!(index.total_lengths, [0, 0]);
+    }
+
+    #[test]
+    fn highlights_count_utf16_units() {
+        let records = vec![record("a", "😀 天气", "")];
+        let index = SearchIndex::build(&records);
+        let hits = index.search("天气", &records, DEFAULT_LIMIT);
+        assert_eq!(hits[0].title_highlights, [[3, 5]]);
+    }
+
+    #[test]
+    fn snippet_centers_on_first_match() {
+        let content = format!("{}needle{}", "x ".repeat(40), " y".repeat(80));
+        let records = vec![record("a", "", &content)];
+        let index = SearchIndex::build(&records);
+        let hit = &index.search("needle", &records, DEFAULT_LIMIT)[0];
+        assert!(hit.snippet.starts_with('…') && hit.snippet.ends_with('…'));
+        let [start, end] = hit.snippet_highlights[0];
+        let units: Vec<u16> = hit.snippet.encode_utf16().collect();
+        assert_eq!(String::from_utf16(&units[start..end]).unwrap(), "needle");
+    }
+}
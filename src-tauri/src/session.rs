@@ -0,0 +1,168 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use serde::Serialize;
+
+use crate::backup::{self, RestoreReport};
+use crate::database::Database;
+use crate::paths::AppPaths;
+use crate::seal::{SealEngine, TrustedClock};
+use crate::settings::Settings;
+use crate::store::RecordStore;
+use crate::vault::{PassphraseChange, Vault};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    // 是否已设置口令（保险库文件是否存在）
+    pub initialized: bool,
+    pub unlocked: bool,
+    // 本次解锁时保险库文件已损坏，已从备份与日志恢复
+    pub recovered: bool,
+    // 数据库文件损坏时改名保留的路径，提示用户数据已回退到上次打开时的快照
+    pub corrupt_database: Option<String>,
+}
+
+// 解锁会话：只有解锁后才持有记录仓库，锁定即丢弃全部明文与主密钥
+pub struct Session {
+    paths: AppPaths,
+    store: Mutex<Option<RecordStore>>,
+    // 解锁与恢复备份互斥，使解锁期间读取的保险库文件不会被恢复替换；
+    // 解锁时的密钥派生只持有此锁，不阻塞其他命令对仓库的访问
+    files: Mutex<()>,
+}
+
+impl Session {
+    pub fn new(paths: AppPaths) -> Self {
+        Self {
+            paths,
+            store: Mutex::new(None),
+            files: Mutex::new(()),
+        }
+    }
+
+    pub fn status(&self) -> Result<VaultStatus, String> {
+        let store = self.store.lock().map_err(|e| e.to_string())?;
+        Ok(VaultStatus {
+            initialized: Vault::exists(&self.paths.vault_file()),
+            unlocked: store.is_some(),
+            recovered: store.as_ref().is_some_and(|s| s.recovered()),
+            corrupt_database: store
+                .as_ref()
+                .and_then(|s| s.corrupt_database())
+                .map(|path| path.to_string_lossy().into_owned()),
+        })
+    }
+
+    // 首次解锁时以该口令创建保险库；每次解锁都导入旧版前端写入的明文 records.json 中新增的记录。
+    // 口令派生密钥较慢，先解开保险库，再持有仓库锁打开记录仓库
+    // 旧版记录中内嵌的图片在打开时转存，按设置去除元数据
+    pub fn unlock(&self, passphrase: &str, settings: &Settings) -> Result<(), String> {
+        let _files = self.files.lock().map_err(|e| e.to_string())?;
+        if self.store.lock().map_err(|e| e.to_string())?.is_some() {
+            return Ok(());
+        }
+
+        // 上次恢复备份在替换文件中途退出时先完成替换
+        backup::resume_restore(&self.paths)?;
+        let vault_path = self.paths.vault_file();
+        let opened = if Vault::exists(&vault_path) {
+            Vault::unlock(vault_path, passphrase).map(|(vault, legacy)| (vault, Some(legacy)))
+        } else {
+            Vault::create(vault_path, passphrase).map(|vault| (vault, None))
+        };
+        let (vault, legacy) = opened?;
+
+        let mut guard = self.store.lock().map_err(|e| e.to_string())?;
+        let clock = TrustedClock::open(self.paths.clock_file());
+        let seal = SealEngine::open(self.paths.seal_key_file(), clock, vault.master_key())?;
+        let mut store = match legacy {
+            Some(legacy) => RecordStore::open(vault, legacy, &self.paths, seal, settings)?,
+            None => RecordStore::import(vault, Vec::new(), &self.paths, seal, settings)?,
+        };
+        store.import_legacy_file(&self.paths.records_file(), settings)?;
+
+        *guard = Some(store);
+        Ok(())
+    }
+
+    pub fn lock(&self) -> Result<(), String> {
+        let mut guard = self.store.lock().map_err(|e| e.to_string())?;
+        *guard = None;
+        Ok(())
+    }
+
+    // 用备份替换当前数据：先校验备份并为当前数据另做一份备份，再锁定并替换文件，
+    // 之后须以备份时的口令解锁。持有仓库锁期间只取快照，打包与校验在锁定之后进行；
+    // 另做备份失败时中止恢复，force 为 true 时（如当前数据已损坏）仍继续
+    pub fn restore_backup(
+        &self,
+        dir: &Path,
+        name: &str,
+        force: bool,
+    ) -> Result<RestoreReport, String> {
+        let _files = self.files.lock().map_err(|e| e.to_string())?;
+        let restored = backup::verify(dir, name)?;
+        let (snapshot, shredded) = {
+            let mut guard = self.store.lock().map_err(|e| e.to_string())?;
+            let shredded = guard.as_ref().and_then(|store| store.shredded_keys());
+            let snapshot = match guard.as_ref() {
+                Some(store) => Some(backup::Snapshot::take(
+                    dir,
+                    &self.paths,
+                    store.now(),
+                    &|path| store.snapshot_database(path),
+                )),
+                // 锁定状态下只读打开数据库，不迁移也不改动当前文件
+                None if Vault::exists(&self.paths.vault_file()) => {
+                    let database = self.paths.database_file();
+                    Some(backup::Snapshot::take(
+                        dir,
+                        &self.paths,
+                        Utc::now(),
+                        &|path| Database::snapshot_file(&database, path),
+                    ))
+                }
+                None => None,
+            };
+            *guard = None;
+            (snapshot, shredded)
+        };
+
+        let safety_backup = match snapshot.map(|s| s.and_then(backup::Snapshot::write)) {
+            Some(Ok(info)) => Some(info),
+            Some(Err(e)) if !force => {
+                return Err(format!("为当前数据另做备份失败，已取消恢复: {}", e))
+            }
+            _ => None,
+        };
+        // 恢复的密钥环中不能带回已销毁的密钥。锁定状态下没有主密钥，
+        // 尚未删除的密钥只能留在备份中，调度线程在解锁期间会及时删除
+        if let Some((shredded, master_key)) = shredded {
+            backup::shred(dir, &master_key, &shredded)?;
+        }
+        backup::restore(dir, name, &self.paths)?;
+        Ok(RestoreReport {
+            restored,
+            safety_backup,
+        })
+    }
+
+    // 两次口令派生在持有仓库锁之前完成，持锁期间只替换保险库中的包裹密钥
+    pub fn change_passphrase(&self, old: &str, new: &str) -> Result<(), String> {
+        let change = PassphraseChange::derive(&self.paths.vault_file(), old, new)?;
+        self.with_store(|store| store.change_passphrase(change))
+    }
+
+    // 在已解锁的仓库上执行操作，锁定状态下返回错误
+    pub fn with_store<T>(
+        &self,
+        f: impl FnOnce(&mut RecordStore) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut guard = self.store.lock().map_err(|e| e.to_string())?;
+        let store = guard.as_mut().ok_or_else(|| "请先解锁".to_string())?;
+        f(store)
+    }
+}
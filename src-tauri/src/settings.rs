@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic;
+use crate::models::Mood;
+
+// 心情强度级数的上限
+const MAX_INTENSITY_LEVELS: u8 = 10;
+// 自动备份间隔的上限（小时）
+const MAX_BACKUP_INTERVAL: u32 = 720;
+
+// 用户偏好，明文保存在 settings.json，不含任何记录内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    // 导入图片时去除 EXIF / XMP / IPTC 元数据（含 GPS 坐标与相机序列号）
+    pub strip_image_metadata: bool,
+    // 去除元数据前先读出拍摄时间，写入记录的 capturedAt
+    pub keep_capture_date: bool,
+    // 可选的心情及其显示名称与颜色
+    pub mood_palette: Vec<MoodOption>,
+    // 心情强度的级数，强度取 1 到该值
+    pub mood_intensity_levels: u8,
+    // 每天在指定的本地整点推送“那年今日”通知，可关闭
+    pub on_this_day_reminder: bool,
+    pub on_this_day_hour: u8,
+    // 每条记录最多保留的历史版本数，0 表示不保留历史
+    pub revision_limit: u32,
+    // 被替代超过该天数的历史版本自动删除，0 表示不限
+    pub revision_max_age_days: u32,
+    // 删除的记录在回收站中保留的天数，0 表示删除时立即彻底删除
+    pub trash_retention_days: u32,
+    // 按间隔（小时）自动备份到备份目录，未指定目录时为数据目录下的 backups
+    pub auto_backup: bool,
+    pub backup_interval: u32,
+    pub backup_dir: Option<String>,
+    // 按天、周、月各保留的备份份数
+    pub backup_keep_daily: u32,
+    pub backup_keep_weekly: u32,
+    pub backup_keep_monthly: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoodOption {
+    pub id: String,
+    pub label: String,
+    pub color: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            strip_image_metadata: true,
+            keep_capture_date: true,
+            mood_palette: default_palette(),
+            mood_intensity_levels: 5,
+            on_this_day_reminder: true,
+            on_this_day_hour: 9,
+            revision_limit: 50,
+            revision_max_age_days: 365,
+            trash_retention_days: 30,
+            auto_backup: true,
+            backup_interval: 24,
+            backup_dir: None,
+            backup_keep_daily: 7,
+            backup_keep_weekly: 4,
+            backup_keep_monthly: 12,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_INTENSITY_LEVELS).contains(&self.mood_intensity_levels) {
+            return Err(format!(
+                "心情强度级数须在 1 到 {} 之间",
+                MAX_INTENSITY_LEVELS
+            ));
+        }
+        if self.on_this_day_hour > 23 {
+            return Err("通知时间须在 0 到 23 点之间".to_string());
+        }
+        if !(1..=MAX_BACKUP_INTERVAL).contains(&self.backup_interval) {
+            return Err(format!(
+                "自动备份间隔须在 1 到 {} 小时之间",
+                MAX_BACKUP_INTERVAL
+            ));
+        }
+        if self.backup_keep_daily == 0
+            && self.backup_keep_weekly == 0
+            && self.backup_keep_monthly == 0
+        {
+            return Err("至少需要保留一份备份".to_string());
+        }
+        if let Some(dir) = self
+            .backup_dir
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+        {
+            if !Path::new(dir).is_absolute() {
+                return Err("备份目录须为绝对路径".to_string());
+            }
+        }
+        let mut ids = HashSet::new();
+        for option in &self.mood_palette {
+            if option.id.trim().is_empty() || option.label.trim().is_empty() {
+                return Err("心情的 id 与名称不能为空".to_string());
+            }
+            if !ids.insert(option.id.as_str()) {
+                return Err(format!("心情 id 重复: {}", option.id));
+            }
+        }
+        Ok(())
+    }
+
+    // 心情须在当前色板中，强度不超过级数
+    pub fn check_mood(&self, mood: &Mood) -> Result<(), String> {
+        if !self.mood_palette.iter().any(|option| option.id == mood.id) {
+            return Err(format!("未知的心情: {}", mood.id));
+        }
+        if !(1..=self.mood_intensity_levels).contains(&mood.intensity) {
+            return Err(format!(
+                "心情强度须在 1 到 {} 之间",
+                self.mood_intensity_levels
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_palette() -> Vec<MoodOption> {
+    [
+        ("happy", "开心", "#F6C344"),
+        ("calm", "平静", "#7FB3D5"),
+        ("touched", "感动", "#F1948A"),
+        ("nostalgic", "怀念", "#C39BD3"),
+        ("sad", "难过", "#5D6D7E"),
+        ("anxious", "焦虑", "#E59866"),
+        ("angry", "生气", "#CD6155"),
+    ]
+    .into_iter()
+    .map(|(id, label, color)| MoodOption {
+        id: id.to_string(),
+        label: label.to_string(),
+        color: color.to_string(),
+    })
+    .collect()
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    // 文件缺失或无法解析时使用默认设置
+    pub fn open(path: PathBuf) -> Self {
+        let settings = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> Result<Settings, String> {
+        self.settings
+            .lock()
+            .map(|s| s.clone())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn update(&self, settings: Settings) -> Result<Settings, String> {
+        settings.validate()?;
+        let mut guard = self.settings.lock().map_err(|e| e.to_string())?;
+        let content = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+        atomic::write_file(&self.path, content.as_bytes())
+            .map_err(|e| format!("保存设置失败: {}", e))?;
+        *guard = settings;
+        Ok(guard.clone())
+    }
+}
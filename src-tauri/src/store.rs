@@ -0,0 +1,2187 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Local, NaiveDate, SecondsFormat, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use crate::archive::{
+    self, ArchiveExport, ArchiveOperation, ArchiveProgress, ArchiveReader, ExportMedia,
+    ExportOptions, ImportPreview, ImportReport, ImportStrategy,
+};
+use crate::book::{BookExport, BookOptions};
+use crate::crypto::{self, Key};
+use crate::database::{Database, ImportBatch};
+use crate::diff;
+use crate::document::{self, DocumentExport, DocumentOptions};
+use crate::history::{
+    self, Retention, Revision, RevisionBody, RevisionDiff, RevisionSummary, StoredRevision,
+};
+use crate::importer::{
+    self, JournalEntryPreview, JournalImportReport, JournalPreview, ParsedJournal, PreparedEntry,
+    PreparedJournal,
+};
+use crate::insights::{self, InsightRange, Insights};
+use crate::journal::{self, JournalEntry, JournalOp};
+use crate::keyring::{Keyring, Shredded, StoredRecord};
+use crate::media::{self, MediaKind, MediaReader, MediaStore, PreparedImage};
+use crate::metadata;
+use crate::models::{
+    CreateRecordInput, EmotionalRecord, EventKind, MemoryEvent, RecordFilter, SealConfig,
+    TagSummary, TrashedRecord, UpdateRecordInput,
+};
+use crate::paths::AppPaths;
+use crate::resurface::{self, OnThisDay};
+use crate::schema;
+use crate::seal::SealEngine;
+use crate::search::{SearchHit, SearchIndex};
+use crate::settings::Settings;
+use crate::tags::{self, TagCipher};
+use crate::thumbnail::ThumbnailInfo;
+use crate::vault::{PassphraseChange, Vault};
+
+// meta 表中标记一次性迁移已完成的键
+const VAULT_MIGRATED: &str = "vaultMigrated";
+const LOCAL_STORAGE_IMPORTED: &str = "localStorageImported";
+// 上次导入的 records.json 的摘要
+const LEGACY_FILE_IMPORTED: &str = "legacyFileImported";
+const SHARED_KEYS_MIGRATED: &str = "sharedKeysMigrated";
+// 媒体库中可能有不再被引用的文件，清理后置为 false
+const MEDIA_DIRTY: &str = "mediaDirty";
+
+// 旧版保险库内嵌的数据：按记录分别加密的格式带有日志检查点序号，更早的格式为明文记录数组
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum VaultData {
+    Current {
+        #[serde(default)]
+        sequence: u64,
+        records: Vec<StoredRecord>,
+    },
+    Legacy(Vec<Value>),
+}
+
+// 归档中的记录相对本地（含回收站）的变化
+#[derive(Clone, Copy)]
+enum Change {
+    New,
+    // 内容不同且归档中的版本更新
+    Changed,
+    // 内容不同且本地的版本更新或同时修改
+    Conflicting,
+    Unchanged,
+}
+
+// 校验并对比后的归档内容，预览与导入共用
+struct ImportPlan {
+    preview: ImportPreview,
+    // 媒体引用已换成本机的引用，尘封载荷已改用本机的尘封密钥
+    records: Vec<(EmotionalRecord, Change)>,
+    // 本机媒体引用 -> 归档中的媒体引用
+    media: Vec<(String, String)>,
+}
+
+// 记录仓库：解锁期间在内存中持有全部明文记录，每次修改只把变化的记录写入数据库
+pub struct RecordStore {
+    vault: Vault,
+    keyring: Keyring,
+    database: Database,
+    // 本次解锁时保险库或数据库文件已损坏，数据来自备份
+    recovered: bool,
+    media: MediaStore,
+    tag_cipher: TagCipher,
+    // 本次解锁期间新导入的媒体及照片拍摄时间，允许编辑器预览
+    pending_media: HashMap<String, Option<DateTime<FixedOffset>>>,
+    records: Vec<EmotionalRecord>,
+    // 回收站中的记录及移入时间，不参与列表、检索与统计
+    trash: Vec<(EmotionalRecord, String)>,
+    // 可能有媒体文件不再被任何记录引用，下次清理时检查
+    media_dirty: bool,
+    // 未尘封记录的全文索引，随记录的写入与删除同步更新
+    index: SearchIndex,
+    seal: SealEngine,
+    events: Vec<MemoryEvent>,
+}
+
+impl RecordStore {
+    // 打开数据库中的记录，数据密钥已被销毁的记录直接丢弃。
+    // 旧版保险库内嵌的记录与预写日志在首次打开时迁移进数据库
+    pub fn open(
+        vault: Vault,
+        legacy: Option<Vec<u8>>,
+        paths: &AppPaths,
+        seal: SealEngine,
+        settings: &Settings,
+    ) -> Result<Self, String> {
+        let keyring = Keyring::load(paths.keyring_file(), vault.master_key())?;
+        let database = Database::open(&paths.database_file())?;
+        let keys_migrated = database.meta(SHARED_KEYS_MIGRATED)?.is_some();
+        let tag_cipher = TagCipher::new(vault.master_key(), !keys_migrated);
+
+        let migrating = legacy.is_some() && database.meta(VAULT_MIGRATED)?.is_none();
+        let mut trash = Vec::new();
+        let records = match &legacy {
+            Some(data) if migrating => {
+                read_legacy_vault(&keyring, data, &paths.journal_file(), vault.master_key())?
+            }
+            _ => {
+                let mut opened = Vec::new();
+                for mut stored in database.load_records()? {
+                    let stored_tags = std::mem::take(&mut stored.tags);
+                    let deleted_at = stored.deleted_at.take();
+                    if let Some(mut record) = keyring.decrypt_record(stored)? {
+                        for tag in &stored_tags {
+                            record.tags.extend(tag_cipher.open(tag, &keyring)?);
+                        }
+                        match deleted_at {
+                            Some(deleted_at) => trash.push((record, deleted_at)),
+                            None => opened.push(record),
+                        }
+                    }
+                }
+                opened
+            }
+        };
+
+        let media = MediaStore::new(paths, vault.master_key());
+        let mut store = Self::new(vault, keyring, database, media, tag_cipher, records, seal)?;
+        store.trash = trash;
+        if !keys_migrated {
+            store.migrate_shared_keys()?;
+        }
+        store.prepare(migrating, settings)?;
+        if migrating {
+            store
+                .database
+                .set_meta(VAULT_MIGRATED, &store.seal.now_iso())?;
+        }
+        // 迁移完成后去掉保险库中的旧数据；从备份解锁时顺带修复主文件
+        if legacy.is_some() || store.vault.recovered() {
+            store.vault.save()?;
+        }
+        if legacy.is_some() {
+            let _ = fs::remove_file(paths.journal_file());
+        }
+        Ok(store)
+    }
+
+    // 在新建的保险库中导入旧版明文记录并立即写入
+    pub fn import(
+        mut vault: Vault,
+        records: Vec<EmotionalRecord>,
+        paths: &AppPaths,
+        seal: SealEngine,
+        settings: &Settings,
+    ) -> Result<Self, String> {
+        vault.save()?;
+        let keyring = Keyring::load(paths.keyring_file(), vault.master_key())?;
+        let database = Database::open(&paths.database_file())?;
+        let media = MediaStore::new(paths, vault.master_key());
+        let tag_cipher = TagCipher::new(vault.master_key(), false);
+        let mut store = Self::new(vault, keyring, database, media, tag_cipher, records, seal)?;
+        store.prepare(true, settings)?;
+        store
+            .database
+            .set_meta(SHARED_KEYS_MIGRATED, &store.seal.now_iso())?;
+        Ok(store)
+    }
+
+    fn new(
+        vault: Vault,
+        keyring: Keyring,
+        database: Database,
+        media: MediaStore,
+        tag_cipher: TagCipher,
+        records: Vec<EmotionalRecord>,
+        seal: SealEngine,
+    ) -> Result<Self, String> {
+        // 没有标记的旧数据检查一次
+        let media_dirty = database.meta(MEDIA_DIRTY)?.as_deref() != Some("false");
+        Ok(Self {
+            recovered: vault.recovered() || database.recovered(),
+            vault,
+            keyring,
+            database,
+            media,
+            tag_cipher,
+            pending_media: HashMap::new(),
+            index: SearchIndex::build(&records),
+            records,
+            trash: Vec::new(),
+            media_dirty,
+            seal,
+            events: Vec::new(),
+        })
+    }
+
+    // 保护旧版尘封记录、转存内嵌图片并处理到期事件，有变化时写入全部记录
+    fn prepare(&mut self, dirty: bool, settings: &Settings) -> Result<(), String> {
+        let protected = self.seal.protect_legacy(&mut self.records)?;
+        let externalized =
+            externalize_images(&self.media, &mut self.keyring, &mut self.records, settings)?;
+        if dirty || protected || externalized {
+            self.save()?;
+        }
+        self.apply_due()
+    }
+
+    pub fn list(&mut self, filter: &RecordFilter) -> Result<Vec<EmotionalRecord>, String> {
+        self.apply_due()?;
+        Ok(self
+            .records
+            .iter()
+            .filter(|r| matches_filter(r, filter))
+            .cloned()
+            .collect())
+    }
+
+    pub fn get(&mut self, id: &str) -> Result<Option<EmotionalRecord>, String> {
+        self.apply_due()?;
+        Ok(self.records.iter().find(|r| r.id == id).cloned())
+    }
+
+    pub fn create(
+        &mut self,
+        input: CreateRecordInput,
+        settings: &Settings,
+    ) -> Result<EmotionalRecord, String> {
+        if let Some(mood) = &input.mood {
+            settings.check_mood(mood)?;
+        }
+        check_inline_media(input.images.iter().chain(&input.music_url))?;
+        let tags = self.canonical_tags(input.tags)?;
+        let now = self.seal.now_iso();
+        let captured_at = input
+            .captured_at
+            .or_else(|| self.earliest_capture(&input.images));
+        let record = EmotionalRecord {
+            id: generate_id(),
+            title: input.title,
+            content: input.content,
+            images: input.images,
+            music_url: input.music_url,
+            music_title: input.music_title,
+            created_at: now.clone(),
+            updated_at: now,
+            is_sealed: false,
+            seal_until: None,
+            auto_destroy_at: None,
+            captured_at,
+            tags,
+            mood: input.mood,
+            sealed_payload: None,
+        };
+
+        self.records.push(record.clone());
+        self.save_record(&record.id)?;
+        Ok(record)
+    }
+
+    pub fn update(
+        &mut self,
+        input: UpdateRecordInput,
+        settings: &Settings,
+    ) -> Result<EmotionalRecord, String> {
+        check_inline_media(input.images.iter().flatten().chain(&input.music_url))?;
+        if input.images.is_some() || input.music_url.is_some() {
+            self.mark_media_dirty()?;
+        }
+        let tags = input.tags.map(|t| self.canonical_tags(t)).transpose()?;
+        let captured = input
+            .images
+            .as_deref()
+            .and_then(|images| self.earliest_capture(images));
+        let record = self
+            .records
+            .iter_mut()
+            .find(|r| r.id == input.id)
+            .ok_or_else(|| "记录不存在".to_string())?;
+        if record.is_sealed {
+            return Err("尘封中的记忆无法编辑".to_string());
+        }
+        // 标题或正文有变化时，编辑前的内容存为历史版本
+        let previous = (
+            record.title.clone(),
+            record.content.clone(),
+            record.updated_at.clone(),
+        );
+        // 色板调整后，记录原有的心情仍可原样保存
+        if let Some(mood) = input
+            .mood
+            .as_ref()
+            .and_then(Option::as_ref)
+            .filter(|m| record.mood.as_ref() != Some(*m))
+        {
+            settings.check_mood(mood)?;
+        }
+
+        if let Some(title) = input.title {
+            record.title = title;
+        }
+        if let Some(content) = input.content {
+            record.content = content;
+        }
+        if let Some(images) = input.images {
+            record.images = images;
+        }
+        if input.music_url.is_some() {
+            record.music_url = input.music_url;
+        }
+        if input.music_title.is_some() {
+            record.music_title = input.music_title;
+        }
+        if input.captured_at.is_some() {
+            record.captured_at = input.captured_at;
+        } else if record.captured_at.is_none() {
+            record.captured_at = captured;
+        }
+        if let Some(tags) = tags {
+            record.tags = tags;
+        }
+        if let Some(mood) = input.mood {
+            record.mood = mood;
+        }
+        record.updated_at = self.seal.now_iso();
+
+        let updated = record.clone();
+        let (title, content, saved_at) = previous;
+        if title != updated.title || content != updated.content {
+            let body = history::body(title, &content, &updated.content);
+            self.save_revision(&updated.id, saved_at, &body, settings)?;
+        } else {
+            self.save_record(&updated.id)?;
+        }
+        Ok(updated)
+    }
+
+    // 记录的历史版本，新的在前
+    pub fn list_revisions(&mut self, id: &str) -> Result<Vec<RevisionSummary>, String> {
+        let (_, revisions) = self.revisions(id)?;
+        Ok(revisions.iter().map(RevisionSummary::from).collect())
+    }
+
+    pub fn get_revision(&mut self, id: &str, number: u32) -> Result<Revision, String> {
+        let (_, revisions) = self.revisions(id)?;
+        find_revision(revisions, number)
+    }
+
+    // 对比两个版本，未指定的一方为记录的当前内容
+    pub fn diff_revisions(
+        &mut self,
+        id: &str,
+        from: Option<u32>,
+        to: Option<u32>,
+    ) -> Result<RevisionDiff, String> {
+        let (current, revisions) = self.revisions(id)?;
+        let version = |number: Option<u32>| match number {
+            Some(number) => find_revision(revisions.clone(), number).map(|r| (r.title, r.content)),
+            None => Ok((current.title.clone(), current.content.clone())),
+        };
+        let (old_title, old_content) = version(from)?;
+        let (new_title, new_content) = version(to)?;
+        Ok(RevisionDiff {
+            from,
+            to,
+            old_title,
+            new_title,
+            lines: diff::compare(&old_content, &new_content),
+        })
+    }
+
+    // 以历史版本的标题与正文作为一次新的编辑，当前内容同样存入历史
+    pub fn restore_revision(
+        &mut self,
+        id: &str,
+        number: u32,
+        settings: &Settings,
+    ) -> Result<EmotionalRecord, String> {
+        let revision = self.get_revision(id, number)?;
+        self.update(
+            UpdateRecordInput {
+                id: id.to_string(),
+                title: Some(revision.title),
+                content: Some(revision.content),
+                images: None,
+                music_url: None,
+                music_title: None,
+                captured_at: None,
+                tags: None,
+                mood: None,
+            },
+            settings,
+        )
+    }
+
+    // 按保留策略清理全部记录的历史版本
+    pub fn prune_revisions(&mut self, settings: &Settings) -> Result<(), String> {
+        let retention = Retention::new(settings, self.seal.now());
+        self.database.prune_revisions(&retention)
+    }
+
+    pub fn seal(&mut self, id: &str, config: SealConfig) -> Result<EmotionalRecord, String> {
+        let record = self
+            .records
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| "记录不存在".to_string())?;
+        self.seal.seal(record, config)?;
+
+        let sealed = record.clone();
+        self.save_record(&sealed.id)?;
+        Ok(sealed)
+    }
+
+    pub fn unseal(&mut self, id: &str) -> Result<EmotionalRecord, String> {
+        let record = self
+            .records
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| "记录不存在".to_string())?;
+        self.seal.unseal(record)?;
+
+        let unsealed = record.clone();
+        self.save_record(&unsealed.id)?;
+        Ok(unsealed)
+    }
+
+    // 把记录移入回收站；保留天数为 0 时直接彻底删除并销毁其数据密钥
+    pub fn delete(&mut self, id: &str, settings: &Settings) -> Result<(), String> {
+        let position = self
+            .records
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| "记录不存在".to_string())?;
+        // 数据库中删除成功后才从内存中移除
+        if settings.trash_retention_days == 0 {
+            self.remove_records(&[id.to_string()], None)?;
+            self.records.remove(position);
+            return Ok(());
+        }
+
+        let deleted_at = self.seal.now_iso();
+        self.database
+            .trash_records(&[id.to_string()], &deleted_at)?;
+        let record = self.records.remove(position);
+        self.index.remove(id);
+        self.trash.push((record, deleted_at));
+        self.seal.persist_clock()
+    }
+
+    // 回收站中的记录，最近删除的在前
+    pub fn list_trash(&mut self, settings: &Settings) -> Result<Vec<TrashedRecord>, String> {
+        self.apply_due()?;
+        let mut trashed: Vec<TrashedRecord> = self
+            .trash
+            .iter()
+            .map(|(record, deleted_at)| TrashedRecord {
+                record: record.clone(),
+                deleted_at: deleted_at.clone(),
+                purge_at: purge_at(deleted_at, settings.trash_retention_days),
+            })
+            .collect();
+        trashed.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(trashed)
+    }
+
+    // 从回收站恢复，尘封状态与标签保持不变
+    pub fn restore_record(&mut self, id: &str) -> Result<EmotionalRecord, String> {
+        let position = self
+            .trash
+            .iter()
+            .position(|(r, _)| r.id == id)
+            .ok_or_else(|| "回收站中没有该记录".to_string())?;
+        self.database.restore_records(&[id.to_string()])?;
+        let (record, _) = self.trash.remove(position);
+        self.index.insert(&record);
+        self.records.push(record.clone());
+        self.seal.persist_clock()?;
+        // 回收站期间已到解封时间的记录随即解封
+        self.apply_due()?;
+        Ok(self
+            .records
+            .iter()
+            .find(|r| r.id == id)
+            .cloned()
+            .unwrap_or(record))
+    }
+
+    // 彻底删除回收站中的指定记录（未指定时为全部），返回删除的条数
+    pub fn empty_trash(&mut self, ids: Option<&[String]>) -> Result<usize, String> {
+        if let Some(id) = ids
+            .unwrap_or_default()
+            .iter()
+            .find(|id| !self.trash.iter().any(|(r, _)| &r.id == *id))
+        {
+            return Err(format!("回收站中没有该记录: {}", id));
+        }
+        let removed: Vec<String> = self
+            .trash
+            .iter()
+            .map(|(r, _)| r.id.clone())
+            .filter(|id| ids.is_none_or(|ids| ids.contains(id)))
+            .collect();
+        // 数据库中删除成功后才从回收站移除，失败时回收站保持原样
+        self.remove_records(&removed, None)?;
+        self.trash.retain(|(r, _)| !removed.contains(&r.id));
+        self.remove_orphaned_media()?;
+        Ok(removed.len())
+    }
+
+    // 后台定期调用：彻底删除超过保留期的回收站记录，再清理不再被引用的媒体文件
+    pub fn purge_trash(&mut self, settings: &Settings) -> Result<(), String> {
+        self.apply_due()?;
+        let now = self.seal.now();
+        let expired: Vec<String> = self
+            .trash
+            .iter()
+            .filter(|(_, deleted_at)| {
+                is_due(&purge_at(deleted_at, settings.trash_retention_days), now)
+            })
+            .map(|(r, _)| r.id.clone())
+            .collect();
+        if !expired.is_empty() {
+            self.empty_trash(Some(&expired))?;
+        }
+        if self.media_dirty {
+            self.remove_orphaned_media()?;
+        }
+        Ok(())
+    }
+
+    pub fn search(
+        &mut self,
+        query: &str,
+        limit: usize,
+        filter: &RecordFilter,
+    ) -> Result<Vec<SearchHit>, String> {
+        self.apply_due()?;
+        let records = self.records.iter().filter(|r| matches_filter(r, filter));
+        Ok(self.index.search(query, records, limit))
+    }
+
+    // 按范围汇总的统计数据
+    pub fn insights(&mut self, range: &InsightRange) -> Result<Insights, String> {
+        self.apply_due()?;
+        let destructions = self.database.load_destructions()?;
+        let today = self.seal.now().with_timezone(&Local).date_naive();
+        insights::compute(&self.records, &destructions, range, today)
+    }
+
+    // 指定本地日期（缺省为今天）的“那年今日”
+    pub fn on_this_day(&mut self, date: Option<NaiveDate>) -> Result<Vec<OnThisDay>, String> {
+        self.apply_due()?;
+        let date = date.unwrap_or_else(|| self.seal.now().with_timezone(&Local).date_naive());
+        Ok(resurface::on_this_day(&self.records, date))
+    }
+
+    // 全部标签及使用次数，常用的在前
+    pub fn list_tags(&self) -> Vec<TagSummary> {
+        let mut summaries: Vec<TagSummary> = Vec::new();
+        for tag in self.records.iter().flat_map(|r| &r.tags) {
+            match summaries.iter_mut().find(|s| tags::same(&s.name, tag)) {
+                Some(summary) => summary.count += 1,
+                None => summaries.push(TagSummary {
+                    name: tag.clone(),
+                    count: 1,
+                }),
+            }
+        }
+        summaries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        summaries
+    }
+
+    // 给指定记录加上标签，返回有变化的记录。尘封中的记录同样可以整理标签
+    pub fn add_tag(&mut self, ids: &[String], tag: &str) -> Result<Vec<EmotionalRecord>, String> {
+        self.ensure_exist(ids)?;
+        let tag = self
+            .canonical_tags(vec![tag.to_string()])?
+            .pop()
+            .ok_or_else(|| "标签不能为空".to_string())?;
+
+        let mut changed = Vec::new();
+        for record in self.records.iter_mut().filter(|r| ids.contains(&r.id)) {
+            if !record.tags.iter().any(|t| tags::same(t, &tag)) {
+                record.tags.push(tag.clone());
+                changed.push(record.id.clone());
+            }
+        }
+        self.save_records(&changed)?;
+        Ok(self.cloned(&changed))
+    }
+
+    // 从指定记录（未指定时为全部记录）移除标签
+    pub fn remove_tag(
+        &mut self,
+        ids: Option<&[String]>,
+        tag: &str,
+    ) -> Result<Vec<EmotionalRecord>, String> {
+        if let Some(ids) = ids {
+            self.ensure_exist(ids)?;
+        }
+
+        let mut changed = Vec::new();
+        for record in self
+            .records
+            .iter_mut()
+            .filter(|r| ids.is_none_or(|ids| ids.contains(&r.id)))
+        {
+            let before = record.tags.len();
+            record.tags.retain(|t| !tags::same(t, tag));
+            if record.tags.len() != before {
+                changed.push(record.id.clone());
+            }
+        }
+        // 与重命名一致，没有记录带有该标签时报错
+        if changed.is_empty() {
+            return Err("标签不存在".to_string());
+        }
+        self.save_records(&changed)?;
+        Ok(self.cloned(&changed))
+    }
+
+    // 在全部记录中重命名标签；新名称已存在时两个标签合并
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> Result<Vec<EmotionalRecord>, String> {
+        let to = tags::normalize(to)?.ok_or_else(|| "标签不能为空".to_string())?;
+        // 只改大小写时沿用新写法，否则与已有的同名标签保持一致
+        let target = if tags::same(from, &to) {
+            to
+        } else {
+            self.canonical_tags(vec![to])?.remove(0)
+        };
+
+        let mut changed = Vec::new();
+        for record in self.records.iter_mut() {
+            let position = match record.tags.iter().position(|t| tags::same(t, from)) {
+                Some(position) => position,
+                None => continue,
+            };
+            record.tags[position] = target.clone();
+            let mut seen = false;
+            record
+                .tags
+                .retain(|t| !tags::same(t, &target) || !std::mem::replace(&mut seen, true));
+            changed.push(record.id.clone());
+        }
+        if changed.is_empty() {
+            return Err("标签不存在".to_string());
+        }
+        self.save_records(&changed)?;
+        Ok(self.cloned(&changed))
+    }
+
+    // 收集未删除的记录（可选包括尘封记录）、设置与引用到的媒体，由调用方在释放锁后写成归档文件
+    pub fn prepare_export(
+        &mut self,
+        options: &ExportOptions,
+        settings: &Settings,
+    ) -> Result<ArchiveExport, String> {
+        // 尘封媒体与尘封密钥不能以明文写入归档
+        if options.include_sealed && options.passphrase.is_none() {
+            return Err("导出尘封记录须设置归档口令".to_string());
+        }
+        self.apply_due()?;
+        // 尘封载荷改用归档专用的密钥加密，不导出本机的尘封密钥
+        let seal_key = Zeroizing::new(crypto::generate_key());
+        let mut records = Vec::new();
+        let mut referenced = BTreeSet::new();
+        let mut sealed_media = HashSet::new();
+        for record in self
+            .records
+            .iter()
+            .filter(|r| options.include_sealed || !r.is_sealed)
+        {
+            let mut record = record.clone();
+            referenced.extend(record.images.iter().cloned());
+            referenced.extend(record.music_url.iter().cloned());
+            sealed_media.extend(self.seal.sealed_images(&record)?);
+            self.seal.export_payload(&mut record, &seal_key)?;
+            records.push(record);
+        }
+        referenced.extend(sealed_media.iter().cloned());
+        let (present, missing_media): (Vec<String>, Vec<String>) = referenced
+            .into_iter()
+            .filter(|r| self.media.is_media_ref(r))
+            .partition(|r| self.media.exists(r));
+        let media = present
+            .into_iter()
+            .map(|media_ref| {
+                Ok(ExportMedia {
+                    file: self.media.locate(&media_ref, &self.keyring)?,
+                    sealed: sealed_media.contains(&media_ref),
+                    media_ref,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(ArchiveExport {
+            records,
+            settings: settings.clone(),
+            seal_key,
+            media,
+            missing_media,
+            exported_at: self.seal.now_iso(),
+            passphrase: options.passphrase.clone(),
+        })
+    }
+
+    // 收集要导出为 Markdown 文件夹或 HTML 文件的记录与媒体，由调用方在释放锁后读取媒体并写入
+    pub fn prepare_document(
+        &mut self,
+        options: &DocumentOptions,
+    ) -> Result<DocumentExport, String> {
+        self.apply_due()?;
+        let mut records = match &options.ids {
+            Some(ids) => {
+                self.ensure_exist(ids)?;
+                self.cloned(ids)
+            }
+            None => self.records.clone(),
+        };
+        document::sort_records(&mut records);
+
+        // 尘封记录的图片在加密载荷中，不会导出
+        let referenced: BTreeSet<&String> = records
+            .iter()
+            .flat_map(|r| r.images.iter().chain(&r.music_url))
+            .filter(|r| self.media.is_media_ref(r))
+            .collect();
+        let mut media = Vec::new();
+        let mut missing_media = Vec::new();
+        for media_ref in referenced {
+            if !self.media.exists(media_ref) {
+                missing_media.push(media_ref.clone());
+                continue;
+            }
+            media.push((
+                media_ref.clone(),
+                self.media.locate(media_ref, &self.keyring)?,
+            ));
+        }
+
+        Ok(DocumentExport {
+            format: options.format,
+            title: options.title.clone(),
+            records,
+            media,
+            missing_media,
+        })
+    }
+
+    // 收集要排版为纪念册的记录与图片，由调用方在释放锁后完全离线地生成 PDF
+    pub fn prepare_book(&mut self, options: &BookOptions) -> Result<BookExport, String> {
+        self.apply_due()?;
+        let mut records = match &options.ids {
+            Some(ids) => {
+                self.ensure_exist(ids)?;
+                self.cloned(ids)
+            }
+            None => self.records.clone(),
+        };
+        if let Some(year) = options.year {
+            records
+                .retain(|r| resurface::local_date(&r.created_at).is_some_and(|d| d.year() == year));
+        }
+        if records.is_empty() {
+            return Err("没有可导出的记录".to_string());
+        }
+        document::sort_records(&mut records);
+
+        // 尘封记录的图片在加密载荷中，不会导出
+        let mut media = Vec::new();
+        let mut missing_media = Vec::new();
+        let mut seen = HashSet::new();
+        for media_ref in records.iter().flat_map(|r| &r.images) {
+            if !self.media.is_media_ref(media_ref) || !seen.insert(media_ref) {
+                continue;
+            }
+            match self.media.locate(media_ref, &self.keyring) {
+                Ok(file) => media.push((media_ref.clone(), file)),
+                Err(_) => missing_media.push(media_ref.clone()),
+            }
+        }
+
+        Ok(BookExport {
+            title: options.title.clone(),
+            records,
+            media,
+            missing_media,
+        })
+    }
+
+    // 校验归档并列出将新增、更新与冲突的记录，不做任何修改（到期的解封与销毁也留到导入时处理）。
+    // 归档由调用方在仓库锁外打开，口令的密钥派生不占用锁
+    pub fn preview_archive(
+        &self,
+        reader: &mut ArchiveReader,
+        settings: &Settings,
+    ) -> Result<ImportPreview, String> {
+        Ok(self.plan_import(reader, settings, &mut || {})?.preview)
+    }
+
+    // 按策略导入归档中的记录与媒体，记录的改动在一个事务中写入，失败时本地数据保持不变。
+    // 归档中的设置不会覆盖本机设置
+    pub fn import_archive(
+        &mut self,
+        reader: &mut ArchiveReader,
+        strategy: ImportStrategy,
+        settings: &Settings,
+        progress: &mut dyn FnMut(ArchiveProgress),
+    ) -> Result<ImportReport, String> {
+        self.apply_due()?;
+        // 读取记录、逐个校验媒体、逐个导入媒体、写入数据库
+        let total = reader.media_refs().len() * 2 + 2;
+        let mut done = 0;
+        let mut step = || {
+            done += 1;
+            progress(ArchiveProgress {
+                operation: ArchiveOperation::Import,
+                done,
+                total,
+            });
+        };
+        let plan = self.plan_import(reader, settings, &mut step)?;
+
+        let now = self.seal.now_iso();
+        let mut records = self.records.clone();
+        let mut trash = self.trash.clone();
+        let mut applied = Vec::new();
+        let mut revisions = Vec::new();
+        let mut cleared_history = Vec::new();
+        let mut skipped = 0;
+        let incoming: HashSet<String> = plan.records.iter().map(|(r, _)| r.id.clone()).collect();
+        for (mut record, change) in plan.records {
+            match (change, strategy) {
+                (Change::Unchanged, _) => continue,
+                (Change::Conflicting, ImportStrategy::Merge) => {
+                    skipped += 1;
+                    continue;
+                }
+                (Change::Changed | Change::Conflicting, ImportStrategy::KeepBoth) => {
+                    record.id = generate_id();
+                }
+                (Change::New, _) => {}
+                (Change::Changed | Change::Conflicting, _) => {
+                    let local = match records.iter().position(|r| r.id == record.id) {
+                        Some(position) => records.remove(position),
+                        None => {
+                            let position = trash
+                                .iter()
+                                .position(|(r, _)| r.id == record.id)
+                                .ok_or_else(|| "记录不存在".to_string())?;
+                            trash.remove(position).0
+                        }
+                    };
+                    // 被覆盖的内容存为历史版本；尘封内容无法接入差异链，旧的历史一并清除
+                    if local.is_sealed || record.is_sealed {
+                        cleared_history.push(record.id.clone());
+                    } else if local.title != record.title || local.content != record.content {
+                        revisions.push((
+                            record.id.clone(),
+                            local.updated_at,
+                            history::body(local.title, &local.content, &record.content),
+                        ));
+                    }
+                }
+            }
+            records.push(record.clone());
+            applied.push(record);
+        }
+
+        let mut trashed = Vec::new();
+        if strategy == ImportStrategy::Replace {
+            let (removed, kept): (Vec<_>, Vec<_>) =
+                records.into_iter().partition(|r| !incoming.contains(&r.id));
+            records = kept;
+            trashed = removed.iter().map(|r| r.id.clone()).collect();
+            trash.extend(removed.into_iter().map(|r| (r, now.clone())));
+        }
+
+        // 只导入被写入的记录引用到的媒体；中途失败留下的文件由下次清理删除
+        let mut referenced = HashSet::new();
+        for record in &applied {
+            referenced.extend(record.images.iter().cloned());
+            referenced.extend(record.music_url.iter().cloned());
+            referenced.extend(self.seal.sealed_images(record)?);
+        }
+        self.mark_media_dirty()?;
+        for (media_ref, archived_ref) in &plan.media {
+            if referenced.contains(media_ref) && !self.media.exists(media_ref) {
+                let kind = MediaKind::of_ref(media_ref)
+                    .ok_or_else(|| format!("无效的媒体引用: {}", media_ref))?;
+                let data = Zeroizing::new(reader.read_media(archived_ref)?);
+                let (_, size) = ingest_stripped(
+                    &self.media,
+                    &mut self.keyring,
+                    kind,
+                    &data,
+                    media_ref,
+                    settings,
+                )?;
+                self.database
+                    .register_media(media_ref, kind.as_str(), size, &now)?;
+            }
+            step();
+        }
+
+        let stored = applied
+            .iter()
+            .map(|r| encrypt(&mut self.keyring, &self.tag_cipher, r))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut stored_revisions = Vec::new();
+        for (id, saved_at, body) in revisions {
+            let plaintext = serde_json::to_vec(&body).map_err(|e| e.to_string())?;
+            stored_revisions.push(StoredRevision {
+                record_id: id.clone(),
+                number: 0,
+                saved_at,
+                replaced_at: now.clone(),
+                body: self.keyring.encrypt_for(&id, &plaintext)?,
+            });
+        }
+        self.keyring.save(self.vault.master_key())?;
+        self.database.import_records(
+            &ImportBatch {
+                records: &stored,
+                revisions: &stored_revisions,
+                cleared_history: &cleared_history,
+                trashed: &trashed,
+                deleted_at: &now,
+            },
+            &Retention::new(settings, self.seal.now()),
+        )?;
+        step();
+
+        // 数据库提交成功后才替换内存中的记录
+        self.records = records;
+        self.trash = trash;
+        self.index = SearchIndex::build(&self.records);
+        self.seal.persist_clock()?;
+        self.apply_due()?;
+        Ok(ImportReport {
+            preview: plan.preview,
+            strategy,
+            imported: applied.len(),
+            trashed: trashed.len(),
+            skipped,
+        })
+    }
+
+    // 标出导出中与已有记录重复的日记，不做任何修改。导出由调用方在仓库锁外打开并解析
+    pub fn preview_journal(&mut self, parsed: &ParsedJournal) -> Result<JournalPreview, String> {
+        self.apply_due()?;
+        Ok(self.journal_preview(parsed))
+    }
+
+    // 把在仓库锁外准备好的日记导入为新记录，照片存入媒体库。
+    // 任何一张照片或记录写入失败时撤销本次导入
+    pub fn import_journal(
+        &mut self,
+        journal: PreparedJournal,
+        progress: &mut dyn FnMut(ArchiveProgress),
+    ) -> Result<JournalImportReport, String> {
+        self.apply_due()?;
+        // 准备照片的步骤已在锁外完成
+        let total = importer::import_steps(journal.entries.len());
+        let mut done = journal.entries.len();
+        let mut step = || {
+            done += 1;
+            progress(ArchiveProgress {
+                operation: ArchiveOperation::ImportJournal,
+                done,
+                total,
+            });
+        };
+        let mut photos = 0;
+        let mut missing_photos = Vec::new();
+        let mut ids = Vec::new();
+        let count = self.records.len();
+        // 失败时只清理本次导入的照片，编辑中已上传的媒体保持不变
+        let pending: HashSet<String> = self.pending_media.keys().cloned().collect();
+        let mut ingested = Vec::new();
+        let mut failure = None;
+        'entries: for PreparedEntry {
+            entry,
+            photos: prepared,
+        } in journal.entries
+        {
+            let mut images = Vec::new();
+            for (name, photo) in prepared {
+                let Some(photo) = photo else {
+                    missing_photos.push(name);
+                    continue;
+                };
+                match self.ingest_prepared(&photo, &name) {
+                    Ok(media_ref) => {
+                        ingested.push(media_ref.clone());
+                        if !images.contains(&media_ref) {
+                            images.push(media_ref);
+                        }
+                    }
+                    Err(e) => {
+                        failure = Some(e);
+                        break 'entries;
+                    }
+                }
+            }
+            photos += images.len();
+            missing_photos.extend(entry.missing_photos);
+
+            // 逐条加入内存，后面的日记按已导入的标签统一大小写
+            let tags = match self.canonical_tags(entry.tags) {
+                Ok(tags) => tags,
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            };
+            let captured_at = entry.captured_at.or_else(|| self.earliest_capture(&images));
+            let record = EmotionalRecord {
+                id: generate_id(),
+                title: entry.title,
+                content: entry.content,
+                images,
+                music_url: None,
+                music_title: entry.music_title,
+                updated_at: entry.updated_at.unwrap_or_else(|| entry.created_at.clone()),
+                created_at: entry.created_at,
+                is_sealed: false,
+                seal_until: None,
+                auto_destroy_at: None,
+                captured_at,
+                tags,
+                mood: entry.mood,
+                sealed_payload: None,
+            };
+            ids.push(record.id.clone());
+            self.records.push(record);
+            step();
+        }
+        let saved = match failure {
+            Some(e) => Err(e),
+            None => self.save_records(&ids),
+        };
+        if let Err(e) = saved {
+            return Err(
+                match self.discard_journal(count, &ids, &ingested, &pending) {
+                    Ok(()) => e,
+                    // 未能删除的照片仍标记为待清理，之后作为孤立文件删除
+                    Err(cleanup) => format!("{}；清理已导入的照片失败: {}", e, cleanup),
+                },
+            );
+        }
+        step();
+
+        Ok(JournalImportReport {
+            preview: journal.preview,
+            imported: ids.len(),
+            photos,
+            missing_photos,
+        })
+    }
+
+    pub fn change_passphrase(&mut self, change: PassphraseChange) -> Result<(), String> {
+        self.vault.change_passphrase(change)
+    }
+
+    // 拍摄时间须在去除元数据之前读出
+    pub fn ingest_media(
+        &mut self,
+        kind: MediaKind,
+        data: &[u8],
+        file_name: &str,
+        settings: &Settings,
+    ) -> Result<String, String> {
+        let captured_at = match kind {
+            MediaKind::Image if settings.keep_capture_date => metadata::capture_date(data),
+            _ => None,
+        };
+        // 导入后未被保存到记录的文件须在之后清理
+        self.mark_media_dirty()?;
+        let (media_ref, size) = ingest_stripped(
+            &self.media,
+            &mut self.keyring,
+            kind,
+            data,
+            file_name,
+            settings,
+        )?;
+        self.database
+            .register_media(&media_ref, kind.as_str(), size, &self.seal.now_iso())?;
+        self.pending_media.insert(media_ref.clone(), captured_at);
+        Ok(media_ref)
+    }
+
+    // 保存在仓库锁外准备好的图片，与 ingest_media 一样登记为刚导入的媒体
+    fn ingest_prepared(
+        &mut self,
+        image: &PreparedImage,
+        file_name: &str,
+    ) -> Result<String, String> {
+        self.mark_media_dirty()?;
+        let media_ref = self
+            .media
+            .ingest_prepared(image, file_name, &mut self.keyring)?;
+        self.database.register_media(
+            &media_ref,
+            MediaKind::Image.as_str(),
+            image.data.len() as u64,
+            &self.seal.now_iso(),
+        )?;
+        self.pending_media
+            .insert(media_ref.clone(), image.captured_at);
+        Ok(media_ref)
+    }
+
+    // 一次性导入旧版前端保存在 localStorage（pick-up-memories-records）中的记录，
+    // 已存在的 id 跳过，返回导入的条数
+    pub fn import_local_storage(
+        &mut self,
+        records: Vec<EmotionalRecord>,
+        settings: &Settings,
+    ) -> Result<usize, String> {
+        if self.database.meta(LOCAL_STORAGE_IMPORTED)?.is_some() {
+            return Ok(0);
+        }
+        let imported = self.merge_legacy(records, settings)?;
+        self.database
+            .set_meta(LOCAL_STORAGE_IMPORTED, &self.seal.now_iso())?;
+        Ok(imported)
+    }
+
+    // 旧版前端迁移到这些命令之前仍在读写明文 records.json，文件保留不删。
+    // 文件内容与上次导入时不同就导入其中新增的记录，已有的 id 跳过，返回导入的条数
+    pub fn import_legacy_file(
+        &mut self,
+        path: &Path,
+        settings: &Settings,
+    ) -> Result<usize, String> {
+        if !path.exists() {
+            return Ok(0);
+        }
+        let content = fs::read(path).map_err(|e| format!("读取记录文件失败: {}", e))?;
+        // 以主密钥计算摘要，数据库中不留明文内容的哈希
+        let digest: String = crypto::keyed_hash(self.vault.master_key(), &content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if self.database.meta(LEGACY_FILE_IMPORTED)?.as_deref() == Some(digest.as_str()) {
+            return Ok(0);
+        }
+        let records = schema::parse_records(&content)?;
+        let imported = self.merge_legacy(records, settings)?;
+        self.database.set_meta(LEGACY_FILE_IMPORTED, &digest)?;
+        Ok(imported)
+    }
+
+    // 只提供仍被未尘封记录引用（或刚导入）的媒体；尘封记录的图片引用位于加密载荷中，
+    // 已销毁记录的引用随记录一并消失，因此二者都无法通过协议读取。
+    // 返回的读取器在释放仓库锁后再按范围解密
+    pub fn open_visible_media(&self, media_ref: &str) -> Result<Option<MediaReader>, String> {
+        if !self.is_visible(media_ref)? {
+            return Ok(None);
+        }
+        self.media.open(media_ref, &self.keyring).map(Some)
+    }
+
+    // 缩略图与原图遵循相同的可见性规则
+    pub fn read_visible_thumbnail(
+        &self,
+        media_ref: &str,
+        size: u32,
+    ) -> Result<Option<Vec<u8>>, String> {
+        if !self.is_visible(media_ref)? {
+            return Ok(None);
+        }
+        self.media
+            .thumbnail(media_ref, size, &self.keyring)
+            .map(Some)
+    }
+
+    pub fn thumbnail_info(
+        &self,
+        media_ref: &str,
+        size: u32,
+    ) -> Result<Option<ThumbnailInfo>, String> {
+        if !self.is_visible(media_ref)? {
+            return Ok(None);
+        }
+        let info = self.media.image_info(media_ref, &self.keyring)?;
+        Ok(Some(ThumbnailInfo::new(media_ref, size, info)))
+    }
+
+    pub fn recovered(&self) -> bool {
+        self.recovered
+    }
+
+    // 本次解锁时数据库已损坏并从快照恢复，损坏的文件改名保留在此
+    pub fn corrupt_database(&self) -> Option<&Path> {
+        self.database.corrupt_copy()
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.seal.now()
+    }
+
+    // 在 path 写入一致的数据库副本，供备份使用
+    pub fn snapshot_database(&self, path: &Path) -> Result<(), String> {
+        self.database.snapshot_to(path)
+    }
+
+    // 尚未从备份中删除的已销毁密钥，以及解开备份中密钥环所需的主密钥；没有时返回 None
+    pub fn shredded_keys(&self) -> Option<(Shredded, Zeroizing<Key>)> {
+        let shredded = self.keyring.shredded();
+        (!shredded.is_empty()).then(|| (shredded.clone(), Zeroizing::new(*self.vault.master_key())))
+    }
+
+    // 已从全部备份中删除的密钥不再记录
+    pub fn forget_shredded(&mut self, done: &Shredded) -> Result<(), String> {
+        self.keyring.forget_shredded(done);
+        self.keyring.save(self.vault.master_key())
+    }
+
+    // 未经到期处理的记录快照，供调度器规划事件
+    pub fn records(&self) -> &[EmotionalRecord] {
+        &self.records
+    }
+
+    // 取出自上次调用以来发生的解封与销毁事件
+    pub fn take_events(&mut self) -> Vec<MemoryEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // 处理到期的解封与自动销毁，有变化时写回磁盘并记录事件
+    pub fn apply_due(&mut self) -> Result<(), String> {
+        let released = self.seal.release_due(&mut self.records)?;
+        if !released.is_empty() {
+            self.save_records(&released)?;
+            for id in released {
+                let title = self
+                    .records
+                    .iter()
+                    .find(|r| r.id == id)
+                    .map(|r| r.title.clone());
+                self.events.push(MemoryEvent {
+                    kind: EventKind::Unseal,
+                    record_id: id,
+                    title,
+                });
+            }
+        }
+        self.destroy_expired()
+    }
+
+    // 规范化标签，与已有标签同名（不区分大小写）时沿用已有的写法
+    fn canonical_tags(&self, names: Vec<String>) -> Result<Vec<String>, String> {
+        let names = tags::normalize_all(names)?;
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                self.records
+                    .iter()
+                    .flat_map(|r| &r.tags)
+                    .find(|t| tags::same(t, &name))
+                    .cloned()
+                    .unwrap_or(name)
+            })
+            .collect())
+    }
+
+    // 与已有记录或导出中更早的日记创建时间相同、文字相同的日记视为重复。
+    // 尘封记录的正文不可见，不参与比较
+    fn journal_preview(&self, parsed: &ParsedJournal) -> JournalPreview {
+        let mut seen: HashSet<(i64, String)> = self
+            .records
+            .iter()
+            .filter(|r| !r.is_sealed)
+            .filter_map(|r| importer::fingerprint(&r.created_at, &r.title, &r.content))
+            .collect();
+        let entries: Vec<JournalEntryPreview> = parsed
+            .entries
+            .iter()
+            .map(|entry| JournalEntryPreview {
+                source: entry.source.clone(),
+                title: entry.title.clone(),
+                created_at: entry.created_at.clone(),
+                tags: entry.tags.clone(),
+                photos: entry.photos.len(),
+                missing_photos: entry.missing_photos.clone(),
+                duplicate: importer::fingerprint(&entry.created_at, &entry.title, &entry.content)
+                    .is_some_and(|key| !seen.insert(key)),
+            })
+            .collect();
+        JournalPreview {
+            format: parsed.format,
+            duplicates: entries.iter().filter(|e| e.duplicate).count(),
+            entries,
+            skipped: parsed.skipped.clone(),
+        }
+    }
+
+    // 解密并还原记录的全部历史版本。尘封期间历史与正文一样不可查看
+    fn revisions(&mut self, id: &str) -> Result<(EmotionalRecord, Vec<Revision>), String> {
+        self.apply_due()?;
+        let current = self
+            .records
+            .iter()
+            .find(|r| r.id == id)
+            .cloned()
+            .ok_or_else(|| "记录不存在".to_string())?;
+        if current.is_sealed {
+            return Err("尘封中的记忆无法查看历史版本".to_string());
+        }
+
+        let mut opened = Vec::new();
+        for stored in self.database.load_revisions(id)? {
+            let plaintext = self
+                .keyring
+                .decrypt_for(id, &stored.body)?
+                .ok_or_else(|| "记录不存在".to_string())?;
+            let body: RevisionBody =
+                serde_json::from_slice(&plaintext).map_err(|e| format!("历史版本已损坏: {}", e))?;
+            opened.push((stored, body));
+        }
+        let revisions = history::reconstruct(&current.content, opened)?;
+        Ok((current, revisions))
+    }
+
+    // 读取并校验归档中的记录与媒体，换用本机的媒体引用与尘封密钥后逐条与本地记录对比
+    fn plan_import(
+        &self,
+        reader: &mut ArchiveReader,
+        settings: &Settings,
+        step: &mut dyn FnMut(),
+    ) -> Result<ImportPlan, String> {
+        let mut records = schema::parse_records(&reader.read(archive::RECORDS_FILE)?)?;
+        step();
+        let archived = reader.media_refs();
+        let has_seal_key = reader.contains(archive::SEAL_KEY_FILE);
+        archive::validate_records(
+            &records,
+            &archived.iter().cloned().collect(),
+            has_seal_key,
+            settings,
+        )?;
+
+        // 媒体引用中的哈希由导出方的主密钥算出，须按（去除元数据后的）内容重新计算本机的引用
+        let mut remap = HashMap::new();
+        let mut media = Vec::new();
+        for archived_ref in archived {
+            let kind = MediaKind::of_ref(&archived_ref)
+                .ok_or_else(|| format!("无效的媒体引用: {}", archived_ref))?;
+            let data = Zeroizing::new(reader.read_media(&archived_ref)?);
+            let data = strip_media(kind, &data, settings)?;
+            let local = self.media.reference(kind, &data, &archived_ref)?;
+            media.push((local.clone(), archived_ref.clone()));
+            remap.insert(archived_ref, local);
+            step();
+        }
+        let seal_key = match has_seal_key {
+            true => Some(reader.read_seal_key()?),
+            false => None,
+        };
+        for record in records.iter_mut() {
+            for media_ref in record.images.iter_mut().chain(record.music_url.iter_mut()) {
+                if let Some(local) = remap.get(media_ref.as_str()) {
+                    *media_ref = local.clone();
+                }
+            }
+            if let Some(key) = &seal_key {
+                self.seal.import_payload(record, key, &remap)?;
+            }
+            record.tags = self.canonical_tags(std::mem::take(&mut record.tags))?;
+        }
+
+        let manifest = reader.manifest();
+        let mut preview = ImportPreview {
+            exported_at: manifest.exported_at.clone(),
+            schema_version: manifest.schema_version,
+            encrypted: manifest.encryption.is_some(),
+            records: records.len(),
+            media: media.len(),
+            new_ids: Vec::new(),
+            changed_ids: Vec::new(),
+            conflicting_ids: Vec::new(),
+            unchanged_ids: Vec::new(),
+        };
+        let mut classified = Vec::with_capacity(records.len());
+        for record in records {
+            let local = self
+                .records
+                .iter()
+                .chain(self.trash.iter().map(|(r, _)| r))
+                .find(|r| r.id == record.id);
+            let (change, ids) = match local {
+                None => (Change::New, &mut preview.new_ids),
+                Some(local) if self.seal.same_content(local, &record)? => {
+                    (Change::Unchanged, &mut preview.unchanged_ids)
+                }
+                Some(local) if is_newer(&record.updated_at, &local.updated_at) => {
+                    (Change::Changed, &mut preview.changed_ids)
+                }
+                Some(_) => (Change::Conflicting, &mut preview.conflicting_ids),
+            };
+            ids.push(record.id.clone());
+            classified.push((record, change));
+        }
+        Ok(ImportPlan {
+            preview,
+            records: classified,
+            media,
+        })
+    }
+
+    fn ensure_exist(&self, ids: &[String]) -> Result<(), String> {
+        match ids
+            .iter()
+            .find(|id| !self.records.iter().any(|r| &r.id == *id))
+        {
+            Some(id) => Err(format!("记录不存在: {}", id)),
+            None => Ok(()),
+        }
+    }
+
+    fn cloned(&self, ids: &[String]) -> Vec<EmotionalRecord> {
+        self.records
+            .iter()
+            .filter(|r| ids.contains(&r.id))
+            .cloned()
+            .collect()
+    }
+
+    // 相同内容的媒体去重后由多条记录共用，只要其中一条仍在尘封中就不可访问
+    fn is_visible(&self, media_ref: &str) -> Result<bool, String> {
+        for record in self.records.iter().filter(|r| r.is_sealed) {
+            if self
+                .seal
+                .sealed_images(record)?
+                .iter()
+                .any(|i| i == media_ref)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(self.pending_media.contains_key(media_ref)
+            || self.records.iter().filter(|r| !r.is_sealed).any(|r| {
+                r.images.iter().any(|i| i == media_ref) || r.music_url.as_deref() == Some(media_ref)
+            }))
+    }
+
+    // 本次解锁期间导入的图片中最早的拍摄时间
+    fn earliest_capture(&self, images: &[String]) -> Option<String> {
+        images
+            .iter()
+            .filter_map(|image| self.pending_media.get(image).copied().flatten())
+            .min()
+            .map(|t| t.to_rfc3339())
+    }
+
+    // 删除媒体库中不再被任何记录（含回收站与尘封载荷中的图片）引用的文件，
+    // 本次解锁期间刚导入、尚未保存到记录的媒体除外。先销毁这些文件的密钥再删除文件
+    fn remove_orphaned_media(&mut self) -> Result<(), String> {
+        let referenced = self.referenced_media(&[])?;
+        let orphans: Vec<String> = self
+            .media
+            .list()?
+            .into_iter()
+            .filter(|media_ref| !referenced.contains(media_ref))
+            .collect();
+        self.retain_shared_keys(&[])?;
+        self.keyring.save(self.vault.master_key())?;
+        self.media.remove(&orphans)?;
+        self.database.unregister_media(&orphans)?;
+        // 刚导入、尚未保存到记录的媒体在锁定后可能成为孤立文件，下次仍须检查
+        self.media_dirty = !self.pending_media.is_empty();
+        self.database
+            .set_meta(MEDIA_DIRTY, &self.media_dirty.to_string())
+    }
+
+    // 导入旧版前端保存的记录，已存在（含回收站中）的 id 跳过
+    fn merge_legacy(
+        &mut self,
+        records: Vec<EmotionalRecord>,
+        settings: &Settings,
+    ) -> Result<usize, String> {
+        let mut seen: HashSet<String> = self
+            .records
+            .iter()
+            .chain(self.trash.iter().map(|(r, _)| r))
+            .map(|r| r.id.clone())
+            .collect();
+        let mut imported: Vec<EmotionalRecord> = records
+            .into_iter()
+            .filter(|r| seen.insert(r.id.clone()))
+            .collect();
+        self.seal.protect_legacy(&mut imported)?;
+        externalize_images(&self.media, &mut self.keyring, &mut imported, settings)?;
+
+        let ids: Vec<String> = imported.iter().map(|r| r.id.clone()).collect();
+        self.records.extend(imported);
+        self.save_records(&ids)?;
+        self.apply_due()?;
+        Ok(ids.len())
+    }
+
+    // 撤销未能保存的日记导入：移除已加入内存的记录与其密钥，删除只被这些记录引用的照片
+    fn discard_journal(
+        &mut self,
+        count: usize,
+        ids: &[String],
+        ingested: &[String],
+        pending: &HashSet<String>,
+    ) -> Result<(), String> {
+        self.records.truncate(count);
+        for id in ids {
+            self.keyring.destroy(id);
+        }
+        let fresh: BTreeSet<&String> = ingested.iter().filter(|r| !pending.contains(*r)).collect();
+        for media_ref in &fresh {
+            self.pending_media.remove(*media_ref);
+        }
+        let referenced = self.referenced_media(&[])?;
+        let orphans: Vec<String> = fresh
+            .into_iter()
+            .filter(|r| !referenced.contains(*r))
+            .cloned()
+            .collect();
+        self.retain_shared_keys(&[])?;
+        self.keyring.save(self.vault.master_key())?;
+        self.media.remove(&orphans)?;
+        self.database.unregister_media(&orphans)
+    }
+
+    // 标记写入数据库，重启后仍会清理
+    fn mark_media_dirty(&mut self) -> Result<(), String> {
+        if !self.media_dirty {
+            self.database.set_meta(MEDIA_DIRTY, "true")?;
+            self.media_dirty = true;
+        }
+        Ok(())
+    }
+
+    // 仍被记录（含回收站与尘封载荷中的图片）或本次解锁期间导入的媒体引用，excluding 中的记录除外
+    fn referenced_media(&self, excluding: &[String]) -> Result<HashSet<String>, String> {
+        let mut referenced: HashSet<String> = self.pending_media.keys().cloned().collect();
+        for record in self.all_records().filter(|r| !excluding.contains(&r.id)) {
+            referenced.extend(record.images.iter().cloned());
+            referenced.extend(record.music_url.iter().cloned());
+            referenced.extend(self.seal.sealed_images(record)?);
+        }
+        Ok(referenced)
+    }
+
+    // 销毁不再被其余记录引用的媒体与标签密钥，调用方负责保存密钥环
+    fn retain_shared_keys(&mut self, excluding: &[String]) -> Result<(), String> {
+        let media: HashSet<String> = self
+            .referenced_media(excluding)?
+            .iter()
+            .filter_map(|media_ref| self.media.content_id_of(media_ref).ok())
+            .collect();
+        let tags: HashSet<String> = self
+            .all_records()
+            .filter(|r| !excluding.contains(&r.id))
+            .flat_map(|r| &r.tags)
+            .map(|tag| self.tag_cipher.lookup(tag))
+            .collect();
+        self.keyring.retain_shared(&media, &tags);
+        Ok(())
+    }
+
+    fn all_records(&self) -> impl Iterator<Item = &EmotionalRecord> {
+        self.records.iter().chain(self.trash.iter().map(|(r, _)| r))
+    }
+
+    // 旧版的媒体文件与标签名以主密钥加密：先为每个媒体文件生成密钥并保存密钥环，
+    // 再逐个改用各自的密钥加密，并重写全部记录使标签名改用标签各自的密钥。
+    // 中途失败时下次解锁重新执行，已改过的文件会被跳过
+    fn migrate_shared_keys(&mut self) -> Result<(), String> {
+        let media = self.media.list()?;
+        let mut keys = Vec::with_capacity(media.len());
+        for media_ref in &media {
+            keys.push(
+                self.keyring
+                    .media_key(&self.media.content_id_of(media_ref)?),
+            );
+        }
+        self.keyring.save(self.vault.master_key())?;
+        for (media_ref, key) in media.iter().zip(&keys) {
+            self.media.rekey(media_ref, self.vault.master_key(), key)?;
+        }
+        self.media.clear_thumbnails()?;
+
+        let stored = self
+            .records
+            .iter()
+            .chain(self.trash.iter().map(|(r, _)| r))
+            .map(|r| encrypt(&mut self.keyring, &self.tag_cipher, r))
+            .collect::<Result<Vec<_>, String>>()?;
+        self.keyring.save(self.vault.master_key())?;
+        self.database.save_records(&stored)?;
+        self.database
+            .set_meta(SHARED_KEYS_MIGRATED, &self.seal.now_iso())?;
+        self.tag_cipher = TagCipher::new(self.vault.master_key(), false);
+        self.mark_media_dirty()
+    }
+
+    // 销毁已到自动销毁时间的记录（包括回收站中的）：先销毁数据密钥，再在一个事务中删除记录并写入销毁日志，
+    // 提交成功后才从内存中移除；失败时下次到期处理重试
+    fn destroy_expired(&mut self) -> Result<(), String> {
+        let now = self.seal.now();
+        let expiring =
+            |r: &EmotionalRecord| r.auto_destroy_at.as_deref().is_some_and(|t| is_due(t, now));
+        let ids: Vec<String> = self
+            .all_records()
+            .filter(|r| expiring(r))
+            .map(|r| r.id.clone())
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.remove_records(&ids, Some(&self.seal.now_iso()))?;
+
+        let (mut expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.records)
+            .into_iter()
+            .partition(|r| ids.contains(&r.id));
+        self.records = kept;
+        let (trashed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.trash)
+            .into_iter()
+            .partition(|(r, _)| ids.contains(&r.id));
+        self.trash = kept;
+        expired.extend(trashed.into_iter().map(|(r, _)| r));
+        self.events.extend(expired.into_iter().map(|r| MemoryEvent {
+            kind: EventKind::Destroy,
+            title: (!r.is_sealed).then_some(r.title),
+            record_id: r.id,
+        }));
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<(), String> {
+        let ids: Vec<String> = self.records.iter().map(|r| r.id.clone()).collect();
+        self.save_records(&ids)
+    }
+
+    fn save_record(&mut self, id: &str) -> Result<(), String> {
+        self.save_records(&[id.to_string()])
+    }
+
+    // 在一个事务中写入指定记录。密钥环须先于数据库写入，避免数据库引用尚未持久化的数据密钥
+    fn save_records(&mut self, ids: &[String]) -> Result<(), String> {
+        let records = self
+            .records
+            .iter()
+            .filter(|r| ids.contains(&r.id))
+            .map(|r| encrypt(&mut self.keyring, &self.tag_cipher, r))
+            .collect::<Result<Vec<_>, String>>()?;
+        self.keyring.save(self.vault.master_key())?;
+        self.database.save_records(&records)?;
+        for record in self.records.iter().filter(|r| ids.contains(&r.id)) {
+            self.index.insert(record);
+            // 已写入记录的媒体不再算作刚导入
+            for media_ref in record.images.iter().chain(&record.music_url) {
+                self.pending_media.remove(media_ref);
+            }
+        }
+        self.seal.persist_clock()
+    }
+
+    // 与 save_records 相同，同时写入被替代的上一版本并按保留策略清理历史
+    fn save_revision(
+        &mut self,
+        id: &str,
+        saved_at: String,
+        body: &RevisionBody,
+        settings: &Settings,
+    ) -> Result<(), String> {
+        let record = self
+            .records
+            .iter()
+            .find(|r| r.id == id)
+            .ok_or_else(|| "记录不存在".to_string())?;
+        let stored = encrypt(&mut self.keyring, &self.tag_cipher, record)?;
+        let plaintext = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+        let revision = StoredRevision {
+            record_id: id.to_string(),
+            number: 0,
+            saved_at,
+            replaced_at: record.updated_at.clone(),
+            body: self.keyring.encrypt_for(id, &plaintext)?,
+        };
+        self.keyring.save(self.vault.master_key())?;
+        self.database.save_revision(
+            &stored,
+            &revision,
+            &Retention::new(settings, self.seal.now()),
+        )?;
+        self.index.insert(record);
+        for media_ref in record.images.iter().chain(&record.music_url) {
+            self.pending_media.remove(media_ref);
+        }
+        self.seal.persist_clock()
+    }
+
+    // 先销毁数据密钥再删除数据库中的行，此后任何残留的密文都无法解开。
+    // 只被这些记录引用的媒体与标签的密钥一并销毁；自动销毁时传入 destroyed_at 记入销毁日志
+    fn remove_records(&mut self, ids: &[String], destroyed_at: Option<&str>) -> Result<(), String> {
+        self.mark_media_dirty()?;
+        for id in ids {
+            self.keyring.destroy(id);
+        }
+        self.retain_shared_keys(ids)?;
+        self.keyring.save(self.vault.master_key())?;
+        self.database.delete_records(ids, destroyed_at)?;
+        for id in ids {
+            self.index.remove(id);
+        }
+        self.seal.persist_clock()
+    }
+}
+
+// 加密记录内容与标签
+fn encrypt(
+    keyring: &mut Keyring,
+    tag_cipher: &TagCipher,
+    record: &EmotionalRecord,
+) -> Result<StoredRecord, String> {
+    let mut stored = keyring.encrypt_record(record)?;
+    stored.tags = record
+        .tags
+        .iter()
+        .map(|tag| tag_cipher.seal(tag, keyring))
+        .collect::<Result<_, _>>()?;
+    Ok(stored)
+}
+
+// 移入回收站后彻底删除的时间
+fn purge_at(deleted_at: &str, retention_days: u32) -> String {
+    DateTime::parse_from_rfc3339(deleted_at)
+        .ok()
+        .and_then(|t| {
+            t.with_timezone(&Utc)
+                .checked_add_days(Days::new(u64::from(retention_days)))
+        })
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| deleted_at.to_string())
+}
+
+fn find_revision(revisions: Vec<Revision>, number: u32) -> Result<Revision, String> {
+    revisions
+        .into_iter()
+        .find(|r| r.number == number)
+        .ok_or_else(|| format!("历史版本不存在: {}", number))
+}
+
+// 把未尘封记录中内嵌的 data URL 图片转存到媒体库，返回是否有变化
+fn externalize_images(
+    media: &MediaStore,
+    keyring: &mut Keyring,
+    records: &mut [EmotionalRecord],
+    settings: &Settings,
+) -> Result<bool, String> {
+    let mut changed = false;
+    for record in records.iter_mut().filter(|r| !r.is_sealed) {
+        for image in record.images.iter_mut() {
+            if let Some((data, file_name)) = media::parse_data_url(image) {
+                let kind = MediaKind::Image;
+                *image = ingest_stripped(media, keyring, kind, &data, &file_name, settings)?.0;
+                changed = true;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+// 所有图片都经过这里入库：按设置去除元数据后再保存，返回引用与保存的字节数
+fn ingest_stripped(
+    media: &MediaStore,
+    keyring: &mut Keyring,
+    kind: MediaKind,
+    data: &[u8],
+    file_name: &str,
+    settings: &Settings,
+) -> Result<(String, u64), String> {
+    let data = strip_media(kind, data, settings)?;
+    let media_ref = media.ingest(kind, &data, file_name, keyring)?;
+    Ok((media_ref, data.len() as u64))
+}
+
+// 在仓库锁外准备待导入的图片，拍摄时间须在去除元数据之前读出
+pub fn prepare_image(data: &[u8], settings: &Settings) -> Result<PreparedImage, String> {
+    let captured_at = settings
+        .keep_capture_date
+        .then(|| metadata::capture_date(data))
+        .flatten();
+    let data = strip_media(MediaKind::Image, data, settings)?.into_owned();
+    Ok(PreparedImage::new(data, captured_at))
+}
+
+// 入库前的内容；预览归档时据此计算本机引用，须与实际入库时一致
+fn strip_media<'a>(
+    kind: MediaKind,
+    data: &'a [u8],
+    settings: &Settings,
+) -> Result<Cow<'a, [u8]>, String> {
+    match kind {
+        MediaKind::Image if settings.strip_image_metadata => metadata::strip(data).map(Cow::Owned),
+        _ => Ok(Cow::Borrowed(data)),
+    }
+}
+
+// 解出旧版保险库内嵌的记录，并重放检查点之后的预写日志
+fn read_legacy_vault(
+    keyring: &Keyring,
+    data: &[u8],
+    journal_path: &Path,
+    master_key: &Key,
+) -> Result<Vec<EmotionalRecord>, String> {
+    let data: VaultData =
+        serde_json::from_slice(data).map_err(|e| format!("解析记录数据失败: {}", e))?;
+    let (mut records, checkpoint) = match data {
+        VaultData::Current { sequence, records } => {
+            let mut opened = Vec::with_capacity(records.len());
+            for stored in records {
+                opened.extend(keyring.decrypt_record(stored)?);
+            }
+            (opened, sequence)
+        }
+        // 明文数组格式出现在加密尘封载荷之后，属于第 1 版
+        VaultData::Legacy(records) => (schema::upgrade_records(records, 1)?, 0),
+    };
+    let entries = journal::read_entries(journal_path, master_key)?;
+    replay(keyring, entries, checkpoint, &mut records)?;
+    Ok(records)
+}
+
+// 把序号大于检查点的日志条目应用到记录上
+fn replay(
+    keyring: &Keyring,
+    entries: Vec<JournalEntry>,
+    checkpoint: u64,
+    records: &mut Vec<EmotionalRecord>,
+) -> Result<(), String> {
+    for entry in entries.into_iter().filter(|e| e.sequence > checkpoint) {
+        match entry.op {
+            JournalOp::Upsert { record } => {
+                let position = records.iter().position(|r| r.id == record.id);
+                // 数据密钥已销毁的记录保持删除状态
+                match (keyring.decrypt_record(record)?, position) {
+                    (Some(record), Some(index)) => records[index] = record,
+                    (Some(record), None) => records.push(record),
+                    (None, Some(index)) => {
+                        records.remove(index);
+                    }
+                    (None, None) => {}
+                }
+            }
+            JournalOp::Delete { id } => records.retain(|r| r.id != id),
+        }
+    }
+    Ok(())
+}
+
+// 图片与音乐须先经 import_media 导入媒体库，不接受内嵌的 data URL
+fn check_inline_media<'a>(values: impl IntoIterator<Item = &'a String>) -> Result<(), String> {
+    if values.into_iter().any(|v| v.starts_with("data:")) {
+        return Err("图片与音乐须先导入媒体库，不支持内嵌的 data URL".to_string());
+    }
+    Ok(())
+}
+
+fn matches_filter(record: &EmotionalRecord, filter: &RecordFilter) -> bool {
+    let mood = record.mood.as_ref();
+    filter
+        .tags
+        .iter()
+        .all(|tag| record.tags.iter().any(|t| tags::same(t, tag)))
+        && (filter.moods.is_empty() || mood.is_some_and(|m| filter.moods.contains(&m.id)))
+        && filter
+            .min_intensity
+            .is_none_or(|min| mood.is_some_and(|m| m.intensity >= min))
+}
+
+// 解析 ISO 时间字符串，无法解析时视为未到期
+pub fn is_due(timestamp: &str, now: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.with_timezone(&Utc) <= now)
+        .unwrap_or(false)
+}
+
+// 两个 ISO 时间中 a 是否晚于 b，无法解析时视为否
+fn is_newer(a: &str, b: &str) -> bool {
+    match (
+        DateTime::parse_from_rfc3339(a),
+        DateTime::parse_from_rfc3339(b),
+    ) {
+        (Ok(a), Ok(b)) => a > b,
+        _ => false,
+    }
+}
+
+// 与前端一致的 id 格式：record_<毫秒时间戳>_<9 位随机串>
+fn generate_id() -> String {
+    const CHARSET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut rng = rand::thread_rng();
+    let suffix: String = (0..9)
+        .map(|_| CHARSET[rng.gen_range(0..CHARSET.len())] as char)
+        .collect();
+    format!("record_{}_{}", Utc::now().timestamp_millis(), suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::models::{CreateRecordInput, UpdateRecordInput};
+    use crate::seal::TrustedClock;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn temp_paths() -> AppPaths {
+        let dir = std::env::temp_dir().join(format!("store-{:016x}", rand::random::<u64>()));
+        let paths = AppPaths::from_data_dir(dir);
+        for dir in [&paths.images_dir, &paths.music_dir, &paths.thumbs_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        paths
+    }
+
+    // 与 Session::unlock 相同：没有保险库时新建，否则解锁后打开
+    fn open_store(paths: &AppPaths) -> RecordStore {
+        let settings = Settings::default();
+        let clock = TrustedClock::open(paths.clock_file());
+        if Vault::exists(&paths.vault_file()) {
+            let (vault, legacy) = Vault::unlock(paths.vault_file(), PASSPHRASE).unwrap();
+            let seal = SealEngine::open(paths.seal_key_file(), clock, vault.master_key()).unwrap();
+            RecordStore::open(vault, legacy, paths, seal, &settings).unwrap()
+        } else {
+            let vault = Vault::create(paths.vault_file(), PASSPHRASE).unwrap();
+            let seal = SealEngine::open(paths.seal_key_file(), clock, vault.master_key()).unwrap();
+            RecordStore::import(vault, Vec::new(), paths, seal, &settings).unwrap()
+        }
+    }
+
+    fn input(title: &str, tags: &[&str]) -> CreateRecordInput {
+        CreateRecordInput {
+            title: title.to_string(),
+            content: format!("{}的正文", title),
+            images: Vec::new(),
+            music_url: None,
+            music_title: None,
+            captured_at: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            mood: None,
+        }
+    }
+
+    fn update(id: &str) -> UpdateRecordInput {
+        UpdateRecordInput {
+            id: id.to_string(),
+            title: None,
+            content: None,
+            images: None,
+            music_url: None,
+            music_title: None,
+            captured_at: None,
+            tags: None,
+            mood: None,
+        }
+    }
+
+    fn titles(store: &mut RecordStore) -> Vec<String> {
+        let mut titles: Vec<String> = store
+            .list(&RecordFilter::default())
+            .unwrap()
+            .into_iter()
+            .map(|r| r.title)
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    fn iso(time: DateTime<Utc>) -> String {
+        time.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    #[test]
+    fn records_are_created_updated_and_deleted() {
+        let paths = temp_paths();
+        let settings = Settings {
+            trash_retention_days: 0,
+            ..Settings::default()
+        };
+        let mut store = open_store(&paths);
+        let first = store.create(input("海边", &["旅行"]), &settings).unwrap();
+        let second = store.create(input("山顶", &["旅行"]), &settings).unwrap();
+        // 与已有标签同名时沿用已有的写法
+        let third = store.create(input("家里", &["Home"]), &settings).unwrap();
+        let fourth = store.create(input("楼下", &["home"]), &settings).unwrap();
+        assert_eq!(fourth.tags, ["Home"]);
+        assert_eq!(store.get(&first.id).unwrap().unwrap().content, "海边的正文");
+        assert!(store.get("record_0_missing").unwrap().is_none());
+
+        let edited = store
+            .update(
+                UpdateRecordInput {
+                    title: Some("海边日落".to_string()),
+                    ..update(&first.id)
+                },
+                &settings,
+            )
+            .unwrap();
+        assert_eq!(edited.title, "海边日落");
+        assert_eq!(edited.content, "海边的正文");
+        assert!(store.update(update("record_0_missing"), &settings).is_err());
+
+        let filter = RecordFilter {
+            tags: vec!["旅行".to_string()],
+            ..RecordFilter::default()
+        };
+        assert_eq!(store.list(&filter).unwrap().len(), 2);
+
+        store.delete(&second.id, &settings).unwrap();
+        assert!(store.delete(&second.id, &settings).is_err());
+        assert!(store.get(&second.id).unwrap().is_none());
+        // 保留天数为 0 时不进回收站
+        assert!(store.list_trash(&settings).unwrap().is_empty());
+        drop(store);
+
+        let mut reopened = open_store(&paths);
+        assert_eq!(titles(&mut reopened), ["家里", "楼下", "海边日落"]);
+        assert_eq!(reopened.get(&third.id).unwrap().unwrap().tags, ["Home"]);
+        assert!(reopened.get(&second.id).unwrap().is_none());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn trashed_records_can_be_restored_or_emptied() {
+        let paths = temp_paths();
+        let settings = Settings::default();
+        let mut store = open_store(&paths);
+        let kept = store.create(input("留下", &[]), &settings).unwrap();
+        let removed = store.create(input("删除", &[]), &settings).unwrap();
+
+        store.delete(&kept.id, &settings).unwrap();
+        store.delete(&removed.id, &settings).unwrap();
+        assert!(titles(&mut store).is_empty());
+        let trash = store.list_trash(&settings).unwrap();
+        assert_eq!(trash.len(), 2);
+        assert!(trash.iter().all(|t| t.purge_at > t.deleted_at));
+        drop(store);
+
+        // 回收站中的记录在重新打开后仍在回收站中
+        let mut store = open_store(&paths);
+        assert!(titles(&mut store).is_empty());
+        assert_eq!(store.list_trash(&settings).unwrap().len(), 2);
+        assert_eq!(store.restore_record(&kept.id).unwrap().title, "留下");
+        assert!(store.restore_record(&kept.id).is_err());
+        assert!(store
+            .empty_trash(Some(std::slice::from_ref(&kept.id)))
+            .is_err());
+        assert_eq!(store.empty_trash(None).unwrap(), 1);
+        assert!(store.list_trash(&settings).unwrap().is_empty());
+        drop(store);
+
+        let mut store = open_store(&paths);
+        assert_eq!(titles(&mut store), ["留下"]);
+        assert!(store.list_trash(&settings).unwrap().is_empty());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn sealed_records_are_released_and_destroyed_when_due() {
+        let paths = temp_paths();
+        let settings = Settings::default();
+        let mut store = open_store(&paths);
+        let record = store.create(input("给未来", &[]), &settings).unwrap();
+        let now = store.now();
+        let sealed = store
+            .seal(
+                &record.id,
+                SealConfig {
+                    seal_until: Some(iso(now + Duration::days(1))),
+                    auto_destroy_at: Some(iso(now + Duration::days(2))),
+                },
+            )
+            .unwrap();
+        assert!(sealed.is_sealed && sealed.title.is_empty());
+        assert!(store.unseal(&record.id).is_err());
+        drop(store);
+
+        let mut store = open_store(&paths);
+        let sealed = store.get(&record.id).unwrap().unwrap();
+        assert!(sealed.is_sealed && sealed.title.is_empty());
+        assert!(store.take_events().is_empty());
+
+        // 解封时间已到：内容随即解开
+        let past = iso(store.now() - Duration::seconds(1));
+        store.records[0].seal_until = Some(past.clone());
+        store.apply_due().unwrap();
+        let released = store.get(&record.id).unwrap().unwrap();
+        assert!(!released.is_sealed);
+        assert_eq!(released.title, "给未来");
+        let events = store.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Unseal);
+
+        // 销毁时间已到：记录与其数据密钥一并删除
+        store.records[0].auto_destroy_at = Some(past);
+        store.apply_due().unwrap();
+        assert!(store.get(&record.id).unwrap().is_none());
+        let events = store.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Destroy);
+        assert_eq!(events[0].title.as_deref(), Some("给未来"));
+        drop(store);
+
+        let mut store = open_store(&paths);
+        assert!(store.get(&record.id).unwrap().is_none());
+        assert!(store.list_trash(&settings).unwrap().is_empty());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn legacy_records_file_is_kept_and_imported_when_it_changes() {
+        let paths = temp_paths();
+        let settings = Settings::default();
+        let legacy = |id: &str| EmotionalRecord {
+            id: id.to_string(),
+            title: id.to_string(),
+            created_at: "2024-01-01T00:00:00.000Z".to_string(),
+            updated_at: "2024-01-01T00:00:00.000Z".to_string(),
+            ..Default::default()
+        };
+        let write = |records: &[EmotionalRecord]| {
+            fs::write(paths.records_file(), serde_json::to_vec(records).unwrap()).unwrap();
+        };
+        let mut store = open_store(&paths);
+        write(&[legacy("record_1_a")]);
+        assert_eq!(
+            store
+                .import_legacy_file(&paths.records_file(), &settings)
+                .unwrap(),
+            1
+        );
+        assert!(paths.records_file().exists());
+        assert_eq!(
+            store
+                .import_legacy_file(&paths.records_file(), &settings)
+                .unwrap(),
+            0
+        );
+
+        // 旧版前端新增了记录：只导入新的 id
+        write(&[legacy("record_1_a"), legacy("record_2_b")]);
+        assert_eq!(
+            store
+                .import_legacy_file(&paths.records_file(), &settings)
+                .unwrap(),
+            1
+        );
+        assert_eq!(titles(&mut store), ["record_1_a", "record_2_b"]);
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use zeroize::Zeroizing;
+
+use crate::crypto::{self, Key};
+use crate::keyring::Keyring;
+
+// 标签名最多的字符数
+const MAX_TAG_CHARS: usize = 32;
+
+// 写入数据库的标签：lookup 为带密钥的哈希，同名（不区分大小写）标签得到相同的值，
+// name 为以该标签在密钥环中的密钥加密的显示名称
+#[derive(Debug, Clone)]
+pub struct StoredTag {
+    pub lookup: String,
+    pub name: String,
+}
+
+// 由主密钥派生的查找密钥生成不泄露名称的查找键；标签名以密钥环中每个标签各自的密钥加密，
+// 最后一条带该标签的记录被销毁时密钥随之销毁。旧版以主密钥派生的密钥加密标签名，仅在迁移时读取
+pub struct TagCipher {
+    lookup_key: Zeroizing<Key>,
+    legacy_name_key: Option<Zeroizing<Key>>,
+}
+
+impl TagCipher {
+    pub fn new(master_key: &Key, legacy: bool) -> Self {
+        Self {
+            lookup_key: Zeroizing::new(crypto::keyed_hash(master_key, b"tag-lookup")),
+            legacy_name_key: legacy
+                .then(|| Zeroizing::new(crypto::keyed_hash(master_key, b"tag-name"))),
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> String {
+        let lookup = crypto::keyed_hash(&self.lookup_key, fold(name).as_bytes());
+        lookup.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn seal(&self, name: &str, keyring: &mut Keyring) -> Result<StoredTag, String> {
+        let lookup = self.lookup(name);
+        let key = keyring.tag_key(&lookup);
+        Ok(StoredTag {
+            name: crypto::encrypt_to_string(&key, name.as_bytes())?,
+            lookup,
+        })
+    }
+
+    // 标签的密钥已被销毁时返回 None。迁移中断时密钥可能已生成而数据库中仍是旧密文
+    pub fn open(&self, tag: &StoredTag, keyring: &Keyring) -> Result<Option<String>, String> {
+        let current = keyring
+            .find_tag_key(&tag.lookup)
+            .map(|key| crypto::decrypt_from_string(&key, &tag.name));
+        let plaintext = match (current, &self.legacy_name_key) {
+            (Some(Ok(plaintext)), _) => plaintext,
+            (_, Some(legacy)) => crypto::decrypt_from_string(legacy, &tag.name)?,
+            (Some(Err(e)), None) => return Err(e),
+            (None, None) => return Ok(None),
+        };
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| "标签名已损坏".to_string())
+    }
+}
+
+// 去掉首尾空白并把连续空白合并为一个空格，空标签返回 None
+pub fn normalize(name: &str) -> Result<Option<String>, String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if name.chars().count() > MAX_TAG_CHARS {
+        return Err(format!("标签不能超过 {} 个字符", MAX_TAG_CHARS));
+    }
+    Ok(Some(name))
+}
+
+// 规范化一组标签，按不区分大小写的名称去重并保持原有顺序
+pub fn normalize_all(names: Vec<String>) -> Result<Vec<String>, String> {
+    let mut seen = HashSet::new();
+    let mut tags = Vec::new();
+    for name in names {
+        if let Some(name) = normalize(&name)? {
+            if seen.insert(fold(&name)) {
+                tags.push(name);
+            }
+        }
+    }
+    Ok(tags)
+}
+
+// 比较标签时不区分大小写
+pub fn same(a: &str, b: &str) -> bool {
+    fold(a) == fold(b)
+}
+
+fn fold(name: &str) -> String {
+    name.to_lowercase()
+}
@@ -0,0 +1,115 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+// 可用的缩略图边长，请求的尺寸向上取到最近的一档，限制缓存文件的数量
+const THUMBNAIL_SIZES: [u32; 3] = [256, 512, 1024];
+// 导入图片时预先生成的尺寸，对应首页卡片
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 512;
+const JPEG_QUALITY: u8 = 80;
+// 计算模糊占位符前先缩小到此边长，结果几乎不受影响但快得多
+const PLACEHOLDER_SAMPLE: u32 = 32;
+
+// 原图尺寸与模糊占位符，加密缓存在 thumbs/<哈希>.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub blur_hash: String,
+}
+
+// 返回给前端的缩略图信息，path 为 memories:// 协议下的路径
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailInfo {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub blur_hash: String,
+}
+
+impl ThumbnailInfo {
+    pub fn new(media_ref: &str, size: u32, info: ImageInfo) -> Self {
+        let (width, height) = fit(info.width, info.height, size);
+        Self {
+            path: thumbnail_path(media_ref, size),
+            width,
+            height,
+            blur_hash: info.blur_hash,
+        }
+    }
+}
+
+pub fn snap_size(requested: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|&size| size >= requested)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+// 协议路径：thumbs/<尺寸>/images/<哈希>.<扩展名>
+pub fn thumbnail_path(media_ref: &str, size: u32) -> String {
+    format!("thumbs/{}/{}", size, media_ref)
+}
+
+// 解析协议路径，返回（尺寸，原图引用）
+pub fn parse_thumbnail_path(path: &str) -> Option<(u32, &str)> {
+    let rest = path.strip_prefix("thumbs/")?;
+    let (size, media_ref) = rest.split_once('/')?;
+    Some((snap_size(size.parse().ok()?), media_ref))
+}
+
+pub fn decode(data: &[u8]) -> Result<DynamicImage, String> {
+    image::load_from_memory(data).map_err(|e| format!("无法解码图片: {}", e))
+}
+
+pub fn info(image: &DynamicImage) -> Result<ImageInfo, String> {
+    Ok(ImageInfo {
+        width: image.width(),
+        height: image.height(),
+        blur_hash: placeholder(image)?,
+    })
+}
+
+// 等比缩小到边长不超过 size 的 JPEG，小图不放大
+pub fn render(image: &DynamicImage, size: u32) -> Result<Vec<u8>, String> {
+    let (width, height) = fit(image.width(), image.height(), size);
+    let resized = if (width, height) == (image.width(), image.height()) {
+        image.to_rgb8()
+    } else {
+        image.thumbnail_exact(width, height).to_rgb8()
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY)
+        .encode_image(&resized)
+        .map_err(|e| format!("生成缩略图失败: {}", e))?;
+    Ok(buffer.into_inner())
+}
+
+fn placeholder(image: &DynamicImage) -> Result<String, String> {
+    let sample = image
+        .thumbnail(PLACEHOLDER_SAMPLE, PLACEHOLDER_SAMPLE)
+        .to_rgba8();
+    // 横图横向多取一个分量，竖图反之
+    let (x, y) = if sample.width() >= sample.height() {
+        (4, 3)
+    } else {
+        (3, 4)
+    };
+    blurhash::encode(x, y, sample.width(), sample.height(), sample.as_raw())
+        .map_err(|e| format!("生成占位图失败: {}", e))
+}
+
+// 等比适配到 size × size 的边界内
+pub fn fit(width: u32, height: u32, size: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= size {
+        return (width, height);
+    }
+    let scale = |side: u32| ((side as u64 * size as u64) / longest as u64).max(1) as u32;
+    (scale(width), scale(height))
+}
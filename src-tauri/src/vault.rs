@@ -0,0 +1,399 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::atomic;
+use crate::crypto::{self, Key, KEY_LEN};
+
+const VAULT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+// Argon2id 参数随文件保存，以便日后调整默认值时仍能打开旧文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    algorithm: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+impl KdfParams {
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            algorithm: "argon2id".to_string(),
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+            salt: STANDARD.encode(salt),
+        }
+    }
+
+    // 由口令派生密钥加密密钥（KEK）
+    pub fn derive(&self, passphrase: &str) -> Result<Zeroizing<Key>, String> {
+        if self.algorithm != "argon2id" {
+            return Err(format!("不支持的密钥派生算法: {}", self.algorithm));
+        }
+        let salt = STANDARD
+            .decode(&self.salt)
+            .map_err(|e| format!("密钥派生参数已损坏: {}", e))?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|e| format!("密钥派生参数无效: {}", e))?;
+
+        let mut kek = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, kek.as_mut())
+            .map_err(|e| format!("密钥派生失败: {}", e))?;
+        Ok(kek)
+    }
+}
+
+// 磁盘上的保险库文件：随机主密钥由口令派生的 KEK 包裹，
+// 其余数据都由主密钥（或其保护的密钥）加密，因此修改口令只需重新包裹主密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    wrapped_key: String,
+    // 旧版内嵌的记录数据，迁移到 records.db 后移除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+// 打开保险库文件失败的原因：口令错误等无法通过备份解决，文件损坏则尝试备份
+enum OpenError {
+    Fatal(String),
+    Corrupt(String),
+}
+
+// 已解锁的保险库，主密钥只存在于内存中，释放时清零
+pub struct Vault {
+    path: PathBuf,
+    kdf: KdfParams,
+    wrapped_key: String,
+    master_key: Zeroizing<Key>,
+    // 从备份恢复时主文件已损坏，下次写入不应把它轮换为备份
+    recovered: bool,
+}
+
+impl Vault {
+    // 用新口令创建空保险库
+    pub fn create(path: PathBuf, passphrase: &str) -> Result<Self, String> {
+        validate_passphrase(passphrase)?;
+        let kdf = KdfParams::generate();
+        let kek = kdf.derive(passphrase)?;
+        let master_key = Zeroizing::new(crypto::generate_key());
+        let wrapped_key = crypto::encrypt_to_string(&kek, master_key.as_ref())?;
+
+        Ok(Self {
+            path,
+            kdf,
+            wrapped_key,
+            master_key,
+            recovered: false,
+        })
+    }
+
+    // 主文件或备份任一存在即视为已初始化
+    pub fn exists(path: &Path) -> bool {
+        path.exists() || backup_path(path).exists()
+    }
+
+    // 用口令解开主密钥，并返回旧版内嵌的记录数据（如有）；主文件被截断或损坏时改用备份
+    pub fn unlock(path: PathBuf, passphrase: &str) -> Result<(Self, Option<Vec<u8>>), String> {
+        let (file, master_key, data, recovered) = match open_file(&path, passphrase) {
+            Ok((file, master_key, data)) => (file, master_key, data, false),
+            Err(OpenError::Fatal(message)) => return Err(message),
+            Err(OpenError::Corrupt(message)) => match open_file(&backup_path(&path), passphrase) {
+                Ok((file, master_key, data)) => (file, master_key, data, true),
+                Err(OpenError::Fatal(backup_message)) if path.exists() => {
+                    return Err(format!("{}，备份也无法打开: {}", message, backup_message))
+                }
+                Err(OpenError::Fatal(message)) => return Err(message),
+                Err(OpenError::Corrupt(_)) => return Err(format!("{}，且没有可用的备份", message)),
+            },
+        };
+
+        let vault = Self {
+            path,
+            kdf: file.kdf,
+            wrapped_key: file.wrapped_key,
+            master_key,
+            recovered,
+        };
+        Ok((vault, data))
+    }
+
+    // 本次解锁是否从备份恢复
+    pub fn recovered(&self) -> bool {
+        self.recovered
+    }
+
+    pub fn master_key(&self) -> &Key {
+        &self.master_key
+    }
+
+    // 写入不含记录数据的保险库文件，用于新建保险库与迁移完成后
+    pub fn save(&mut self) -> Result<(), String> {
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            kdf: self.kdf.clone(),
+            wrapped_key: self.wrapped_key.clone(),
+            data: None,
+        };
+        let content = serde_json::to_string(&file).map_err(|e| e.to_string())?;
+
+        // 写入前把当前主文件轮换为备份，主文件日后损坏时仍可用备份解锁。
+        // 备份中不保留已迁移的旧数据
+        if !self.recovered {
+            if let Ok(previous) = read_file(&self.path) {
+                let previous = VaultFile {
+                    data: None,
+                    ..previous
+                };
+                let content = serde_json::to_string(&previous).map_err(|e| e.to_string())?;
+                atomic::write_file(&backup_path(&self.path), content.as_bytes())
+                    .map_err(|e| format!("备份保险库失败: {}", e))?;
+            }
+        }
+        atomic::write_file(&self.path, content.as_bytes())
+            .map_err(|e| format!("保存保险库失败: {}", e))?;
+        self.recovered = false;
+        Ok(())
+    }
+
+    // 用预先派生好的新 KEK 重新包裹主密钥，数据本身无需重新加密
+    pub fn change_passphrase(&mut self, change: PassphraseChange) -> Result<(), String> {
+        if change.master_key.as_ref() != self.master_key.as_ref() {
+            return Err("口令错误".to_string());
+        }
+        let file = read_file(&self.path).map_err(OpenError::into_message)?;
+        let kdf = change.kdf;
+        let wrapped_key = crypto::encrypt_to_string(&change.kek, self.master_key.as_ref())?;
+
+        let updated = VaultFile {
+            kdf: kdf.clone(),
+            wrapped_key: wrapped_key.clone(),
+            ..file
+        };
+        let content = serde_json::to_string(&updated).map_err(|e| e.to_string())?;
+        atomic::write_file(&self.path, content.as_bytes())
+            .map_err(|e| format!("保存保险库失败: {}", e))?;
+
+        // 备份同样改用新口令包裹，否则从备份恢复时仍需旧口令
+        let backup = backup_path(&self.path);
+        if let Ok(file) = read_file(&backup) {
+            let rewrapped = VaultFile {
+                kdf: kdf.clone(),
+                wrapped_key: wrapped_key.clone(),
+                ..file
+            };
+            let content = serde_json::to_string(&rewrapped).map_err(|e| e.to_string())?;
+            atomic::write_file(&backup, content.as_bytes())
+                .map_err(|e| format!("保存保险库备份失败: {}", e))?;
+        }
+
+        self.kdf = kdf;
+        self.wrapped_key = wrapped_key;
+        Ok(())
+    }
+}
+
+// 修改口令所需的两次密钥派生较慢，在持有仓库锁之前完成：
+// 用旧口令解开的主密钥用于确认旧口令，新口令派生的 KEK 用于重新包裹
+pub struct PassphraseChange {
+    master_key: Zeroizing<Key>,
+    kdf: KdfParams,
+    kek: Zeroizing<Key>,
+}
+
+impl PassphraseChange {
+    pub fn derive(path: &Path, old: &str, new: &str) -> Result<Self, String> {
+        validate_passphrase(new)?;
+        let file = read_file(path).map_err(OpenError::into_message)?;
+        let master_key = unwrap_key(&file.kdf, &file.wrapped_key, old)?;
+        let kdf = KdfParams::generate();
+        let kek = kdf.derive(new)?;
+        Ok(Self {
+            master_key,
+            kdf,
+            kek,
+        })
+    }
+}
+
+impl OpenError {
+    fn into_message(self) -> String {
+        match self {
+            OpenError::Fatal(message) | OpenError::Corrupt(message) => message,
+        }
+    }
+}
+
+// records.vault -> records.vault.bak
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+type Opened = (VaultFile, Zeroizing<Key>, Option<Vec<u8>>);
+
+fn open_file(path: &Path, passphrase: &str) -> Result<Opened, OpenError> {
+    let file = read_file(path)?;
+    let master_key =
+        unwrap_key(&file.kdf, &file.wrapped_key, passphrase).map_err(OpenError::Fatal)?;
+    // 口令正确但数据无法解密，说明文件内容已损坏
+    let data = file
+        .data
+        .as_deref()
+        .map(|data| crypto::decrypt_from_string(&master_key, data))
+        .transpose()
+        .map_err(|_| OpenError::Corrupt("保险库数据已损坏".to_string()))?;
+    Ok((file, master_key, data))
+}
+
+fn read_file(path: &Path) -> Result<VaultFile, OpenError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| OpenError::Corrupt(format!("读取保险库失败: {}", e)))?;
+    let file: VaultFile = serde_json::from_str(&content)
+        .map_err(|e| OpenError::Corrupt(format!("保险库文件已损坏: {}", e)))?;
+    if file.version > VAULT_VERSION {
+        return Err(OpenError::Fatal(
+            "保险库由更新版本的应用创建，请升级后再打开".to_string(),
+        ));
+    }
+    Ok(file)
+}
+
+fn unwrap_key(
+    kdf: &KdfParams,
+    wrapped_key: &str,
+    passphrase: &str,
+) -> Result<Zeroizing<Key>, String> {
+    let kek = kdf.derive(passphrase)?;
+    let key = Zeroizing::new(
+        crypto::decrypt_from_string(&kek, wrapped_key).map_err(|_| "口令错误".to_string())?,
+    );
+    let mut master_key = Zeroizing::new([0u8; KEY_LEN]);
+    if key.len() != KEY_LEN {
+        return Err("保险库主密钥已损坏".to_string());
+    }
+    master_key.copy_from_slice(&key);
+    Ok(master_key)
+}
+
+pub fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < 6 {
+        return Err("口令至少需要 6 个字符".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse";
+
+    fn temp_vault() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vault-{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("records.vault")
+    }
+
+    fn created(path: &Path) -> Vault {
+        let mut vault = Vault::create(path.to_path_buf(), PASSPHRASE).unwrap();
+        vault.save().unwrap();
+        vault
+    }
+
+    fn cleanup(path: &Path) {
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unlock_returns_the_master_key_of_a_created_vault() {
+        let path = temp_vault();
+        assert!(!Vault::exists(&path));
+        assert!(Vault::create(path.clone(), "short").is_err());
+        let vault = created(&path);
+        assert!(Vault::exists(&path));
+
+        let (unlocked, legacy) = Vault::unlock(path.clone(), PASSPHRASE).unwrap();
+        assert_eq!(unlocked.master_key(), vault.master_key());
+        assert!(legacy.is_none());
+        assert!(!unlocked.recovered());
+        cleanup(&path);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected_without_using_the_backup() {
+        let path = temp_vault();
+        let mut vault = created(&path);
+        vault.save().unwrap();
+        assert_eq!(
+            Vault::unlock(path.clone(), "wrong passphrase")
+                .err()
+                .unwrap(),
+            "口令错误"
+        );
+        cleanup(&path);
+    }
+
+    #[test]
+    fn truncated_vault_is_recovered_from_the_backup() {
+        let path = temp_vault();
+        let mut vault = created(&path);
+        // 第二次保存时把第一次的文件轮换为备份
+        vault.save().unwrap();
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+        let (mut unlocked, _) = Vault::unlock(path.clone(), PASSPHRASE).unwrap();
+        assert!(unlocked.recovered());
+        assert_eq!(unlocked.master_key(), vault.master_key());
+        // 恢复后的保存不把损坏的主文件轮换为备份
+        unlocked.save().unwrap();
+        let (reopened, _) = Vault::unlock(path.clone(), PASSPHRASE).unwrap();
+        assert!(!reopened.recovered());
+        cleanup(&path);
+    }
+
+    #[test]
+    fn passphrase_change_keeps_the_master_key() {
+        let path = temp_vault();
+        let mut vault = created(&path);
+        vault.save().unwrap();
+        let master_key = *vault.master_key();
+
+        assert!(PassphraseChange::derive(&path, "wrong passphrase", "new passphrase").is_err());
+        let change = PassphraseChange::derive(&path, PASSPHRASE, "new passphrase").unwrap();
+        vault.change_passphrase(change).unwrap();
+
+        assert!(Vault::unlock(path.clone(), PASSPHRASE).is_err());
+        let (unlocked, _) = Vault::unlock(path.clone(), "new passphrase").unwrap();
+        assert_eq!(*unlocked.master_key(), master_key);
+        // 备份同样改用新口令
+        let (backup, _) = Vault::unlock(backup_path(&path), "new passphrase").unwrap();
+        assert_eq!(*backup.master_key(), master_key);
+        cleanup(&path);
+    }
+}
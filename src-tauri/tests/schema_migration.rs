@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::PathBuf;
+
+use pick_up_memories_lib::{
+    parse_records, upgrade_record, EmotionalRecord, Envelope, Mood, SCHEMA_VERSION,
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("读取 {} 失败: {}", path.display(), e))
+}
+
+#[test]
+fn v0_bare_array_is_upgraded() {
+    let records = parse_records(&fixture("v0_records.json")).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let first = &records[0];
+    assert_eq!(first.title, "第一次看海");
+    assert_eq!(first.images, vec!["/images/sea.jpg"]);
+    assert_eq!(first.music_url, None);
+    assert_eq!(first.sealed_payload, None);
+    assert_eq!(first.captured_at, None);
+    assert!(first.tags.is_empty());
+    assert_eq!(first.mood, None);
+
+    let second = &records[1];
+    assert!(second.images.is_empty());
+    assert!(second.is_sealed);
+    assert_eq!(
+        second.seal_until.as_deref(),
+        Some("2024-08-01T22:15:00.000Z")
+    );
+    assert_eq!(second.auto_destroy_at, None);
+}
+
+#[test]
+fn v0_export_file_is_upgraded() {
+    let records = parse_records(&fixture("v0_export.json")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].music_url.as_deref(), Some("/music/song.mp3"));
+    assert_eq!(records[0].music_title.as_deref(), Some("后来"));
+    assert!(!records[0].is_sealed);
+}
+
+#[test]
+fn v1_envelope_keeps_sealed_payload() {
+    let records = parse_records(&fixture("v1_envelope.json")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].sealed_payload.as_deref(),
+        Some("c2VhbGVkLXBheWxvYWQ=")
+    );
+    assert_eq!(records[0].captured_at, None);
+}
+
+#[test]
+fn v2_envelope_is_loaded_unchanged() {
+    let records = parse_records(&fixture("v2_envelope.json")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].captured_at.as_deref(),
+        Some("2024-03-27T16:45:12+08:00")
+    );
+    assert_eq!(
+        records[0].auto_destroy_at.as_deref(),
+        Some("2030-01-01T00:00:00.000Z")
+    );
+    assert!(records[0].tags.is_empty());
+}
+
+#[test]
+fn v3_envelope_keeps_tags_and_mood() {
+    let records = parse_records(&fixture("v3_envelope.json")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].tags, vec!["旅行", "Family"]);
+    assert_eq!(
+        records[0].mood,
+        Some(Mood {
+            id: "nostalgic".to_string(),
+            intensity: 4
+        })
+    );
+}
+
+#[test]
+fn newer_schema_is_refused() {
+    let error = parse_records(&fixture("future_envelope.json")).unwrap_err();
+    assert!(error.contains("99"), "{}", error);
+
+    let record = serde_json::json!({ "id": "x" });
+    assert!(upgrade_record(record, SCHEMA_VERSION + 1).is_err());
+}
+
+#[test]
+fn current_envelope_round_trips() {
+    let records: Vec<EmotionalRecord> = parse_records(&fixture("v0_records.json")).unwrap();
+    let content = serde_json::to_vec(&Envelope::new(&records)).unwrap();
+
+    let document: serde_json::Value = serde_json::from_slice(&content).unwrap();
+    assert_eq!(document["schemaVersion"], SCHEMA_VERSION);
+
+    let reloaded = parse_records(&content).unwrap();
+    assert_eq!(
+        serde_json::to_value(&reloaded).unwrap(),
+        serde_json::to_value(&records).unwrap()
+    );
+}